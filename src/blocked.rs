@@ -0,0 +1,295 @@
+// This program is free software; you can redistribute it and/or
+// modify it under the terms of the GNU General Public License as
+// published by the Free Software Foundation; either version 2 of the
+// License, or (at your option) any later version.
+
+// This program is distributed in the hope that it will be useful, but
+// WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the GNU
+// General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with this program; if not, write to the Free Software
+// Foundation, Inc., 51 Franklin Street, Fifth Floor, Boston, MA
+// 02110-1301, USA.
+
+extern crate bit_vec;
+
+use bit_vec::BitVec;
+use std::collections::hash_map::RandomState;
+use std::hash::{BuildHasher,Hash};
+
+use super::{ASMS,Resettable};
+use super::bloom::{needed_bits,optimal_num_hashes};
+use super::hashing::HashIter;
+
+/// Number of bits in each block, the size of a typical cache line
+pub const BLOCK_BITS: usize = 1 << BLOCK_SHIFT;
+const BLOCK_SHIFT: u32 = 9;
+
+/// A Blocked BloomFilter.  The bits are divided into blocks the size
+/// of a cache line, and all the bits for an item are set within a
+/// single block chosen by the first hash.  This means each `insert`
+/// or `contains` touches only one cache line, at the cost of a
+/// slightly higher false positive rate than a standard BloomFilter
+/// with the same number of bits.
+///
+/// # Example Usage
+///
+/// ```rust
+/// use bloom::{ASMS,BlockedBloomFilter};
+///
+/// let mut filter = BlockedBloomFilter::with_rate(0.01,1000);
+/// filter.insert(&1);
+/// assert!(filter.contains(&1));
+/// ```
+pub struct BlockedBloomFilter<R = RandomState, S = RandomState> {
+    bits: BitVec,
+    num_blocks: u64,
+    num_hashes: u32,
+    hash_builder_one: R,
+    hash_builder_two: S,
+}
+
+impl BlockedBloomFilter<RandomState, RandomState> {
+    /// Create a new BlockedBloomFilter with at least the specified
+    /// number of bits, and hashes.  The number of bits is rounded up
+    /// to a whole number of blocks.
+    pub fn with_size(num_bits: usize, num_hashes: u32) -> BlockedBloomFilter<RandomState, RandomState> {
+        BlockedBloomFilter::with_size_and_hashers(num_bits,num_hashes,
+                                                  RandomState::new(),RandomState::new())
+    }
+
+    /// create a BlockedBloomFilter that expects to hold
+    /// `expected_num_items`.  The filter will be sized as a standard
+    /// BloomFilter with a false positive rate of `rate`, and then
+    /// rounded up to a whole number of blocks.
+    pub fn with_rate(rate: f32, expected_num_items: u32) -> BlockedBloomFilter<RandomState, RandomState> {
+        let bits = needed_bits(rate,expected_num_items);
+        BlockedBloomFilter::with_size(bits,optimal_num_hashes(bits,expected_num_items))
+    }
+}
+
+impl<R,S> BlockedBloomFilter<R,S>
+    where R: BuildHasher, S: BuildHasher
+{
+    /// Create a new BlockedBloomFilter with at least the specified
+    /// number of bits, hashes, and the two specified HashBuilders.
+    /// The HashBuilders MUST provide independent hash values, as for
+    /// `BloomFilter::with_size_and_hashers`.
+    pub fn with_size_and_hashers(num_bits: usize, num_hashes: u32,
+                                 hash_builder_one: R, hash_builder_two: S) -> BlockedBloomFilter<R,S> {
+        let num_blocks = num_bits.div_ceil(BLOCK_BITS).max(1);
+        BlockedBloomFilter {
+            bits: BitVec::from_elem(num_blocks*BLOCK_BITS,false),
+            num_blocks: num_blocks as u64,
+            num_hashes: num_hashes,
+            hash_builder_one: hash_builder_one,
+            hash_builder_two: hash_builder_two,
+        }
+    }
+
+    /// Get the number of bits this BlockedBloomFilter is using
+    pub fn num_bits(&self) -> usize {
+        self.bits.len()
+    }
+
+    /// Get the number of blocks this BlockedBloomFilter is using
+    pub fn num_blocks(&self) -> usize {
+        self.num_blocks as usize
+    }
+
+    /// Get the number of hash functions this BlockedBloomFilter is
+    /// using
+    pub fn num_hashes(&self) -> u32 {
+        self.num_hashes
+    }
+
+    // the hash values for item.  The first picks the block, and the
+    // rest pick bits within it
+    fn hashes<T: Hash>(&self, item: &T) -> HashIter {
+        HashIter::from(item,
+                       self.num_hashes+1,
+                       &self.hash_builder_one,
+                       &self.hash_builder_two)
+    }
+}
+
+// index of the bit within a block that h selects.  This uses the
+// high bits of the hash, as the low bits of the later hashes from a
+// HashIter aren't independent enough for a power of two modulus
+fn bit_in_block(h: u64) -> usize {
+    (h >> (64 - BLOCK_SHIFT)) as usize
+}
+
+impl<R,S> ASMS for BlockedBloomFilter<R,S>
+    where R: BuildHasher, S: BuildHasher {
+    /// Insert item into this BlockedBloomFilter.
+    ///
+    /// If the filter did not have this value present, `true` is returned.
+    ///
+    /// If the filter did have this value present, `false` is returned.
+    fn insert<T: Hash>(&mut self, item: &T) -> bool {
+        let mut hashes = self.hashes(item);
+        let block = match hashes.next() {
+            Some(h) => (h % self.num_blocks) as usize * BLOCK_BITS,
+            None => { return false; }
+        };
+        let mut contained = true;
+        for h in hashes {
+            let idx = block + bit_in_block(h);
+            if !self.bits[idx] {
+                contained = false;
+                self.bits.set(idx,true);
+            }
+        }
+        !contained
+    }
+
+    /// Check if the item has been inserted into this
+    /// BlockedBloomFilter.  This function can return false
+    /// positives, but not false negatives.
+    fn contains<T: Hash>(&self, item: &T) -> bool {
+        let mut hashes = self.hashes(item);
+        let block = match hashes.next() {
+            Some(h) => (h % self.num_blocks) as usize * BLOCK_BITS,
+            None => { return true; }
+        };
+        for h in hashes {
+            if !self.bits[block + bit_in_block(h)] {
+                return false;
+            }
+        }
+        true
+    }
+
+    /// Remove all values from this BlockedBloomFilter
+    fn clear(&mut self) {
+        self.bits.clear();
+    }
+
+    /// The number of bits this BlockedBloomFilter is using
+    fn capacity_bits(&self) -> usize {
+        self.bits.len()
+    }
+
+    /// The number of hash values computed for each item, including
+    /// the one that picks the block, so one more than the inherent
+    /// `num_hashes`
+    fn num_hashes(&self) -> u32 {
+        self.num_hashes + 1
+    }
+}
+
+impl<R,S> Resettable for BlockedBloomFilter<R,S>
+    where R: BuildHasher, S: BuildHasher {
+    /// Remove all values from this BlockedBloomFilter
+    fn reset(&mut self) {
+        self.clear();
+    }
+}
+
+#[cfg(test)]
+extern crate rand;
+
+#[cfg(feature = "do-bench")]
+#[cfg(test)]
+mod bench {
+    extern crate test;
+    use self::test::Bencher;
+    use blocked::rand::{self,Rng};
+
+    use super::BlockedBloomFilter;
+    use bloom::BloomFilter;
+    use ASMS;
+
+    const CNT: u32 = 500000;
+    const RATE: f32 = 0.01;
+
+    fn filled<F: ASMS>(mut filter: F) -> F {
+        let mut rng = rand::thread_rng();
+        for _ in 0..CNT {
+            filter.insert(&rng.gen::<i32>());
+        }
+        filter
+    }
+
+    #[bench]
+    fn blocked_contains_benchmark(b: &mut Bencher) {
+        let bf = filled(BlockedBloomFilter::with_rate(RATE,CNT));
+        let mut rng = rand::thread_rng();
+        b.iter(|| {
+            let v = rng.gen::<i32>();
+            bf.contains(&v);
+        })
+    }
+
+    #[bench]
+    fn scattered_contains_benchmark(b: &mut Bencher) {
+        // the same number of bits as the blocked filter above
+        let blocked = BlockedBloomFilter::with_rate(RATE,CNT);
+        let bf:BloomFilter = filled(BloomFilter::with_size(blocked.num_bits(),blocked.num_hashes()));
+        let mut rng = rand::thread_rng();
+        b.iter(|| {
+            let v = rng.gen::<i32>();
+            bf.contains(&v);
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashSet;
+    use blocked::rand::{self,Rng};
+    use super::{BlockedBloomFilter,BLOCK_BITS};
+    use ASMS;
+
+    #[test]
+    fn simple() {
+        let mut b:BlockedBloomFilter = BlockedBloomFilter::with_rate(0.01,100);
+        assert!(b.insert(&1));
+        assert!(!b.insert(&1));
+        assert!(b.contains(&1));
+        assert!(!b.contains(&2));
+        b.clear();
+        assert!(!b.contains(&1));
+    }
+
+    #[test]
+    fn sizes() {
+        let b = BlockedBloomFilter::with_size(1000,4);
+        assert_eq!(b.num_blocks(),2);
+        assert_eq!(b.num_bits(),2*BLOCK_BITS);
+        assert_eq!(BlockedBloomFilter::with_size(0,4).num_blocks(),1);
+    }
+
+    #[test]
+    fn fpr_test() {
+        let cnt = 100000;
+        let rate: f32 = 0.01;
+
+        let mut b = BlockedBloomFilter::with_rate(rate,cnt);
+        let mut set:HashSet<i32> = HashSet::new();
+        let mut rng = rand::thread_rng();
+
+        for _ in 0..cnt {
+            let v = rng.gen::<i32>();
+            set.insert(v);
+            b.insert(&v);
+        }
+
+        let mut false_positives = 0;
+        for _ in 0..cnt {
+            let v = rng.gen::<i32>();
+            match (b.contains(&v),set.contains(&v)) {
+                (true, false) => { false_positives += 1; }
+                (false, true) => { panic!("false negative for {}", v); }
+                _ => {}
+            }
+        }
+
+        // blocking costs some accuracy, but not too much
+        let actual_rate = false_positives as f32 / cnt as f32;
+        assert!(actual_rate < rate * 2.0);
+    }
+}
@@ -16,13 +16,33 @@
 extern crate core;
 extern crate bit_vec;
 
+use alloc::vec::Vec;
 use bit_vec::BitVec;
-use std::cmp::{min,max};
+use core::convert::TryFrom;
+use core::fmt;
+use core::hash::{BuildHasher,Hash,Hasher};
+use core::mem;
+use core::ops::{BitAnd,BitAndAssign,BitOr,BitOrAssign};
+#[cfg(feature = "std")]
+use std::cmp::max;
+#[cfg(feature = "std")]
 use std::collections::hash_map::RandomState;
-use std::hash::{BuildHasher,Hash};
+#[cfg(feature = "std")]
+use std::error::Error;
+#[cfg(feature = "std")]
+use std::fs::File;
+#[cfg(feature = "std")]
+use std::io::{self,Read,Write};
+#[cfg(feature = "std")]
+use std::path::Path;
 
-use super::{ASMS,Intersectable,Unionable};
+use super::{ASMS,DefaultBuildHasher,Intersectable,Resettable,Unionable};
+#[cfg(feature = "std")]
+use super::hashers::SaltedBuildHasher;
+use super::hashers::SipBuildHasher;
+use super::hashers::murmur3_x64_128;
 use super::hashing::HashIter;
+use super::popcount;
 
 /// A standard BloomFilter.  If an item is instered then `contains`
 /// is guaranteed to return `true` for that item.  For items not
@@ -51,32 +71,320 @@ use super::hashing::HashIter;
 /// filter.contains(&1); /* true */
 /// filter.contains(&2); /* false */
 /// ```
-pub struct BloomFilter<R = RandomState, S = RandomState> {
+pub struct BloomFilter<R = DefaultBuildHasher, S = DefaultBuildHasher> {
     bits: BitVec,
     num_hashes: u32,
     hash_builder_one: R,
     hash_builder_two: S,
+    single_hash: bool,
+    pow2: bool,
+    num_inserts: u64,
+    rate: Option<f32>,
+    expected_items: Option<u32>,
 }
 
 
+/// The false positive rate of `BloomFilter::default()`
+#[cfg(feature = "std")]
+pub const DEFAULT_RATE: f32 = 0.01;
+/// The number of items `BloomFilter::default()` is sized for
+#[cfg(feature = "std")]
+pub const DEFAULT_EXPECTED_ITEMS: u32 = 1000;
+
+#[cfg(feature = "std")]
+impl Default for BloomFilter<RandomState, RandomState> {
+    /// Create a small BloomFilter, sized for `DEFAULT_EXPECTED_ITEMS`
+    /// (1000) items at a false positive rate of `DEFAULT_RATE`
+    /// (0.01), with `RandomState` hashers
+    fn default() -> BloomFilter<RandomState, RandomState> {
+        BloomFilter::with_rate(DEFAULT_RATE,DEFAULT_EXPECTED_ITEMS)
+    }
+}
+
+#[cfg(feature = "std")]
 impl BloomFilter<RandomState, RandomState> {
     /// Create a new BloomFilter with the specified number of bits,
     /// and hashes
+    ///
+    /// # Panics
+    /// Panics if `num_bits` is 0
     pub fn with_size(num_bits: usize, num_hashes: u32) -> BloomFilter<RandomState, RandomState> {
-        BloomFilter {
-            bits: BitVec::from_elem(num_bits,false),
-            num_hashes: num_hashes,
-            hash_builder_one: RandomState::new(),
-            hash_builder_two: RandomState::new(),
-        }
+        BloomFilter::with_size_and_hashers(num_bits,num_hashes,RandomState::new(),RandomState::new())
     }
 
     /// create a BloomFilter that expects to hold
     /// `expected_num_items`.  The filter will be sized to have a
     /// false positive rate of the value specified in `rate`.
+    ///
+    /// # Panics
+    /// Panics if `rate` is not strictly between 0 and 1
     pub fn with_rate(rate: f32, expected_num_items: u32) -> BloomFilter<RandomState, RandomState> {
-        let bits = needed_bits(rate,expected_num_items);
-        BloomFilter::with_size(bits,optimal_num_hashes(bits,expected_num_items))
+        BloomFilter::with_rate_and_hashers(rate,expected_num_items,RandomState::new(),RandomState::new())
+    }
+
+    /// Create a BloomFilter that uses exactly `num_bits` bits and
+    /// expects to hold `expected_num_items`, for when the memory
+    /// budget is fixed rather than the false positive rate.  The
+    /// number of hashes is `optimal_num_hashes(num_bits,
+    /// expected_num_items)`, and the false positive rate once the
+    /// filter holds that many items is `achievable_rate(num_bits,
+    /// expected_num_items)`.
+    ///
+    /// # Panics
+    /// Panics if `num_bits` is 0
+    pub fn with_bit_budget(num_bits: usize, expected_num_items: u32) -> BloomFilter<RandomState, RandomState> {
+        BloomFilter::with_size(num_bits,optimal_num_hashes(num_bits,expected_num_items))
+    }
+
+    /// Create a BloomFilter like `with_rate` that expects to hold
+    /// `expected_num_items`, which may be more than `u32::MAX`.  The
+    /// size is computed with `needed_bits_u64`.
+    ///
+    /// # Panics
+    /// Panics if `rate` is not strictly between 0 and 1
+    pub fn with_rate_u64(rate: f64, expected_num_items: u64) -> BloomFilter<RandomState, RandomState> {
+        let bits = needed_bits_u64(rate,expected_num_items);
+        let mut filter = BloomFilter::with_size(bits,optimal_num_hashes_u64(bits,expected_num_items));
+        filter.rate = Some(rate as f32);
+        if expected_num_items <= u32::MAX as u64 {
+            filter.expected_items = Some(expected_num_items as u32);
+        }
+        filter
+    }
+
+    /// Create a BloomFilter like `with_rate` that hashes each item
+    /// only once.  Normally every item is run through two hashers;
+    /// this instead derives both hash values from a single hasher,
+    /// which roughly halves the hashing cost of large items such as
+    /// long strings.
+    pub fn with_rate_single_hash(rate: f32, expected_num_items: u32) -> BloomFilter<RandomState, RandomState> {
+        BloomFilter::with_rate_single_hash_and_hashers(rate,expected_num_items,
+                                                       RandomState::new(),RandomState::new())
+    }
+
+    /// Create a BloomFilter with as many bits as `with_rate` would
+    /// use, but with `num_hashes` hashes rather than
+    /// `optimal_num_hashes`.  Fewer hashes make `insert` and
+    /// `contains` faster, but any number other than the optimal one
+    /// gives a false positive rate above `rate` once the filter holds
+    /// `expected_num_items`.
+    ///
+    /// # Panics
+    /// Panics if `rate` is not strictly between 0 and 1
+    pub fn with_rate_and_hashes(rate: f32, expected_num_items: u32, num_hashes: u32) -> BloomFilter<RandomState, RandomState> {
+        BloomFilter::with_rate_hashes_and_hashers(rate,expected_num_items,num_hashes,
+                                                  RandomState::new(),RandomState::new())
+    }
+
+    /// Create a BloomFilter like `with_rate`, but with the number of
+    /// bits rounded up to a power of two.  This lets `insert` and
+    /// `contains` turn hashes into bit indices with a mask rather
+    /// than a modulo, which is faster, at the cost of using up to
+    /// twice as many bits.  The extra bits lower the false positive
+    /// rate below `rate`.
+    pub fn with_rate_pow2(rate: f32, expected_num_items: u32) -> BloomFilter<RandomState, RandomState> {
+        let bits = needed_bits(rate,expected_num_items).next_power_of_two();
+        let mut filter = BloomFilter::with_size(bits,optimal_num_hashes(bits,expected_num_items));
+        filter.rate = Some(rate);
+        filter.expected_items = Some(expected_num_items);
+        filter
+    }
+
+    /// Reconstruct a BloomFilter from the output of Guava's
+    /// `BloomFilter.writeTo`, using fresh `RandomState` hashers.  See
+    /// `from_guava_bytes_with_hashers`.
+    pub fn from_guava_bytes(bytes: &[u8]) -> Result<BloomFilter<RandomState, RandomState>, ParseError> {
+        BloomFilter::from_guava_bytes_with_hashers(bytes,RandomState::new(),RandomState::new())
+    }
+
+    /// Reconstruct a BloomFilter from bytes produced by `to_bytes`.
+    /// The returned filter uses fresh `RandomState` hashers, so it
+    /// will only answer `contains` meaningfully for items inserted
+    /// after loading.  Use `from_bytes_with_hashers` to supply the
+    /// hashers the original filter was built with.
+    pub fn from_bytes(bytes: &[u8]) -> Result<BloomFilter<RandomState, RandomState>, ParseError> {
+        BloomFilter::from_bytes_with_hashers(bytes,RandomState::new(),RandomState::new())
+    }
+
+    /// Create a BloomFilter sized to hold the items in `items` with a
+    /// false positive rate of `rate`, and insert them all.  The
+    /// filter is sized from the length of the iterator, so this needs
+    /// an `ExactSizeIterator`.  For other iterators create a filter
+    /// of the right size first and use `extend`.
+    ///
+    /// # Panics
+    /// Panics if `items` has more than `u32::MAX` items
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use bloom::{ASMS,BloomFilter};
+    /// let filter = BloomFilter::from_rate_iter(0.01,vec!["a","b","c"]);
+    /// assert!(filter.contains(&"b"));
+    /// ```
+    pub fn from_rate_iter<I>(rate: f32, items: I) -> BloomFilter<RandomState, RandomState>
+        where I: IntoIterator, I::IntoIter: ExactSizeIterator, I::Item: Hash
+    {
+        let iter = items.into_iter();
+        let expected_num_items = u32::try_from(max(iter.len(),1))
+            .unwrap_or_else(|_| panic!("can't size a BloomFilter for {} items",iter.len()));
+        let mut filter = BloomFilter::with_rate(rate,expected_num_items);
+        filter.extend(iter);
+        filter
+    }
+
+    /// Rebuild a BloomFilter from words returned by `as_slice`.  The
+    /// returned filter uses fresh `RandomState` hashers; use
+    /// `from_raw_with_hashers` to supply the original ones.
+    ///
+    /// # Panics
+    /// Panics if `bits` holds fewer than `num_bits` bits, or
+    /// `num_bits` is 0
+    pub fn from_raw(bits: Vec<u32>, num_bits: usize, num_hashes: u32) -> BloomFilter<RandomState, RandomState> {
+        BloomFilter::from_raw_with_hashers(bits,num_bits,num_hashes,RandomState::new(),RandomState::new())
+    }
+
+    /// Create a BloomFilter that uses `bits` as its storage, without
+    /// copying them, and fresh `RandomState` hashers; use
+    /// `from_bit_vec_with_hashers` to supply others.  Any bits already
+    /// set are kept.
+    ///
+    /// # Panics
+    /// Panics if `bits` is empty
+    pub fn from_bit_vec(bits: BitVec, num_hashes: u32) -> BloomFilter<RandomState, RandomState> {
+        BloomFilter::from_bit_vec_with_hashers(bits,num_hashes,RandomState::new(),RandomState::new())
+    }
+
+    /// Read a BloomFilter written by `write_to` from `reader`.  As
+    /// with `from_bytes` the returned filter uses fresh `RandomState`
+    /// hashers; use `read_from_with_hashers` to supply the original
+    /// ones.
+    pub fn read_from<Rd: Read>(reader: Rd) -> io::Result<BloomFilter<RandomState, RandomState>> {
+        BloomFilter::read_from_with_hashers(reader,RandomState::new(),RandomState::new())
+    }
+
+    /// Load a BloomFilter saved with `save_to_path`.  As with
+    /// `from_bytes` the returned filter uses fresh `RandomState`
+    /// hashers; use `load_from_path_with_hashers` to supply the
+    /// original ones.
+    pub fn load_from_path(path: &Path) -> io::Result<BloomFilter<RandomState, RandomState>> {
+        BloomFilter::load_from_path_with_hashers(path,RandomState::new(),RandomState::new())
+    }
+}
+
+/// A BloomFilter whose hashers are a fixed pair of keyed
+/// SipBuildHashers (`SipBuildHasher::default_pair`) rather than
+/// `RandomState`s.  The hashers write integers as little endian
+/// bytes whatever the platform's byte order or word size, so the same
+/// items set the same bits in every process and on every platform,
+/// and these filters can be serialized and reloaded elsewhere and
+/// still answer `contains` for the items they hold.  As the keys are
+/// public, don't use them where an attacker could choose items to
+/// cause false positives; use `BloomFilter` with its per-process
+/// random hashers there.
+///
+/// # Example Usage
+///
+/// ```rust
+/// use bloom::{ASMS,DefaultBloomFilter};
+///
+/// let mut filter = DefaultBloomFilter::with_rate_fixed(0.01,1000);
+/// filter.insert(&1);
+/// let bytes = filter.to_bytes();
+/// let reloaded = DefaultBloomFilter::from_bytes_fixed(&bytes).unwrap();
+/// assert!(reloaded.contains(&1));
+/// ```
+pub type DefaultBloomFilter = BloomFilter<SipBuildHasher, SipBuildHasher>;
+
+impl BloomFilter<SipBuildHasher, SipBuildHasher> {
+    /// Create a DefaultBloomFilter with the specified number of bits
+    /// and hashes
+    ///
+    /// # Panics
+    /// Panics if `num_bits` is 0
+    pub fn with_size_fixed(num_bits: usize, num_hashes: u32) -> DefaultBloomFilter {
+        let (one, two) = SipBuildHasher::default_pair();
+        BloomFilter::with_size_and_hashers(num_bits,num_hashes,one,two)
+    }
+
+    /// Reconstruct a DefaultBloomFilter from bytes produced by
+    /// `to_bytes`.  Unlike `from_bytes`, the result answers
+    /// `contains` for the items the serialized filter holds.
+    pub fn from_bytes_fixed(bytes: &[u8]) -> Result<DefaultBloomFilter, ParseError> {
+        let (one, two) = SipBuildHasher::default_pair();
+        BloomFilter::from_bytes_with_hashers(bytes,one,two)
+    }
+}
+
+#[cfg(feature = "std")]
+impl BloomFilter<SipBuildHasher, SipBuildHasher> {
+    /// Create a DefaultBloomFilter that expects to hold
+    /// `expected_num_items` with a false positive rate of `rate`,
+    /// like `with_rate`
+    ///
+    /// # Panics
+    /// Panics if `rate` is not strictly between 0 and 1
+    pub fn with_rate_fixed(rate: f32, expected_num_items: u32) -> DefaultBloomFilter {
+        let (one, two) = SipBuildHasher::default_pair();
+        BloomFilter::with_rate_and_hashers(rate,expected_num_items,one,two)
+    }
+
+    /// Create a BloomFilter that expects to hold
+    /// `expected_num_items` with a false positive rate of `rate`,
+    /// like `with_rate`, but whose hashers are derived from `seed`
+    /// rather than chosen randomly.  Filters built with the same seed
+    /// set the same bits for the same items, in any process.
+    pub fn with_rate_and_seed(rate: f32, expected_num_items: u32, seed: u64) -> BloomFilter<SipBuildHasher, SipBuildHasher> {
+        let (one, two) = SipBuildHasher::pair_from_seed(seed);
+        BloomFilter::with_rate_and_hashers(rate,expected_num_items,one,two)
+    }
+
+    /// Create a BloomFilter that expects to hold
+    /// `expected_num_items` with a false positive rate of `rate`,
+    /// like `with_rate`, hashing with SipHash-1-3 keyed by
+    /// `(0, key0)` and `(key1, 0)`.  Filters built with the same keys
+    /// set the same bits for the same items, in any process.
+    ///
+    /// # Panics
+    /// Panics if `rate` is not strictly between 0 and 1, or both keys
+    /// are 0, which would make the two hashers identical
+    pub fn with_rate_and_keys(rate: f32, expected_num_items: u32, key0: u64, key1: u64) -> BloomFilter<SipBuildHasher, SipBuildHasher> {
+        if key0 == 0 && key1 == 0 {
+            panic!("with_rate_and_keys needs a non-zero key, or both hashers are the same");
+        }
+        BloomFilter::with_rate_and_hashers(rate,expected_num_items,
+                                           SipBuildHasher::with_keys(0,key0),
+                                           SipBuildHasher::with_keys(key1,0))
+    }
+}
+
+#[cfg(feature = "std")]
+impl<H> BloomFilter<SaltedBuildHasher<H>, SaltedBuildHasher<H>>
+    where H: BuildHasher + Clone
+{
+    /// Create a BloomFilter that expects to hold
+    /// `expected_num_items` with a false positive rate of `rate`,
+    /// like `with_rate_and_hashers`, but with a single BuildHasher.
+    /// The two hashers the filter needs are copies of `hasher`
+    /// salted with different values (see `SaltedBuildHasher::pair`).
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use bloom::{ASMS,BloomFilter};
+    /// use bloom::hashers::FnvBuildHasher;
+    ///
+    /// let mut filter = BloomFilter::with_rate_and_hasher(0.01,100,FnvBuildHasher);
+    /// filter.insert(&1);
+    /// assert!(filter.contains(&1));
+    /// ```
+    ///
+    /// # Panics
+    /// Panics if `rate` is not strictly between 0 and 1
+    pub fn with_rate_and_hasher(rate: f32, expected_num_items: u32,
+                                hasher: H) -> BloomFilter<SaltedBuildHasher<H>, SaltedBuildHasher<H>> {
+        let (one, two) = SaltedBuildHasher::pair(hasher);
+        BloomFilter::with_rate_and_hashers(rate,expected_num_items,one,two)
     }
 }
 
@@ -90,13 +398,22 @@ impl<R,S> BloomFilter<R,S>
     /// two HashBuilders that produce the same or correlated hash
     /// values will break the false positive guarantees of the
     /// BloomFilter.
+    ///
+    /// # Panics
+    /// Panics if `num_bits` is 0
     pub fn with_size_and_hashers(num_bits: usize, num_hashes: u32,
                                  hash_builder_one: R, hash_builder_two: S) -> BloomFilter<R,S> {
+        check_num_bits(num_bits);
         BloomFilter {
             bits: BitVec::from_elem(num_bits,false),
             num_hashes: num_hashes,
             hash_builder_one: hash_builder_one,
             hash_builder_two: hash_builder_two,
+            single_hash: false,
+            pow2: num_bits.is_power_of_two(),
+            num_inserts: 0,
+            rate: None,
+            expected_items: None,
         }
     }
 
@@ -109,11 +426,75 @@ impl<R,S> BloomFilter<R,S>
     /// two HashBuilders that produce the same or correlated hash
     /// values will break the false positive guarantees of the
     /// BloomFilter.
+    ///
+    /// # Panics
+    /// Panics if `rate` is not strictly between 0 and 1
+    #[cfg(feature = "std")]
     pub fn with_rate_and_hashers(rate: f32, expected_num_items: u32,
                                  hash_builder_one: R, hash_builder_two: S) -> BloomFilter<R, S> {
         let bits = needed_bits(rate,expected_num_items);
-        BloomFilter::with_size_and_hashers(bits,optimal_num_hashes(bits,expected_num_items),
-                                           hash_builder_one,hash_builder_two)
+        let mut filter = BloomFilter::with_size_and_hashers(bits,optimal_num_hashes(bits,expected_num_items),
+                                                            hash_builder_one,hash_builder_two);
+        filter.rate = Some(rate);
+        filter.expected_items = Some(expected_num_items);
+        filter
+    }
+
+    /// Create a BloomFilter like `with_rate_single_hash`, hashing
+    /// items only with `hash_builder_one`.  `hash_builder_two` is
+    /// kept so the filter has the same type as a two hasher one, but
+    /// is never used.
+    ///
+    /// # Panics
+    /// Panics if `rate` is not strictly between 0 and 1
+    #[cfg(feature = "std")]
+    pub fn with_rate_single_hash_and_hashers(rate: f32, expected_num_items: u32,
+                                             hash_builder_one: R, hash_builder_two: S) -> BloomFilter<R, S> {
+        let mut filter = BloomFilter::with_rate_and_hashers(rate,expected_num_items,
+                                                            hash_builder_one,hash_builder_two);
+        filter.single_hash = true;
+        filter
+    }
+
+    /// Create a BloomFilter like `with_rate_and_hashes`, hashing
+    /// items with `hash_builder_one` and `hash_builder_two`, which
+    /// MUST provide independent hash values.
+    ///
+    /// # Panics
+    /// Panics if `rate` is not strictly between 0 and 1
+    #[cfg(feature = "std")]
+    pub fn with_rate_hashes_and_hashers(rate: f32, expected_num_items: u32, num_hashes: u32,
+                                        hash_builder_one: R, hash_builder_two: S) -> BloomFilter<R, S> {
+        let bits = needed_bits(rate,expected_num_items);
+        let mut filter = BloomFilter::with_size_and_hashers(bits,num_hashes,hash_builder_one,hash_builder_two);
+        filter.rate = Some(rate);
+        filter.expected_items = Some(expected_num_items);
+        filter
+    }
+
+    /// Resize this BloomFilter in place to hold `expected_num_items`
+    /// with a false positive rate of `rate`, as if it had been
+    /// created with `with_rate_and_hashers` using the same hashers.
+    /// The bits are reallocated at the new size and the number of
+    /// hashes recomputed.
+    ///
+    /// This discards everything in the filter: the old items can't
+    /// be rehashed into the new bits, so they must be inserted again
+    /// if they are still needed.
+    ///
+    /// # Panics
+    /// Panics if `rate` is not strictly between 0 and 1, or the new
+    /// size would have no bits
+    #[cfg(feature = "std")]
+    pub fn reset_to(&mut self, rate: f32, expected_num_items: u32) {
+        let bits = needed_bits(rate,expected_num_items);
+        check_num_bits(bits);
+        self.bits = BitVec::from_elem(bits,false);
+        self.num_hashes = optimal_num_hashes(bits,expected_num_items);
+        self.pow2 = bits.is_power_of_two();
+        self.num_inserts = 0;
+        self.rate = Some(rate);
+        self.expected_items = Some(expected_num_items);
     }
 
     /// Get the number of bits this BloomFilter is using
@@ -121,205 +502,2860 @@ impl<R,S> BloomFilter<R,S>
         self.bits.len()
     }
 
-    /// Get the number of hash functions this BloomFilter is using
-    pub fn num_hashes(&self) -> u32 {
-        self.num_hashes
+    /// Get the false positive rate this BloomFilter was sized for,
+    /// if it was created from a rate (for example with `with_rate`).
+    /// Returns `None` for filters created with an explicit size or
+    /// rebuilt from bytes or raw words.
+    pub fn configured_rate(&self) -> Option<f32> {
+        self.rate
     }
-}
 
-impl<R,S> ASMS for BloomFilter<R,S>
-    where R: BuildHasher, S: BuildHasher {
-    /// Insert item into this BloomFilter.
-    ///
-    /// If the BloomFilter did not have this value present, `true` is returned.
-    ///
-    /// If the BloomFilter did have this value present, `false` is returned.
-    fn insert<T: Hash>(& mut self,item: &T) -> bool {
-        let mut contained = true;
-        for h in HashIter::from(item,
-                                self.num_hashes,
-                                &self.hash_builder_one,
-                                &self.hash_builder_two) {
-            let idx = (h % self.bits.len() as u64) as usize;
-            match self.bits.get(idx) {
-                Some(b) => {
-                    if !b {
-                        contained = false;
-                    }
-                }
-                None => { panic!("Hash mod failed in insert"); }
-            }
-            self.bits.set(idx,true)
-        }
-        !contained
+    /// Get the number of items this BloomFilter was sized for, if it
+    /// was created from a rate.  Returns `None` otherwise, and for
+    /// filters from `with_rate_u64` expecting more than `u32::MAX`
+    /// items.
+    pub fn expected_items(&self) -> Option<u32> {
+        self.expected_items
     }
 
-    /// Check if the item has been inserted into this bloom filter.
-    /// This function can return false positives, but not false
-    /// negatives.
-    fn contains<T: Hash>(&self, item: &T) -> bool {
-        for h in HashIter::from(item,
-                                self.num_hashes,
-                                &self.hash_builder_one,
-                                &self.hash_builder_two) {
-            let idx = (h % self.bits.len() as u64) as usize;
-            match self.bits.get(idx) {
-                Some(b) => {
-                    if !b {
-                        return false;
-                    }
-                }
-                None => { panic!("Hash mod failed"); }
-            }
-        }
-        true
+    /// Get the number of times `insert` (or `insert_hash`) has been
+    /// called on this BloomFilter since it was created or last
+    /// cleared, whether or not the item was already present.  A union
+    /// adds the other filter's inserts to this count.  Filters
+    /// rebuilt from bytes or raw words start from 0.
+    pub fn num_inserts(&self) -> u64 {
+        self.num_inserts
     }
 
-    /// Remove all values from this BloomFilter
-    fn clear(&mut self) {
-        self.bits.clear();
+    /// Returns true if this BloomFilter hashes each item only once,
+    /// as created by `with_rate_single_hash`
+    pub fn is_single_hash(&self) -> bool {
+        self.single_hash
     }
-}
 
-impl Intersectable for BloomFilter {
-    /// Calculates the intersection of two BloomFilters.  Only items inserted into both filters will still be present in `self`.
+    /// Get the indices of the bits `item` maps to, in the order
+    /// `insert` sets them.  There are `num_hashes` of them, and
+    /// there may be repeats.  This is meant for debugging, and
+    /// doesn't change the filter.
     ///
-    /// Both BloomFilters must be using the same number of
-    /// bits. Returns true if self changed.
+    /// # Example
     ///
-    /// # Panics
-    /// Panics if the BloomFilters are not using the same number of bits
-    fn intersect(&mut self, other: &BloomFilter) -> bool {
-        self.bits.intersect(&other.bits)
+    /// ```rust
+    /// use bloom::{ASMS,BloomFilter};
+    ///
+    /// let mut filter = BloomFilter::with_rate(0.01,100);
+    /// filter.insert(&"item");
+    /// let words = filter.as_slice();
+    /// for i in filter.hash_indices(&"item") {
+    ///     assert!(words[i / 32] & (1 << (i % 32)) != 0);
+    /// }
+    /// ```
+    pub fn hash_indices<T: Hash>(&self, item: &T) -> Vec<usize> {
+        self.hashes(item).map(|h| self.index(h)).collect()
     }
-}
-
 
-impl Unionable for BloomFilter {
-    /// Calculates the union of two BloomFilters.  Items inserted into
-    /// either filters will be present in `self`.
+    /// Insert item into this BloomFilter, returning `true` if it was
+    /// (probably) already present before this call.  This is the
+    /// inverse of `insert`'s return value, and reads more naturally
+    /// for "was this seen before?" checks such as deduplication.
     ///
-    /// Both BloomFilters must be using the same number of
-    /// bits. Returns true if self changed.
+    /// # Example
     ///
-    /// # Panics
-    /// Panics if the BloomFilters are not using the same number of bits
-    fn union(&mut self, other: &BloomFilter) -> bool {
-        self.bits.union(&other.bits)
+    /// ```rust
+    /// use bloom::BloomFilter;
+    ///
+    /// let mut seen = BloomFilter::with_rate(0.01,1000);
+    /// assert!(!seen.check_and_set(&"event"));
+    /// assert!(seen.check_and_set(&"event"));
+    /// ```
+    pub fn check_and_set<T: Hash>(&mut self, item: &T) -> bool {
+        let hashes = self.hashes(item);
+        !self.insert_hashes(hashes)
     }
-}
 
+    /// Insert every item from `items` into this BloomFilter
+    pub fn insert_all<T: Hash, I: IntoIterator<Item = T>>(&mut self, items: I) {
+        for item in items {
+            let hashes = self.hashes(&item);
+            self.insert_hashes(hashes);
+        }
+    }
 
-/// Return the optimal number of hashes to use for the given number of
-/// bits and items in a filter
-pub fn optimal_num_hashes(num_bits: usize, num_items: u32) -> u32 {
-    min(
-        max(
-            (num_bits as f32 / num_items as f32 * core::f32::consts::LN_2).round() as u32,
-             2
-           ),
-        200
-      )
-}
-
-/// Return the number of bits needed to satisfy the specified false
-/// positive rate, if the filter will hold `num_items` items.
-pub fn needed_bits(false_pos_rate:f32, num_items: u32) -> usize {
-    let ln22 = core::f32::consts::LN_2 * core::f32::consts::LN_2;
-    (num_items as f32 * ((1.0/false_pos_rate).ln() / ln22)).round() as usize
-}
-
-#[cfg(test)]
-extern crate rand;
+    /// Returns true if every item in `items` is in this BloomFilter,
+    /// which is always the case for an empty slice.  Like
+    /// `contains`, this can return false positives, but not false
+    /// negatives.
+    pub fn contains_all<T: Hash>(&self, items: &[T]) -> bool {
+        items.iter().all(|item| self.contains_hashes(self.hashes(item)))
+    }
 
-#[cfg(feature = "do-bench")]
-#[cfg(test)]
-mod bench {
-    extern crate test;
-    use self::test::Bencher;
-    use bloom::rand::{self,Rng};
+    /// Returns true if any item in `items` is in this BloomFilter,
+    /// which is never the case for an empty slice.  Like `contains`,
+    /// this can return false positives, but not false negatives.
+    pub fn contains_any<T: Hash>(&self, items: &[T]) -> bool {
+        items.iter().any(|item| self.contains_hashes(self.hashes(item)))
+    }
 
-    use super::BloomFilter;
-    use ASMS;
+    /// Get the number of items in `items` that are in this
+    /// BloomFilter, i.e. the number for which `contains` returns
+    /// true.  False positives are included in the count.
+    pub fn count_present<T: Hash>(&self, items: &[T]) -> usize {
+        items.iter().filter(|item| self.contains_hashes(self.hashes(item))).count()
+    }
 
-    #[bench]
-    fn insert_benchmark(b: &mut Bencher) {
-        let cnt = 500000;
-        let rate = 0.01 as f32;
+    /// Check if the item has been inserted into this BloomFilter,
+    /// looking at only the first `k` of its `num_hashes` bits.  This
+    /// is cheaper than `contains`, and still never returns a false
+    /// negative, but a smaller `k` raises the false positive rate, so
+    /// it is best used as a pre-check before a full `contains`.  With
+    /// `k` equal to `num_hashes` it is the same as `contains`.
+    ///
+    /// # Panics
+    /// Panics if `k` is more than `num_hashes`
+    pub fn contains_with_k<T: Hash>(&self, item: &T, k: u32) -> bool {
+        if k > self.num_hashes {
+            panic!("can't check {} hashes of a BloomFilter using {}", k, self.num_hashes);
+        }
+        self.contains_hashes(self.first_hashes(item,k))
+    }
 
-        let mut bf:BloomFilter = BloomFilter::with_rate(rate,cnt);
-        let mut rng = rand::thread_rng();
+    /// Insert raw bytes into this BloomFilter.  The bytes are
+    /// written straight into the hashers with `Hasher::write`,
+    /// without the length `Hash` adds for a slice, so the filter
+    /// uses the plain hash of the bytes.  This matches other
+    /// implementations hashing the same bytes with the same hash
+    /// functions.  Bytes inserted this way can only be found with
+    /// `contains_bytes`.
+    ///
+    /// Returns `true` if the BloomFilter did not already have this
+    /// value present, as for `insert`.
+    pub fn insert_bytes(&mut self, bytes: &[u8]) -> bool {
+        let hashes = self.bytes_hashes(bytes);
+        self.insert_hashes(hashes)
+    }
 
-        b.iter(|| {
-            let v = rng.gen::<i32>();
-            bf.insert(&v);
-        })
+    /// Check if raw bytes have been inserted with `insert_bytes`.
+    /// This function can return false positives, but not false
+    /// negatives.
+    pub fn contains_bytes(&self, bytes: &[u8]) -> bool {
+        self.contains_hashes(self.bytes_hashes(bytes))
     }
 
-    #[bench]
-    fn contains_benchmark(b: &mut Bencher) {
-        let cnt = 500000;
-        let rate = 0.01 as f32;
+    // the hash values for raw bytes
+    fn bytes_hashes(&self, bytes: &[u8]) -> HashIter {
+        if self.single_hash {
+            HashIter::from_single_bytes(bytes,self.num_hashes,&self.hash_builder_one)
+        } else {
+            HashIter::from_bytes(bytes,
+                                 self.num_hashes,
+                                 &self.hash_builder_one,
+                                 &self.hash_builder_two)
+        }
+    }
 
-        let mut bf:BloomFilter = BloomFilter::with_rate(rate,cnt);
-        let mut rng = rand::thread_rng();
+    // the hash values for item, from one or both hashers
+    fn hashes<T: Hash>(&self, item: &T) -> HashIter {
+        self.first_hashes(item,self.num_hashes)
+    }
 
-        let mut i = 0;
-        while i < cnt {
-            let v = rng.gen::<i32>();
-            bf.insert(&v);
-            i+=1;
+    // the first count hash values for item, which are the same
+    // whatever count is
+    fn first_hashes<T: Hash>(&self, item: &T, count: u32) -> HashIter {
+        if self.single_hash {
+            HashIter::from_single(item,count,&self.hash_builder_one)
+        } else {
+            HashIter::from(item,
+                           count,
+                           &self.hash_builder_one,
+                           &self.hash_builder_two)
         }
+    }
 
-        b.iter(|| {
-            let v = rng.gen::<i32>();
-            bf.contains(&v);
-        })
+    /// Get the number of hash functions this BloomFilter is using
+    pub fn num_hashes(&self) -> u32 {
+        self.num_hashes
     }
-}
 
-#[cfg(test)]
-mod tests {
-    use std::collections::HashSet;
-    use bloom::rand::{self,Rng};
-    use super::{BloomFilter,needed_bits,optimal_num_hashes};
-    use {ASMS,Intersectable,Unionable};
+    /// Get the number of bits that are set in this BloomFilter.
+    /// Dividing this by `num_bits` gives how full the filter is.
+    /// With the `simd` feature this uses AVX2 where the CPU has it,
+    /// which is much faster for large filters.
+    pub fn count_ones(&self) -> usize {
+        popcount::count_ones(self.bits.storage())
+    }
 
-    #[test]
-    fn simple() {
-        let mut b:BloomFilter = BloomFilter::with_rate(0.01,100);
-        b.insert(&1);
-        assert!(b.contains(&1));
-        assert!(!b.contains(&2));
-        b.clear();
-        assert!(!b.contains(&1));
+    /// Count how many times each bit index is picked by the hashes of
+    /// `items`, without inserting them.  The result has one entry per
+    /// bit, and its entries sum to `items.len() * num_hashes()`.
+    /// Indices hit far more often than the rest point to hashers that
+    /// don't spread the items evenly.
+    pub fn index_histogram<T: Hash>(&self, items: &[T]) -> Vec<u32> {
+        let mut histogram = vec![0; self.bits.len()];
+        for item in items {
+            for h in self.hashes(item) {
+                histogram[self.index(h)] += 1;
+            }
+        }
+        histogram
     }
 
-    #[test]
-    fn intersect() {
-        let mut b1:BloomFilter = BloomFilter::with_rate(0.01,20);
-        b1.insert(&1);
-        b1.insert(&2);
-        let mut b2:BloomFilter = BloomFilter::with_rate(0.01,20);
-        b2.insert(&1);
+    /// Get the number of bytes this BloomFilter is using, counting
+    /// both the struct itself and the words backing its bits.  Any
+    /// heap memory owned by the hashers is not included.
+    pub fn memory_bytes(&self) -> usize {
+        mem::size_of::<BloomFilter<R,S>>() + mem::size_of_val(self.bits.storage())
+    }
 
-        b1.intersect(&b2);
+    /// Returns true if no bits are set in this BloomFilter, as is the
+    /// case for a new or cleared filter
+    pub fn is_empty(&self) -> bool {
+        self.bits.none()
+    }
 
-        assert!(b1.contains(&1));
-        assert!(!b1.contains(&2));
+    /// Estimate the current false positive probability of this
+    /// BloomFilter from how many of its bits are set, as
+    /// `(count_ones / num_bits) ^ num_hashes`.  Unlike the rate a
+    /// filter was sized for, this rises as more items are inserted,
+    /// so it can be used to tell when a filter is overloaded.
+    #[cfg(feature = "std")]
+    pub fn current_fpp(&self) -> f64 {
+        let fill = self.count_ones() as f64 / self.bits.len() as f64;
+        fill.powi(self.num_hashes as i32)
     }
 
-    #[test]
-    fn union() {
-        let mut b1:BloomFilter = BloomFilter::with_rate(0.01,20);
-        b1.insert(&1);
-        let mut b2:BloomFilter = BloomFilter::with_rate(0.01,20);
-        b2.insert(&2);
+    /// Estimate the number of distinct items that have been inserted
+    /// into this BloomFilter from how many of its bits are set, as
+    /// `-(m / k) * ln(1 - X / m)` where `X` is the number of set
+    /// bits, `m` is `num_bits` and `k` is `num_hashes`.
+    ///
+    /// Returns 0 for an empty filter.  A filter with every bit set
+    /// can't tell how many items it holds, so in that case this
+    /// returns the estimate for a filter with a single unset bit.
+    #[cfg(feature = "std")]
+    pub fn estimate_count(&self) -> u64 {
+        estimate_items(self.count_ones(),self.bits.len(),self.num_hashes).round() as u64
+    }
 
-        b1.union(&b2);
+    /// Get the words backing this BloomFilter.  Bit `i` of the filter
+    /// is bit `i % 32` (counting from the least significant bit) of
+    /// word `i / 32`.
+    pub fn as_slice(&self) -> &[u32] {
+        self.bits.storage()
+    }
 
-        assert!(b1.contains(&1));
-        assert!(b1.contains(&2));
+    /// Rebuild a BloomFilter from words returned by `as_slice`,
+    /// hashing items with `hash_builder_one` and
+    /// `hash_builder_two`. Bits past `num_bits` in the last word are
+    /// ignored.  The words don't say how items were hashed, so the
+    /// filter always uses both hashers; a filter from
+    /// `with_rate_single_hash` has to be saved with `to_bytes` to be
+    /// rebuilt.
+    ///
+    /// # Panics
+    /// Panics if `bits` holds fewer than `num_bits` bits, or
+    /// `num_bits` is 0
+    pub fn from_raw_with_hashers(bits: Vec<u32>, num_bits: usize, num_hashes: u32,
+                                 hash_builder_one: R, hash_builder_two: S) -> BloomFilter<R,S> {
+        check_num_bits(num_bits);
+        if bits.len() * 32 < num_bits {
+            panic!("from_raw with {} words can hold {} bits, but num_bits is {}",
+                   bits.len(),bits.len()*32,num_bits);
+        }
+        BloomFilter {
+            bits: bit_vec_from_words(bits.into_iter(),num_bits),
+            num_hashes: num_hashes,
+            hash_builder_one: hash_builder_one,
+            hash_builder_two: hash_builder_two,
+            single_hash: false,
+            pow2: num_bits.is_power_of_two(),
+            num_inserts: 0,
+            rate: None,
+            expected_items: None,
+        }
+    }
+
+    /// Create a BloomFilter that uses `bits` as its storage, without
+    /// copying them, hashing items with `hash_builder_one` and
+    /// `hash_builder_two`.  Any bits already set are kept.  As with
+    /// `from_raw_with_hashers`, the filter always uses both hashers.
+    ///
+    /// # Panics
+    /// Panics if `bits` is empty
+    pub fn from_bit_vec_with_hashers(bits: BitVec, num_hashes: u32,
+                                     hash_builder_one: R, hash_builder_two: S) -> BloomFilter<R,S> {
+        check_num_bits(bits.len());
+        BloomFilter {
+            pow2: bits.len().is_power_of_two(),
+            bits: bits,
+            num_hashes: num_hashes,
+            hash_builder_one: hash_builder_one,
+            hash_builder_two: hash_builder_two,
+            single_hash: false,
+            num_inserts: 0,
+            rate: None,
+            expected_items: None,
+        }
+    }
+
+    /// Consume this BloomFilter, returning the BitVec holding its
+    /// bits without copying them
+    pub fn into_bit_vec(self) -> BitVec {
+        self.bits
+    }
+
+    /// Serialize this BloomFilter into a portable byte layout.
+    ///
+    /// The layout is a header of a 4 byte magic number, a `u32`
+    /// format version, the number of bits as a `u64`, the number of
+    /// hashes as a `u32` and a `u32` of flags, followed by the
+    /// backing storage as `u32` words, and finally a `u32` CRC-32 of
+    /// everything before it.  The only flag is bit 0, set for a
+    /// filter that hashes each item once (see
+    /// `with_rate_single_hash`).  All integers are written
+    /// little-endian, so the output is the same regardless of the
+    /// host's endianness.  The hashers are not serialized.
+    ///
+    /// Version 1 of the format had no checksum, and versions 1 and 2
+    /// no flags.  They can still be read, giving filters that use
+    /// both hashers, but are no longer written.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let num_words = words_for_bits(self.bits.len());
+        let mut bytes = Vec::with_capacity(HEADER_LEN + num_words * 4 + CHECKSUM_LEN);
+        bytes.extend_from_slice(&self.header());
+        for word in self.bits.storage().iter().take(num_words) {
+            bytes.extend_from_slice(&word.to_le_bytes());
+        }
+        let crc = crc32(0,&bytes);
+        bytes.extend_from_slice(&crc.to_le_bytes());
+        bytes
+    }
+
+    /// Write this BloomFilter to `writer` in the format produced by
+    /// `to_bytes`, without building the whole serialized filter in
+    /// memory first.  `writer` is written to in small pieces, so
+    /// wrap it in a `BufWriter` if those are expensive.
+    #[cfg(feature = "std")]
+    pub fn write_to<W: Write>(&self, mut writer: W) -> io::Result<()> {
+        let header = self.header();
+        writer.write_all(&header)?;
+        let mut crc = crc32(0,&header);
+        let num_words = words_for_bits(self.bits.len());
+        let mut buf = [0u8; IO_CHUNK_LEN];
+        for chunk in self.bits.storage()[..num_words].chunks(IO_CHUNK_LEN / 4) {
+            for (word,out) in chunk.iter().zip(buf.chunks_mut(4)) {
+                out.copy_from_slice(&word.to_le_bytes());
+            }
+            writer.write_all(&buf[..chunk.len() * 4])?;
+            crc = crc32(crc,&buf[..chunk.len() * 4]);
+        }
+        writer.write_all(&crc.to_le_bytes())
+    }
+
+    /// Serialize this BloomFilter in the layout of Guava's
+    /// `BloomFilter.writeTo`, so it can be read with Guava's
+    /// `BloomFilter.readFrom`: the strategy ordinal byte, the number
+    /// of hashes as a byte, the number of `long`s as a big-endian
+    /// `int`, then the bits as big-endian `long`s.
+    ///
+    /// Guava picks bits differently to this crate, so items must have
+    /// been inserted with `insert_guava` for Guava to find them.
+    ///
+    /// # Panics
+    /// Panics if the number of bits is not a multiple of 64, as Guava
+    /// filters always are, or there are more than 255 hashes
+    pub fn to_guava_bytes(&self) -> Vec<u8> {
+        let num_bits = self.bits.len();
+        if !num_bits.is_multiple_of(64) {
+            panic!("a Guava BloomFilter needs a multiple of 64 bits, this one has {}", num_bits);
+        }
+        if self.num_hashes > u8::MAX as u32 {
+            panic!("a Guava BloomFilter can use at most 255 hashes, this one uses {}", self.num_hashes);
+        }
+        let num_longs = num_bits / 64;
+        let mut bytes = Vec::with_capacity(GUAVA_HEADER_LEN + num_longs * 8);
+        bytes.push(GUAVA_STRATEGY);
+        bytes.push(self.num_hashes as u8);
+        bytes.extend_from_slice(&(num_longs as u32).to_be_bytes());
+        for pair in self.bits.storage()[..num_longs * 2].chunks(2) {
+            let long = pair[0] as u64 | (pair[1] as u64) << 32;
+            bytes.extend_from_slice(&long.to_be_bytes());
+        }
+        bytes
+    }
+
+    /// Reconstruct a BloomFilter from the output of Guava's
+    /// `BloomFilter.writeTo`, hashing items passed to `insert` and
+    /// `contains` with `hash_builder_one` and `hash_builder_two`.
+    /// Items the Guava filter holds are only found by
+    /// `contains_guava`.
+    ///
+    /// Only filters using Guava's default `MURMUR128_MITZ_64`
+    /// strategy can be read.  Returns an error if `bytes` uses
+    /// another strategy, is truncated, has trailing data, or
+    /// describes a filter with no bits.
+    pub fn from_guava_bytes_with_hashers(bytes: &[u8],
+                                         hash_builder_one: R, hash_builder_two: S) -> Result<BloomFilter<R,S>, ParseError> {
+        if bytes.len() < GUAVA_HEADER_LEN {
+            return Err(ParseError::Truncated);
+        }
+        if bytes[0] != GUAVA_STRATEGY {
+            return Err(ParseError::UnsupportedStrategy(bytes[0]));
+        }
+        let num_hashes = bytes[1] as u32;
+        let num_longs = u32::from_be_bytes([bytes[2],bytes[3],bytes[4],bytes[5]]) as u64;
+        if num_longs == 0 {
+            return Err(ParseError::ZeroBits);
+        }
+        let expected = GUAVA_HEADER_LEN as u64 + num_longs * 8;
+        if (bytes.len() as u64) < expected {
+            return Err(ParseError::Truncated);
+        }
+        if (bytes.len() as u64) > expected {
+            return Err(ParseError::TrailingBytes);
+        }
+        let mut words = Vec::with_capacity(num_longs as usize * 2);
+        for c in bytes[GUAVA_HEADER_LEN..].chunks(8) {
+            let long = u64::from_be_bytes([c[0],c[1],c[2],c[3],c[4],c[5],c[6],c[7]]);
+            words.push(long as u32);
+            words.push((long >> 32) as u32);
+        }
+        Ok(BloomFilter::from_raw_with_hashers(words,num_longs as usize * 64,num_hashes,
+                                              hash_builder_one,hash_builder_two))
+    }
+
+    // the serialized header for this filter
+    fn header(&self) -> [u8; HEADER_LEN] {
+        let mut header = [0u8; HEADER_LEN];
+        header[0..4].copy_from_slice(&MAGIC);
+        header[4..8].copy_from_slice(&FORMAT_VERSION.to_le_bytes());
+        header[8..16].copy_from_slice(&(self.bits.len() as u64).to_le_bytes());
+        header[16..20].copy_from_slice(&self.num_hashes.to_le_bytes());
+        let flags = if self.single_hash { FLAG_SINGLE_HASH } else { 0 };
+        header[20..24].copy_from_slice(&flags.to_le_bytes());
+        header
+    }
+
+    /// Reconstruct a BloomFilter from bytes produced by `to_bytes`,
+    /// hashing items with `hash_builder_one` and `hash_builder_two`.
+    /// These must produce the same hash values as the HashBuilders
+    /// the serialized filter was built with for `contains` to give
+    /// the same answers as the original filter.
+    ///
+    /// Returns an error if `bytes` is truncated, has trailing data,
+    /// doesn't start with a supported header, or doesn't match its
+    /// checksum.
+    pub fn from_bytes_with_hashers(bytes: &[u8],
+                                   hash_builder_one: R, hash_builder_two: S) -> Result<BloomFilter<R,S>, ParseError> {
+        let (num_bits, num_hashes, version, single_hash) = parse_header(bytes)?;
+        check_len(bytes.len() as u64,num_bits,version)?;
+        let mut payload = bytes;
+        if version >= 2 {
+            let (data, checksum) = bytes.split_at(bytes.len() - CHECKSUM_LEN);
+            check_crc(crc32(0,data),checksum)?;
+            payload = data;
+        }
+        let words = payload[header_len(version)..].chunks(4).map(|c| {
+            u32::from_le_bytes([c[0],c[1],c[2],c[3]])
+        });
+        Ok(BloomFilter {
+            bits: bit_vec_from_words(words,num_bits),
+            num_hashes: num_hashes,
+            hash_builder_one: hash_builder_one,
+            hash_builder_two: hash_builder_two,
+            single_hash: single_hash,
+            pow2: num_bits.is_power_of_two(),
+            num_inserts: 0,
+            rate: None,
+            expected_items: None,
+        })
+    }
+
+    /// Read a BloomFilter written by `write_to` (or `to_bytes`) from
+    /// `reader`, hashing items with `hash_builder_one` and
+    /// `hash_builder_two`.  See `from_bytes_with_hashers` for the
+    /// requirements on the hashers.
+    ///
+    /// The bits are read straight into the filter, a small piece at a
+    /// time, so a large filter is never held in memory twice.
+    /// Reading stops at the end of the filter, so anything after it
+    /// is left in `reader`.  A truncated or invalid filter gives an
+    /// error of kind `InvalidData`.  Unlike `load_from_path`, the
+    /// size in the header can't be checked against the length of the
+    /// input up front, so the filter is grown as its bits arrive
+    /// rather than allocated from the header.  A header claiming a
+    /// huge filter over a short input gives an error once the input
+    /// runs out, but an untrusted reader that keeps producing bytes
+    /// can still use up as much memory as it likes; limit it with
+    /// `Read::take` if that matters.
+    #[cfg(feature = "std")]
+    pub fn read_from_with_hashers<Rd: Read>(mut reader: Rd,
+                                            hash_builder_one: R, hash_builder_two: S) -> io::Result<BloomFilter<R,S>> {
+        let header = read_header(&mut reader)?;
+        let (num_bits, num_hashes, version, single_hash) = parse_header(&header)?;
+        let mut crc = crc32(0,&header);
+
+        // the header isn't trusted, so only grow the bits as far as
+        // the words actually read
+        let mut bits = BitVec::new();
+        let num_words = words_for_bits(num_bits);
+        let mut buf = [0u8; IO_CHUNK_LEN];
+        let mut word_idx = 0;
+        while word_idx < num_words {
+            let n = (num_words - word_idx).min(IO_CHUNK_LEN / 4);
+            reader.read_exact(&mut buf[..n * 4]).map_err(truncated_on_eof)?;
+            crc = crc32(crc,&buf[..n * 4]);
+            let grow_by = (n * 32).min(num_bits - bits.len());
+            bits.grow(grow_by,false);
+            let words = buf[..n * 4].chunks(4).map(|c| u32::from_le_bytes([c[0],c[1],c[2],c[3]]));
+            set_words(&mut bits,word_idx,words);
+            word_idx += n;
+        }
+        if version >= 2 {
+            let mut checksum = [0u8; CHECKSUM_LEN];
+            reader.read_exact(&mut checksum).map_err(truncated_on_eof)?;
+            check_crc(crc,&checksum)?;
+        }
+        Ok(BloomFilter {
+            bits: bits,
+            num_hashes: num_hashes,
+            hash_builder_one: hash_builder_one,
+            hash_builder_two: hash_builder_two,
+            single_hash: single_hash,
+            pow2: num_bits.is_power_of_two(),
+            num_inserts: 0,
+            rate: None,
+            expected_items: None,
+        })
+    }
+
+    /// Write this BloomFilter to the file at `path` in the format
+    /// produced by `to_bytes`, replacing the file if it exists.
+    #[cfg(feature = "std")]
+    pub fn save_to_path(&self, path: &Path) -> io::Result<()> {
+        let mut file = File::create(path)?;
+        file.write_all(&self.to_bytes())
+    }
+
+    /// Load a BloomFilter saved with `save_to_path`, hashing items
+    /// with `hash_builder_one` and `hash_builder_two`.  See
+    /// `from_bytes_with_hashers` for the requirements on the
+    /// hashers.
+    ///
+    /// The header is checked against the size of the file before the
+    /// filter is allocated, so a corrupt file fails with an error of
+    /// kind `InvalidData` rather than a huge allocation.
+    #[cfg(feature = "std")]
+    pub fn load_from_path_with_hashers(path: &Path,
+                                       hash_builder_one: R, hash_builder_two: S) -> io::Result<BloomFilter<R,S>> {
+        let mut file = File::open(path)?;
+        let header = read_header(&mut file)?;
+        let (num_bits, _, version, _) = parse_header(&header)?;
+        let len = check_len(file.metadata()?.len(),num_bits,version)?;
+
+        let mut bytes = Vec::with_capacity(len);
+        bytes.extend_from_slice(&header);
+        file.read_to_end(&mut bytes)?;
+        Ok(BloomFilter::from_bytes_with_hashers(&bytes,hash_builder_one,hash_builder_two)?)
+    }
+}
+
+/// Magic number that starts every serialized BloomFilter
+const MAGIC: [u8; 4] = *b"BLMF";
+/// Version of the serialized format written by `to_bytes`
+const FORMAT_VERSION: u32 = 3;
+/// Length of the serialized header in bytes
+const HEADER_LEN: usize = 24;
+/// Length of the serialized header in bytes before version 3, which
+/// added the flags
+const HEADER_LEN_V2: usize = 20;
+/// Header flag for a filter that hashes each item once
+const FLAG_SINGLE_HASH: u32 = 1;
+/// Length of the trailing checksum in bytes, from version 2
+const CHECKSUM_LEN: usize = 4;
+/// Ordinal of Guava's `MURMUR128_MITZ_64` strategy
+const GUAVA_STRATEGY: u8 = 1;
+/// Length of the header of Guava's serialized BloomFilter
+const GUAVA_HEADER_LEN: usize = 6;
+/// Size of the pieces `write_to` and `read_from` copy bits in
+#[cfg(feature = "std")]
+const IO_CHUNK_LEN: usize = 4096;
+
+/// Errors that can occur when reading a serialized BloomFilter or
+/// GolombSet
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ParseError {
+    /// The input ended before the whole filter could be read
+    Truncated,
+    /// The input had data after the end of the filter
+    TrailingBytes,
+    /// The input did not start with the expected magic number
+    BadMagic,
+    /// The input was written with a format version this crate can't
+    /// read
+    UnsupportedVersion(u32),
+    /// The input describes a filter with no bits
+    ZeroBits,
+    /// The input doesn't match its checksum, so has been corrupted
+    ChecksumMismatch,
+    /// The input is a Guava BloomFilter using a hashing strategy
+    /// other than `MURMUR128_MITZ_64` (ordinal 1)
+    UnsupportedStrategy(u8),
+    /// The input describes a GolombSet with parameters that can't be
+    /// used, such as 0 bits per value
+    InvalidParameters,
+    /// The input describes a filter with more bits than this platform
+    /// can address
+    TooLarge(u64),
+    /// The input sets header flags this crate doesn't know about
+    UnsupportedFlags(u32),
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            ParseError::Truncated => write!(f, "serialized filter is truncated"),
+            ParseError::TrailingBytes => write!(f, "serialized filter has trailing bytes"),
+            ParseError::BadMagic => write!(f, "input is not a serialized filter of this type (bad magic number)"),
+            ParseError::UnsupportedVersion(v) => {
+                write!(f, "unsupported BloomFilter format version {}, expected 1 to {}",
+                       v,FORMAT_VERSION)
+            }
+            ParseError::ZeroBits => write!(f, "serialized BloomFilter has no bits"),
+            ParseError::ChecksumMismatch => write!(f, "serialized BloomFilter is corrupt (checksum mismatch)"),
+            ParseError::UnsupportedStrategy(s) => {
+                write!(f, "unsupported Guava BloomFilter strategy {}, expected {}",
+                       s,GUAVA_STRATEGY)
+            }
+            ParseError::InvalidParameters => write!(f, "serialized GolombSet has invalid parameters"),
+            ParseError::TooLarge(n) => write!(f, "serialized BloomFilter has {} bits, too many for this platform", n),
+            ParseError::UnsupportedFlags(flags) => write!(f, "serialized BloomFilter has unsupported flags {:#x}", flags),
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl Error for ParseError {}
+
+#[cfg(feature = "std")]
+impl From<ParseError> for io::Error {
+    fn from(err: ParseError) -> io::Error {
+        io::Error::new(io::ErrorKind::InvalidData, err)
+    }
+}
+
+// read the number of bits and hashes, the version, and whether the
+// filter hashes items once out of a serialized header, checking the
+// magic and flags along the way
+fn parse_header(bytes: &[u8]) -> Result<(usize, u32, u32, bool), ParseError> {
+    if bytes.len() < HEADER_LEN_V2 {
+        return Err(ParseError::Truncated);
+    }
+    if bytes[0..4] != MAGIC {
+        return Err(ParseError::BadMagic);
+    }
+    let version = u32::from_le_bytes([bytes[4],bytes[5],bytes[6],bytes[7]]);
+    if version == 0 || version > FORMAT_VERSION {
+        return Err(ParseError::UnsupportedVersion(version));
+    }
+    if bytes.len() < header_len(version) {
+        return Err(ParseError::Truncated);
+    }
+    let mut num_bits = [0u8; 8];
+    num_bits.copy_from_slice(&bytes[8..16]);
+    let num_bits = u64::from_le_bytes(num_bits);
+    if num_bits == 0 {
+        return Err(ParseError::ZeroBits);
+    }
+    let num_bits = usize::try_from(num_bits).map_err(|_| ParseError::TooLarge(num_bits))?;
+    let num_hashes = u32::from_le_bytes([bytes[16],bytes[17],bytes[18],bytes[19]]);
+    let mut flags = 0;
+    if version >= 3 {
+        flags = u32::from_le_bytes([bytes[20],bytes[21],bytes[22],bytes[23]]);
+        if flags & !FLAG_SINGLE_HASH != 0 {
+            return Err(ParseError::UnsupportedFlags(flags));
+        }
+    }
+    Ok((num_bits, num_hashes, version, flags & FLAG_SINGLE_HASH != 0))
+}
+
+// the length of the header in the given format version
+fn header_len(version: u32) -> usize {
+    if version >= 3 { HEADER_LEN } else { HEADER_LEN_V2 }
+}
+
+// read a serialized header from reader, which is shorter before
+// version 3
+#[cfg(feature = "std")]
+fn read_header<Rd: Read>(reader: &mut Rd) -> io::Result<Vec<u8>> {
+    let mut header = vec![0u8; HEADER_LEN_V2];
+    reader.read_exact(&mut header).map_err(truncated_on_eof)?;
+    let version = u32::from_le_bytes([header[4],header[5],header[6],header[7]]);
+    if (3..=FORMAT_VERSION).contains(&version) {
+        header.resize(HEADER_LEN,0);
+        reader.read_exact(&mut header[HEADER_LEN_V2..]).map_err(truncated_on_eof)?;
+    }
+    Ok(header)
+}
+
+// a rate of 1 or more would give a filter with no bits, and a rate
+// of 0 or less (or NaN) one with infinitely many
+#[cfg(feature = "std")]
+fn check_rate(rate: f64) {
+    if !(rate > 0.0 && rate < 1.0) {
+        panic!("false positive rate must be between 0 and 1 (exclusive), got {}", rate);
+    }
+}
+
+// a filter with no bits can't hold anything, and would divide by
+// zero when computing indices
+fn check_num_bits(num_bits: usize) {
+    if num_bits == 0 {
+        panic!("BloomFilter must have at least one bit");
+    }
+}
+
+// check that len is the exact serialized length of a filter with
+// num_bits bits, returning that length
+fn check_len(len: u64, num_bits: usize, version: u32) -> Result<usize, ParseError> {
+    let trailer = if version >= 2 { CHECKSUM_LEN } else { 0 };
+    let expected = (num_bits as u64).div_ceil(32)
+        .checked_mul(4)
+        .and_then(|b| b.checked_add((header_len(version) + trailer) as u64));
+    match expected {
+        Some(e) if len == e => Ok(e as usize),
+        Some(e) if len > e => Err(ParseError::TrailingBytes),
+        _ => Err(ParseError::Truncated),
+    }
+}
+
+// number of u32 words needed to hold num_bits bits
+fn words_for_bits(num_bits: usize) -> usize {
+    num_bits.div_ceil(32)
+}
+
+// build a BitVec of num_bits bits from u32 words in BitVec's storage
+// order, ignoring any bits past num_bits
+fn bit_vec_from_words<I: Iterator<Item=u32>>(words: I, num_bits: usize) -> BitVec {
+    let mut bits = BitVec::from_elem(num_bits,false);
+    set_words(&mut bits,0,words);
+    bits
+}
+
+// copy words into the storage of bits from word start on, ignoring
+// any words past the end and clearing any bits past the end in the
+// last word
+fn set_words<I: Iterator<Item=u32>>(bits: &mut BitVec, start: usize, words: I) {
+    let num_bits = bits.len();
+    // the storage keeps its length, and BitVec only needs the unused
+    // bits of its last word to stay clear, which is restored below
+    let storage = unsafe { bits.storage_mut() };
+    for (slot,word) in storage[start..].iter_mut().zip(words) {
+        *slot = word;
+    }
+    let extra = num_bits % 32;
+    if extra != 0 {
+        if let Some(last) = storage.last_mut() {
+            *last &= (1 << extra) - 1;
+        }
+    }
+}
+
+// table for the CRC-32 used by zlib and gzip (reflected polynomial
+// 0xedb88320), one entry per byte value
+const CRC32_TABLE: [u32; 256] = crc32_table();
+
+const fn crc32_table() -> [u32; 256] {
+    let mut table = [0u32; 256];
+    let mut i = 0;
+    while i < 256 {
+        let mut c = i as u32;
+        let mut k = 0;
+        while k < 8 {
+            c = if c & 1 != 0 { 0xedb88320 ^ (c >> 1) } else { c >> 1 };
+            k += 1;
+        }
+        table[i] = c;
+        i += 1;
+    }
+    table
+}
+
+// continue the CRC-32 crc (0 to start) over data, so that
+// crc32(crc32(0,a),b) is the CRC-32 of a followed by b
+fn crc32(crc: u32, data: &[u8]) -> u32 {
+    let mut c = !crc;
+    for &b in data {
+        c = CRC32_TABLE[((c ^ b as u32) & 0xff) as usize] ^ (c >> 8);
+    }
+    !c
+}
+
+// check the computed crc against the little-endian stored checksum
+fn check_crc(crc: u32, checksum: &[u8]) -> Result<(), ParseError> {
+    if crc.to_le_bytes() == checksum {
+        Ok(())
+    } else {
+        Err(ParseError::ChecksumMismatch)
+    }
+}
+
+// reading a serialized filter that ends early is a parse error, not
+// an io one
+#[cfg(feature = "std")]
+fn truncated_on_eof(e: io::Error) -> io::Error {
+    if e.kind() == io::ErrorKind::UnexpectedEof {
+        io::Error::from(ParseError::Truncated)
+    } else {
+        e
+    }
+}
+
+impl<R,S> BloomFilter<R,S> {
+    /// Insert an item that has already been hashed to `h1` and `h2`
+    /// into this BloomFilter, skipping the filter's own hashers.
+    /// The caller is responsible for `h1` and `h2` being independent
+    /// hashes of the item; correlated values will break the false
+    /// positive guarantees of the BloomFilter.  Items inserted this
+    /// way can only be found with `contains_hash`.
+    ///
+    /// Returns `true` if the BloomFilter did not already have this
+    /// value present, as for `insert`.
+    pub fn insert_hash(&mut self, h1: u64, h2: u64) -> bool {
+        let hashes = HashIter::from_hashes(h1,h2,self.num_hashes);
+        self.insert_hashes(hashes)
+    }
+
+    /// Check if an item hashed to `h1` and `h2` has been inserted
+    /// with `insert_hash`.  This function can return false
+    /// positives, but not false negatives.
+    pub fn contains_hash(&self, h1: u64, h2: u64) -> bool {
+        self.contains_hashes(HashIter::from_hashes(h1,h2,self.num_hashes))
+    }
+
+    /// Insert an item into this BloomFilter the way Guava's
+    /// `BloomFilter.put` does, so that a Guava filter read from
+    /// `to_guava_bytes` will contain it.  `bytes` must be what the
+    /// Guava filter's `Funnel` writes for the item, for example the
+    /// UTF-8 bytes of a string for `Funnels.stringFunnel(UTF_8)`, or
+    /// the little-endian bytes of an `int` for
+    /// `Funnels.integerFunnel()`.  These are hashed with
+    /// `hashers::murmur3_x64_128`, ignoring the filter's hashers.
+    ///
+    /// Returns `true` if the BloomFilter did not already have this
+    /// value present, as for `insert`.
+    pub fn insert_guava(&mut self, bytes: &[u8]) -> bool {
+        self.num_inserts = self.num_inserts.saturating_add(1);
+        let mut contained = true;
+        for idx in self.guava_indices(bytes) {
+            if !self.bits[idx] {
+                contained = false;
+                self.bits.set(idx,true)
+            }
+        }
+        !contained
+    }
+
+    /// Check if an item has been inserted with `insert_guava`, or
+    /// into the Guava filter this one was read from with
+    /// `from_guava_bytes`.  `bytes` must be what the Guava filter's
+    /// `Funnel` writes for the item.  This function can return false
+    /// positives, but not false negatives.
+    pub fn contains_guava(&self, bytes: &[u8]) -> bool {
+        self.guava_indices(bytes).all(|idx| self.bits[idx])
+    }
+
+    // the bits Guava's MURMUR128_MITZ_64 strategy picks for bytes:
+    // h1 + i*h2, with the sign bit cleared, modulo the number of bits
+    fn guava_indices(&self, bytes: &[u8]) -> impl Iterator<Item=usize> {
+        let (h1, h2) = murmur3_x64_128(bytes,0);
+        let num_bits = self.bits.len() as u64;
+        (0..self.num_hashes as u64).map(move |i| {
+            ((h1.wrapping_add(i.wrapping_mul(h2)) & i64::MAX as u64) % num_bits) as usize
+        })
+    }
+
+    // the index of the bit that h selects.  Filters with a power of
+    // two number of bits can mask, which gives the same index as the
+    // modulo but is much cheaper
+    fn index(&self, h: u64) -> usize {
+        let idx = if self.pow2 {
+            (h & (self.bits.len() as u64 - 1)) as usize
+        } else {
+            (h % self.bits.len() as u64) as usize
+        };
+        debug_assert!(idx < self.bits.len());
+        idx
+    }
+
+    // set the bits for hashes, returning true if any were unset
+    fn insert_hashes(&mut self, hashes: HashIter) -> bool {
+        self.num_inserts = self.num_inserts.saturating_add(1);
+        let mut contained = true;
+        for h in hashes {
+            let idx = self.index(h);
+            if !self.bits[idx] {
+                contained = false;
+                self.bits.set(idx,true)
+            }
+        }
+        !contained
+    }
+
+    // check that the bits for hashes are all set
+    fn contains_hashes(&self, hashes: HashIter) -> bool {
+        for h in hashes {
+            if !self.bits[self.index(h)] {
+                return false;
+            }
+        }
+        true
+    }
+}
+
+impl<R,S> ASMS for BloomFilter<R,S>
+    where R: BuildHasher, S: BuildHasher {
+    /// Insert item into this BloomFilter.
+    ///
+    /// If the BloomFilter did not have this value present, `true` is returned.
+    ///
+    /// If the BloomFilter did have this value present, `false` is returned.
+    fn insert<T: Hash>(& mut self,item: &T) -> bool {
+        let hashes = self.hashes(item);
+        self.insert_hashes(hashes)
+    }
+
+    /// Check if the item has been inserted into this bloom filter.
+    /// This function can return false positives, but not false
+    /// negatives.
+    fn contains<T: Hash>(&self, item: &T) -> bool {
+        self.contains_hashes(self.hashes(item))
+    }
+
+    /// Remove all values from this BloomFilter
+    fn clear(&mut self) {
+        self.bits.clear();
+        self.num_inserts = 0;
+    }
+
+    /// The number of bits this BloomFilter is using
+    fn capacity_bits(&self) -> usize {
+        self.bits.len()
+    }
+
+    /// The number of hash functions this BloomFilter is using
+    fn num_hashes(&self) -> u32 {
+        self.num_hashes
+    }
+}
+
+impl<R,S> Resettable for BloomFilter<R,S>
+    where R: BuildHasher, S: BuildHasher {
+    /// Remove all values from this BloomFilter, including the count of
+    /// inserts
+    fn reset(&mut self) {
+        self.clear();
+    }
+}
+
+impl<R,S> BloomFilter<R,S>
+    where R: BuildHasher + Clone, S: BuildHasher + Clone
+{
+    /// Calculates the union of two BloomFilters into a new filter,
+    /// leaving both `self` and `other` unchanged.  The new filter
+    /// uses the same hashers as `self`.
+    ///
+    /// # Panics
+    /// Panics if the BloomFilters are not using the same number of
+    /// bits and hashes
+    pub fn union_new(&self, other: &BloomFilter<R,S>) -> BloomFilter<R,S> {
+        self.check_compatible(other);
+        let mut bits = self.bits.clone();
+        bits.union(&other.bits);
+        self.with_bits(bits,self.num_inserts.saturating_add(other.num_inserts))
+    }
+
+    /// Calculates the intersection of two BloomFilters into a new
+    /// filter, leaving both `self` and `other` unchanged.  The new
+    /// filter uses the same hashers as `self`.
+    ///
+    /// # Panics
+    /// Panics if the BloomFilters are not using the same number of
+    /// bits and hashes
+    pub fn intersect_new(&self, other: &BloomFilter<R,S>) -> BloomFilter<R,S> {
+        self.check_compatible(other);
+        let mut bits = self.bits.clone();
+        bits.intersect(&other.bits);
+        self.with_bits(bits,self.num_inserts)
+    }
+
+    /// Replace this BloomFilter with an empty one of the same size,
+    /// number of hashes and hashers, and return the filter as it was.
+    /// This is like `mem::take`, but keeps the filter's shape, and
+    /// avoids cloning the bits when the old contents are still needed.
+    pub fn take(&mut self) -> BloomFilter<R,S> {
+        let empty = self.with_bits(BitVec::from_elem(self.bits.len(),false),0);
+        mem::replace(self,empty)
+    }
+
+    // a filter like self, with its hashers, but holding bits
+    fn with_bits(&self, bits: BitVec, num_inserts: u64) -> BloomFilter<R,S> {
+        BloomFilter {
+            bits: bits,
+            num_hashes: self.num_hashes,
+            hash_builder_one: self.hash_builder_one.clone(),
+            hash_builder_two: self.hash_builder_two.clone(),
+            single_hash: self.single_hash,
+            pow2: self.pow2,
+            num_inserts: num_inserts,
+            rate: self.rate,
+            expected_items: self.expected_items,
+        }
+    }
+
+    /// Fold this BloomFilter into a new filter `factor` times
+    /// smaller, by ORing together the `factor` equal length pieces of
+    /// its bits.  Items are turned into bit indices modulo the number
+    /// of bits, so every item in `self` is still contained in the
+    /// folded filter, which uses the same hashers, though with a
+    /// higher false positive rate.  Folding only preserves membership
+    /// because indices are computed this way, and the folded filter
+    /// can only be combined with filters of its own size.
+    ///
+    /// # Panics
+    /// Panics if `factor` is 0, or doesn't divide `num_bits`
+    pub fn fold(&self, factor: usize) -> BloomFilter<R,S> {
+        let num_bits = self.bits.len();
+        if factor == 0 || !num_bits.is_multiple_of(factor) {
+            panic!("can only fold a BloomFilter by a factor of its number of bits ({}), got {}",
+                   num_bits,factor);
+        }
+        let new_bits = num_bits / factor;
+        let mut bits = BitVec::from_elem(new_bits,false);
+        for (i,bit) in self.bits.iter().enumerate() {
+            if bit {
+                bits.set(i % new_bits,true);
+            }
+        }
+        BloomFilter {
+            bits: bits,
+            num_hashes: self.num_hashes,
+            hash_builder_one: self.hash_builder_one.clone(),
+            hash_builder_two: self.hash_builder_two.clone(),
+            single_hash: self.single_hash,
+            pow2: new_bits.is_power_of_two(),
+            num_inserts: self.num_inserts,
+            rate: None,
+            expected_items: None,
+        }
+    }
+}
+
+impl<R,S> BloomFilter<R,S> {
+    /// Clears every bit in `self` that is set in `other`.  Items
+    /// inserted only into `self` will usually still be present, and
+    /// items inserted into both are removed.  This is only an
+    /// approximation of the set difference: an item inserted only
+    /// into `self` is lost if any of its bits is shared with an item
+    /// in `other`, so unlike other BloomFilter operations this can
+    /// give false negatives.  Returns true if self changed.
+    ///
+    /// # Panics
+    /// Panics if the BloomFilters are not using the same number of
+    /// bits and hashes
+    pub fn difference(&mut self, other: &BloomFilter<R,S>) -> bool {
+        self.check_compatible(other);
+        self.bits.difference(&other.bits)
+    }
+
+    /// Calculates the union of two BloomFilters like `union`, but
+    /// returns false without touching `self` when `other` has no bits
+    /// set.  Checking an empty filter stops at the first set word and
+    /// never writes, so this is cheaper than `union` where most
+    /// filters being merged in are empty.  Returns true if self
+    /// changed.
+    ///
+    /// # Panics
+    /// Panics if the BloomFilters are not using the same number of
+    /// bits and hashes
+    pub fn union_if_nonempty(&mut self, other: &BloomFilter<R,S>) -> bool {
+        self.check_compatible(other);
+        if other.bits.none() {
+            return false;
+        }
+        self.num_inserts = self.num_inserts.saturating_add(other.num_inserts);
+        self.bits.union(&other.bits)
+    }
+
+    /// Returns true if `self` and `other` can be combined with
+    /// `union`, `intersect` and the other operations that panic on
+    /// mismatched filters: they must have the same number of bits and
+    /// hashes, and both or neither must hash each item once.
+    ///
+    /// The hashers can't be compared, so this can't tell whether the
+    /// two filters hash items the same way.  Combining filters whose
+    /// hashers differ won't panic, but gives meaningless results.
+    pub fn is_compatible_with(&self, other: &BloomFilter<R,S>) -> bool {
+        self.bits.len() == other.bits.len() &&
+            self.num_hashes == other.num_hashes &&
+            self.single_hash == other.single_hash
+    }
+
+    // panic unless other has the same number of bits and hashes as
+    // self, and hashes items the same way, so combining the two is
+    // meaningful
+    fn check_compatible(&self, other: &BloomFilter<R,S>) {
+        if self.bits.len() != other.bits.len() {
+            panic!("BloomFilters have different numbers of bits: {} vs {}",
+                   self.bits.len(),other.bits.len());
+        }
+        if self.num_hashes != other.num_hashes {
+            panic!("BloomFilters have different numbers of hashes: {} vs {}",
+                   self.num_hashes,other.num_hashes);
+        }
+        if self.single_hash != other.single_hash {
+            panic!("BloomFilters hash items differently: only one hashes each item once");
+        }
+    }
+}
+
+impl<'b,R,S> BitOr<&'b BloomFilter<R,S>> for &BloomFilter<R,S>
+    where R: BuildHasher + Clone, S: BuildHasher + Clone {
+    type Output = BloomFilter<R,S>;
+
+    /// The union of two BloomFilters, as a new filter.  See
+    /// `union_new`.
+    fn bitor(self, other: &'b BloomFilter<R,S>) -> BloomFilter<R,S> {
+        self.union_new(other)
+    }
+}
+
+impl<'b,R,S> BitAnd<&'b BloomFilter<R,S>> for &BloomFilter<R,S>
+    where R: BuildHasher + Clone, S: BuildHasher + Clone {
+    type Output = BloomFilter<R,S>;
+
+    /// The intersection of two BloomFilters, as a new filter.  See
+    /// `intersect_new`.
+    fn bitand(self, other: &'b BloomFilter<R,S>) -> BloomFilter<R,S> {
+        self.intersect_new(other)
+    }
+}
+
+impl<R,S> BitOrAssign<&BloomFilter<R,S>> for BloomFilter<R,S>
+    where R: BuildHasher, S: BuildHasher {
+    /// Union `other` into `self`.  See `Unionable::union`.
+    fn bitor_assign(&mut self, other: &BloomFilter<R,S>) {
+        self.union(other);
+    }
+}
+
+impl<R,S> BitAndAssign<&BloomFilter<R,S>> for BloomFilter<R,S>
+    where R: BuildHasher, S: BuildHasher {
+    /// Intersect `self` with `other`.  See
+    /// `Intersectable::intersect`.
+    fn bitand_assign(&mut self, other: &BloomFilter<R,S>) {
+        self.intersect(other);
+    }
+}
+
+impl<T,R,S> Extend<T> for BloomFilter<R,S>
+    where T: Hash, R: BuildHasher, S: BuildHasher {
+    /// Insert every item from `iter` into this BloomFilter
+    fn extend<I: IntoIterator<Item=T>>(&mut self, iter: I) {
+        for item in iter {
+            self.insert(&item);
+        }
+    }
+}
+
+impl<R,S> fmt::Debug for BloomFilter<R,S>
+    where R: BuildHasher, S: BuildHasher {
+    /// Shows the size of the filter and how many bits are set, but
+    /// not the bits themselves or the hashers
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("BloomFilter")
+            .field("num_bits", &self.bits.len())
+            .field("num_hashes", &self.num_hashes)
+            .field("set_bits", &self.count_ones())
+            .finish()
+    }
+}
+
+impl<R,S> PartialEq for BloomFilter<R,S> {
+    /// Two BloomFilters are equal if they have the same number of
+    /// bits and hashes, both or neither hash items only once (see
+    /// `with_rate_single_hash`), and exactly the same bits set.  The
+    /// hashers are not compared, so filters that hash items
+    /// differently can still be equal.
+    fn eq(&self, other: &BloomFilter<R,S>) -> bool {
+        self.num_hashes == other.num_hashes &&
+            self.single_hash == other.single_hash &&
+            self.bits == other.bits
+    }
+}
+
+impl<R,S> Eq for BloomFilter<R,S> {}
+
+impl<R,S> Hash for BloomFilter<R,S> {
+    /// Hashes the same things `eq` compares: the number of bits and
+    /// hashes, whether items are hashed once, and the bits set.  Two
+    /// BloomFilters that are equal hash equally, so filters can be
+    /// used as `HashMap` keys.
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.bits.len().hash(state);
+        self.num_hashes.hash(state);
+        self.single_hash.hash(state);
+        self.bits.storage()[..words_for_bits(self.bits.len())].hash(state);
+    }
+}
+
+impl<R,S> Intersectable for BloomFilter<R,S>
+    where R: BuildHasher, S: BuildHasher {
+    /// Calculates the intersection of two BloomFilters.  Only items inserted into both filters will still be present in `self`.
+    ///
+    /// Both BloomFilters must be using the same number of bits and
+    /// hashes. Returns true if self changed.
+    ///
+    /// # Panics
+    /// Panics if the BloomFilters are not using the same number of
+    /// bits and hashes
+    fn intersect(&mut self, other: &BloomFilter<R,S>) -> bool {
+        self.check_compatible(other);
+        self.bits.intersect(&other.bits)
+    }
+}
+
+
+impl<R,S> Unionable for BloomFilter<R,S>
+    where R: BuildHasher, S: BuildHasher {
+    /// Calculates the union of two BloomFilters.  Items inserted into
+    /// either filters will be present in `self`.
+    ///
+    /// Both BloomFilters must be using the same number of bits and
+    /// hashes. Returns true if self changed.
+    ///
+    /// # Panics
+    /// Panics if the BloomFilters are not using the same number of
+    /// bits and hashes
+    fn union(&mut self, other: &BloomFilter<R,S>) -> bool {
+        self.check_compatible(other);
+        self.num_inserts = self.num_inserts.saturating_add(other.num_inserts);
+        self.bits.union(&other.bits)
+    }
+}
+
+/// The union of all of `filters`: a filter holding every item
+/// inserted into any of them, or `None` if there are no filters.
+///
+/// All the filters must be using the same hashers for the result to
+/// be meaningful.
+///
+/// # Panics
+/// Panics if the BloomFilters are not all using the same number of
+/// bits and hashes
+///
+/// # Example
+///
+/// ```rust
+/// use bloom::{ASMS,BloomFilter};
+/// use bloom::hashers::SipBuildHasher;
+///
+/// let (one, two) = SipBuildHasher::pair_from_seed(1);
+/// let mut a = BloomFilter::with_size_and_hashers(1000,4,one,two);
+/// let mut b = BloomFilter::with_size_and_hashers(1000,4,one,two);
+/// a.insert(&1);
+/// b.insert(&2);
+/// let union = bloom::union_all(vec![&a,&b]).unwrap();
+/// assert!(union.contains(&1) && union.contains(&2));
+/// ```
+pub fn union_all<'a,R,S,I>(filters: I) -> Option<BloomFilter<R,S>>
+    where R: BuildHasher + Clone + 'a, S: BuildHasher + Clone + 'a,
+          I: IntoIterator<Item = &'a BloomFilter<R,S>>
+{
+    let mut filters = filters.into_iter();
+    let first = filters.next()?;
+    let mut union = first.with_bits(first.bits.clone(),first.num_inserts);
+    for filter in filters {
+        union.union(filter);
+    }
+    Some(union)
+}
+
+
+// estimate how many distinct items were inserted into a filter with
+// num_bits bits and num_hashes hashes that has ones bits set
+#[cfg(feature = "std")]
+fn estimate_items(ones: usize, num_bits: usize, num_hashes: u32) -> f64 {
+    if ones == 0 || num_hashes == 0 {
+        return 0.0;
+    }
+    let m = num_bits as f64;
+    let x = if ones >= num_bits { m - 1.0 } else { ones as f64 };
+    -(m / num_hashes as f64) * (1.0 - x / m).ln()
+}
+
+/// Estimate the number of distinct items in `A ∪ B`, the union of
+/// the sets of items inserted into `a` and `b`.
+///
+/// This is the estimate `estimate_count` would give for the bitwise
+/// OR of the two filters, without building it.  Subtracting it from
+/// the sum of the two filters' own estimates gives an estimate of the
+/// size of their intersection.
+///
+/// Both filters must be using the same hashers for the result to be
+/// meaningful.
+///
+/// # Panics
+/// Panics if the BloomFilters are not using the same number of bits
+/// and hashes
+#[cfg(feature = "std")]
+pub fn union_cardinality<R,S>(a: &BloomFilter<R,S>, b: &BloomFilter<R,S>) -> f64
+    where R: BuildHasher, S: BuildHasher
+{
+    a.check_compatible(b);
+    let union_ones: usize = a.bits.storage().iter().zip(b.bits.storage().iter())
+        .map(|(x,y)| (x | y).count_ones() as usize)
+        .sum();
+    estimate_items(union_ones,a.bits.len(),a.num_hashes)
+}
+
+/// Estimate the Jaccard similarity `|A ∩ B| / |A ∪ B|` of the sets
+/// of items inserted into `a` and `b`.
+///
+/// The sizes of `A`, `B` and `A ∪ B` are estimated from the number
+/// of bits set in `a`, `b`, and their bitwise OR, and the size of the
+/// intersection follows from `|A ∩ B| = |A| + |B| - |A ∪ B|`.  Returns
+/// 1.0 if both filters are empty.
+///
+/// Both filters must be using the same hashers for the result to be
+/// meaningful.
+///
+/// # Panics
+/// Panics if the BloomFilters are not using the same number of bits
+/// and hashes
+#[cfg(feature = "std")]
+pub fn jaccard_similarity<R,S>(a: &BloomFilter<R,S>, b: &BloomFilter<R,S>) -> f64
+    where R: BuildHasher, S: BuildHasher
+{
+    let union = union_cardinality(a,b);
+    if union == 0.0 {
+        return 1.0;
+    }
+    let a_items = estimate_items(a.count_ones(),a.bits.len(),a.num_hashes);
+    let b_items = estimate_items(b.count_ones(),b.bits.len(),b.num_hashes);
+    let intersection = (a_items + b_items - union).max(0.0);
+    (intersection / union).min(1.0)
+}
+
+/// The fewest hashes `optimal_num_hashes` will return
+pub const MIN_NUM_HASHES: u32 = 2;
+/// The most hashes `optimal_num_hashes` will return
+pub const MAX_NUM_HASHES: u32 = 200;
+
+/// Return the optimal number of hashes to use for the given number of
+/// bits and items in a filter, between `MIN_NUM_HASHES` and
+/// `MAX_NUM_HASHES`.  See `optimal_num_hashes_bounded` for why the
+/// number of hashes is bounded.
+#[cfg(feature = "std")]
+pub fn optimal_num_hashes(num_bits: usize, num_items: u32) -> u32 {
+    optimal_num_hashes_capped(num_bits,num_items,MAX_NUM_HASHES)
+}
+
+/// Return the optimal number of hashes to use for the given number of
+/// bits and items in a filter, between `MIN_NUM_HASHES` and
+/// `max_hashes`.
+///
+/// # Panics
+/// Panics if `max_hashes` is less than `MIN_NUM_HASHES`
+#[cfg(feature = "std")]
+pub fn optimal_num_hashes_capped(num_bits: usize, num_items: u32, max_hashes: u32) -> u32 {
+    optimal_num_hashes_bounded(num_bits,num_items,MIN_NUM_HASHES,max_hashes)
+}
+
+/// Return the optimal number of hashes to use for the given number of
+/// bits and items in a filter, between `min_hashes` and `max_hashes`.
+///
+/// The optimal number is `num_bits / num_items * ln(2)`, which can
+/// be far from useful at the extremes.  With less than about one bit
+/// per item it rounds down to 0, and a filter with no hashes would
+/// say it contains everything, so there is a floor.  With very many
+/// bits per item it grows without bound, but each hash costs time on
+/// every `insert` and `contains` while the false positive rate is
+/// already negligible, so there is a ceiling.
+///
+/// # Panics
+/// Panics if `min_hashes` is 0, or more than `max_hashes`
+#[cfg(feature = "std")]
+pub fn optimal_num_hashes_bounded(num_bits: usize, num_items: u32, min_hashes: u32, max_hashes: u32) -> u32 {
+    bounded_num_hashes(num_bits,num_items as u64,min_hashes,max_hashes)
+}
+
+/// Return the lowest false positive rate a filter with `num_bits`
+/// bits can have once it holds `num_items` items, which is the rate
+/// of a filter from `BloomFilter::with_bit_budget`.  This is
+/// `(1 - e^(-k * num_items / num_bits))^k`, with `k` from
+/// `optimal_num_hashes`, and is the inverse of `needed_bits`.
+///
+/// # Example
+///
+/// ```rust
+/// use bloom::{achievable_rate,needed_bits};
+///
+/// let rate = achievable_rate(needed_bits(0.01,1000),1000);
+/// assert!((rate - 0.01).abs() < 0.001);
+/// ```
+#[cfg(feature = "std")]
+pub fn achievable_rate(num_bits: usize, num_items: u32) -> f64 {
+    let k = optimal_num_hashes(num_bits,num_items) as f64;
+    let fill = 1.0 - (-k * num_items as f64 / num_bits as f64).exp();
+    fill.powf(k)
+}
+
+/// Return the number of bits needed to satisfy the specified false
+/// positive rate, if the filter will hold `num_items` items.  This is
+/// computed using `f64` math, so it is accurate for large filters.
+///
+/// # Panics
+/// Panics if `false_pos_rate` is not strictly between 0 and 1
+#[cfg(feature = "std")]
+pub fn needed_bits(false_pos_rate:f32, num_items: u32) -> usize {
+    needed_bits_u64(false_pos_rate as f64,num_items as u64)
+}
+
+/// Return the optimal number of hashes to use for the given number of
+/// bits and items in a filter, like `optimal_num_hashes` but for
+/// filters expected to hold more than `u32::MAX` items.
+#[cfg(feature = "std")]
+pub fn optimal_num_hashes_u64(num_bits: usize, num_items: u64) -> u32 {
+    bounded_num_hashes(num_bits,num_items,MIN_NUM_HASHES,MAX_NUM_HASHES)
+}
+
+// the optimal number of hashes, clamped to [min_hashes, max_hashes]
+#[cfg(feature = "std")]
+fn bounded_num_hashes(num_bits: usize, num_items: u64, min_hashes: u32, max_hashes: u32) -> u32 {
+    if min_hashes == 0 {
+        panic!("filters need at least one hash");
+    }
+    if min_hashes > max_hashes {
+        panic!("min_hashes ({}) is more than max_hashes ({})", min_hashes, max_hashes);
+    }
+    let k = (num_bits as f64 / num_items as f64 * core::f64::consts::LN_2).round();
+    k.clamp(min_hashes as f64,max_hashes as f64) as u32
+}
+
+/// Return the number of bits needed to satisfy the specified false
+/// positive rate, if the filter will hold `num_items` items.  This is
+/// like `needed_bits`, but takes a `u64` item count and an `f64` rate
+/// for filters holding billions of items.
+///
+/// # Panics
+/// Panics if `false_pos_rate` is not strictly between 0 and 1
+#[cfg(feature = "std")]
+pub fn needed_bits_u64(false_pos_rate: f64, num_items: u64) -> usize {
+    check_rate(false_pos_rate);
+    let ln22 = core::f64::consts::LN_2 * core::f64::consts::LN_2;
+    (num_items as f64 * ((1.0/false_pos_rate).ln() / ln22)).round() as usize
+}
+
+#[cfg(test)]
+extern crate rand;
+
+#[cfg(feature = "do-bench")]
+#[cfg(test)]
+mod bench {
+    extern crate test;
+    use self::test::Bencher;
+    use bloom::rand::{self,Rng};
+
+    use super::BloomFilter;
+    use ASMS;
+
+    #[bench]
+    fn insert_benchmark(b: &mut Bencher) {
+        let cnt = 500000;
+        let rate = 0.01 as f32;
+
+        let mut bf:BloomFilter = BloomFilter::with_rate(rate,cnt);
+        let mut rng = rand::thread_rng();
+
+        b.iter(|| {
+            let v = rng.gen::<i32>();
+            bf.insert(&v);
+        })
+    }
+
+    #[bench]
+    fn contains_benchmark(b: &mut Bencher) {
+        let cnt = 500000;
+        let rate = 0.01 as f32;
+
+        let mut bf:BloomFilter = BloomFilter::with_rate(rate,cnt);
+        let mut rng = rand::thread_rng();
+
+        let mut i = 0;
+        while i < cnt {
+            let v = rng.gen::<i32>();
+            bf.insert(&v);
+            i+=1;
+        }
+
+        b.iter(|| {
+            let v = rng.gen::<i32>();
+            bf.contains(&v);
+        })
+    }
+
+    // 256 byte keys, where the cost of hashing dominates
+    fn long_keys() -> Vec<Vec<u8>> {
+        let mut rng = rand::thread_rng();
+        (0..1000).map(|_| (0..256).map(|_| rng.gen::<u8>()).collect()).collect()
+    }
+
+    #[bench]
+    fn insert_long_key_benchmark(b: &mut Bencher) {
+        let keys = long_keys();
+        let mut bf:BloomFilter = BloomFilter::with_rate(0.01,500000);
+        let mut i = 0;
+        b.iter(|| {
+            bf.insert(&keys[i % keys.len()]);
+            i+=1;
+        })
+    }
+
+    #[bench]
+    fn insert_long_key_single_hash_benchmark(b: &mut Bencher) {
+        let keys = long_keys();
+        let mut bf:BloomFilter = BloomFilter::with_rate_single_hash(0.01,500000);
+        let mut i = 0;
+        b.iter(|| {
+            bf.insert(&keys[i % keys.len()]);
+            i+=1;
+        })
+    }
+
+    fn contains_filled(b: &mut Bencher, mut bf: BloomFilter) {
+        let mut rng = rand::thread_rng();
+        for _ in 0..500000 {
+            bf.insert(&rng.gen::<i32>());
+        }
+        b.iter(|| {
+            let v = rng.gen::<i32>();
+            bf.contains(&v);
+        })
+    }
+
+    #[bench]
+    fn contains_modulo_benchmark(b: &mut Bencher) {
+        // the same number of bits as with_rate_pow2 uses, plus one so
+        // the modulo is needed
+        let bits = BloomFilter::with_rate_pow2(0.01,500000).num_bits() + 1;
+        contains_filled(b,BloomFilter::with_size(bits,7));
+    }
+
+    #[bench]
+    fn contains_pow2_benchmark(b: &mut Bencher) {
+        let bits = BloomFilter::with_rate_pow2(0.01,500000).num_bits();
+        contains_filled(b,BloomFilter::with_size(bits,7));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::{HashMap,HashSet};
+    use std::collections::hash_map::RandomState;
+    use std::env;
+    use std::fs::{self,File};
+    use std::hash::{BuildHasher,Hasher};
+    use std::io::{Cursor,ErrorKind,Write};
+    use std::mem;
+    use bit_vec::BitVec;
+    use bloom::rand::{self,Rng};
+    use super::{BloomFilter,DefaultBloomFilter,ParseError,achievable_rate,jaccard_similarity,needed_bits,needed_bits_u64,
+                optimal_num_hashes,optimal_num_hashes_u64,optimal_num_hashes_capped,
+                optimal_num_hashes_bounded,union_cardinality};
+    use hashers::{FnvBuildHasher,SipBuildHasher};
+    use hashing::HashIter;
+    use {ASMS,Intersectable,Unionable};
+
+    #[test]
+    fn simple() {
+        let mut b:BloomFilter = BloomFilter::with_rate(0.01,100);
+        b.insert(&1);
+        assert!(b.contains(&1));
+        assert!(!b.contains(&2));
+        b.clear();
+        assert!(!b.contains(&1));
+    }
+
+    #[test]
+    fn count_ones() {
+        let mut b:BloomFilter = BloomFilter::with_size(1000,3);
+        assert!(b.is_empty());
+        assert_eq!(b.count_ones(),0);
+
+        b.insert(&1);
+        assert!(!b.is_empty());
+        let ones = b.count_ones();
+        assert!((1..=3).contains(&ones));
+        b.insert(&1);
+        assert_eq!(b.count_ones(),ones);
+        for i in 2..20 {
+            b.insert(&i);
+        }
+        assert!(b.count_ones() > ones);
+        assert_eq!(b.count_ones(), b.as_slice().iter().map(|w| w.count_ones() as usize).sum::<usize>());
+
+        b.clear();
+        assert!(b.is_empty());
+        assert_eq!(b.count_ones(),0);
+    }
+
+    #[test]
+    fn memory_bytes() {
+        let b:BloomFilter = BloomFilter::with_size(8000,3);
+        assert_eq!(b.memory_bytes(), mem::size_of::<BloomFilter>() + 8000 / 8);
+        // rounded up to a whole number of u32 words
+        let b:BloomFilter = BloomFilter::with_size(8001,3);
+        assert_eq!(b.memory_bytes(), mem::size_of::<BloomFilter>() + 1004);
+    }
+
+    #[test]
+    fn current_fpp() {
+        let rate = 0.01;
+        let mut b:BloomFilter = BloomFilter::with_rate(rate,1000);
+        assert_eq!(b.current_fpp(),0.0);
+
+        for i in 0..1000 {
+            b.insert(&i);
+        }
+        let at_capacity = b.current_fpp();
+        assert!(at_capacity > rate as f64 / 2.0);
+        assert!(at_capacity < rate as f64 * 2.0);
+
+        for i in 1000..5000 {
+            b.insert(&i);
+        }
+        assert!(b.current_fpp() > rate as f64 * 20.0);
+    }
+
+    #[test]
+    fn estimate_count() {
+        let mut b:BloomFilter = BloomFilter::with_rate(0.01,2000);
+        assert_eq!(b.estimate_count(),0);
+
+        for i in 0..1000 {
+            b.insert(&i);
+            b.insert(&i);
+        }
+        let estimate = b.estimate_count();
+        assert!(estimate > 950 && estimate < 1050);
+
+        let mut full:BloomFilter = BloomFilter::with_size(10,2);
+        for i in 0..1000 {
+            full.insert(&i);
+        }
+        assert_eq!(full.count_ones(),10);
+        // 5 * ln(10)
+        assert_eq!(full.estimate_count(),12);
+    }
+
+    #[test]
+    fn extend() {
+        let items = vec![1,2,3,4,5];
+        let b = BloomFilter::from_rate_iter(0.01,items.clone());
+        assert_eq!(b.num_bits(),needed_bits(0.01,5));
+        for i in &items {
+            assert!(b.contains(i));
+        }
+
+        let mut b2 = BloomFilter::with_rate_and_seed(0.01,100,1);
+        b2.extend(items.iter().filter(|&&i| i % 2 == 0));
+        assert!(b2.contains(&2));
+        assert!(b2.contains(&4));
+        assert!(!b2.contains(&3));
+
+        let empty = BloomFilter::from_rate_iter(0.01,Vec::<u32>::new());
+        assert!(empty.is_empty());
+    }
+
+    #[test]
+    #[cfg(target_pointer_width = "64")]
+    #[should_panic(expected = "can't size a BloomFilter")]
+    fn from_rate_iter_too_long() {
+        BloomFilter::from_rate_iter(0.01,0..u32::MAX as usize + 1);
+    }
+
+    #[test]
+    fn debug() {
+        let mut b:BloomFilter = BloomFilter::with_size(100,3);
+        b.insert(&1);
+        let s = format!("{:?}", b);
+        assert!(s.starts_with("BloomFilter { num_bits: 100, num_hashes: 3, set_bits: "));
+    }
+
+    #[test]
+    fn eq() {
+        let h1 = RandomState::new();
+        let h2 = RandomState::new();
+        let mut b1 = BloomFilter::with_size_and_hashers(1000,4,h1.clone(),h2.clone());
+        let mut b2 = BloomFilter::with_size_and_hashers(1000,4,h1.clone(),h2.clone());
+        assert!(b1 == b2);
+        for i in 0..20 {
+            b1.insert(&i);
+            b2.insert(&i);
+        }
+        assert!(b1 == b2);
+        b2.insert(&100);
+        assert!(b1 != b2);
+
+        assert!(BloomFilter::with_size_and_hashers(1000,4,h1.clone(),h2.clone()) !=
+                BloomFilter::with_size_and_hashers(1000,5,h1.clone(),h2.clone()));
+        assert!(BloomFilter::with_size_and_hashers(1000,4,h1.clone(),h2.clone()) !=
+                BloomFilter::with_size_and_hashers(1001,4,h1,h2));
+    }
+
+    #[test]
+    fn single_hash() {
+        let mut b:BloomFilter = BloomFilter::with_rate_single_hash(0.01,1000);
+        assert!(b.is_single_hash());
+        assert!(!BloomFilter::with_rate(0.01,1000).is_single_hash());
+        for i in 0..1000 {
+            b.insert(&format!("key {}", i));
+        }
+        for i in 0..1000 {
+            assert!(b.contains(&format!("key {}", i)));
+        }
+        let false_positives = (1000..11000).filter(|i| b.contains(&format!("key {}", i))).count();
+        assert!(false_positives < 200);
+    }
+
+    #[test]
+    #[should_panic(expected = "hash items differently")]
+    fn single_hash_mismatch() {
+        let mut b1:BloomFilter = BloomFilter::with_rate_single_hash(0.01,1000);
+        let b2:BloomFilter = BloomFilter::with_size(b1.num_bits(),b1.num_hashes());
+        b1.union(&b2);
+    }
+
+    #[test]
+    fn insert_hash() {
+        let mut b:BloomFilter = BloomFilter::with_rate(0.01,100);
+        assert!(b.insert_hash(0x1234,0xfedcba));
+        assert!(!b.insert_hash(0x1234,0xfedcba));
+        assert!(b.contains_hash(0x1234,0xfedcba));
+        assert!(!b.contains_hash(0x4321,0xabcdef));
+    }
+
+    #[test]
+    fn insert_hash_matches_insert() {
+        let (one, two) = SipBuildHasher::pair_from_seed(5);
+        let mut b = BloomFilter::with_rate_and_hashers(0.01,100,one,two);
+        b.insert(&"bloom");
+        let h1 = one.hash_one("bloom");
+        let h2 = two.hash_one("bloom");
+        assert!(b.contains_hash(h1,h2));
+    }
+
+    #[test]
+    fn pow2() {
+        let b:BloomFilter = BloomFilter::with_rate_pow2(0.01,1000);
+        assert!(b.num_bits().is_power_of_two());
+        assert!(b.num_bits() >= needed_bits(0.01,1000));
+        assert!(b.num_bits() < 2 * needed_bits(0.01,1000));
+    }
+
+    #[test]
+    fn pow2_indices() {
+        // masking must pick the same bits as taking the hash modulo
+        // the number of bits
+        let (one, two) = SipBuildHasher::pair_from_seed(9);
+        let mut b = BloomFilter::with_size_and_hashers(1024,5,one,two);
+        for i in 0..50 {
+            b.insert(&i);
+        }
+        let mut expected = [0u32; 1024 / 32];
+        for i in 0..50 {
+            for h in HashIter::from(i,5,&one,&two) {
+                let idx = (h % 1024) as usize;
+                expected[idx / 32] |= 1 << (idx % 32);
+            }
+        }
+        assert_eq!(b.as_slice(), &expected[..]);
+        for i in 0..50 {
+            assert!(b.contains(&i));
+        }
+    }
+
+    #[test]
+    fn num_inserts() {
+        let mut b:BloomFilter = BloomFilter::with_rate(0.01,100);
+        assert_eq!(b.num_inserts(),0);
+        for i in 0..10 {
+            b.insert(&i);
+        }
+        for i in 0..5 {
+            b.insert(&i);
+        }
+        b.insert_hash(1,2);
+        b.extend(vec![20,21]);
+        assert_eq!(b.num_inserts(),18);
+        b.clear();
+        assert_eq!(b.num_inserts(),0);
+    }
+
+    #[test]
+    fn fold() {
+        let (h1, h2) = SipBuildHasher::pair_from_seed(3);
+        let mut b = BloomFilter::with_size_and_hashers(15000,10,h1,h2);
+        b.insert_all(0..1000);
+        let half = b.fold(2);
+        assert_eq!(half.num_bits(),7500);
+        assert_eq!(half.num_hashes(),b.num_hashes());
+        for i in 0..1000 {
+            assert!(half.contains(&i));
+        }
+        assert!(half.count_ones() <= b.count_ones());
+
+        let mut p = BloomFilter::with_rate_pow2(0.01,100);
+        p.insert_all(0..100);
+        let quarter = p.fold(4);
+        for i in 0..100 {
+            assert!(quarter.contains(&i));
+        }
+    }
+
+    #[test]
+    #[should_panic(expected = "can only fold")]
+    fn fold_bad_factor() {
+        let b:BloomFilter = BloomFilter::with_size(1000,3);
+        b.fold(3);
+    }
+
+    #[test]
+    fn single_hasher() {
+        let mut b = BloomFilter::with_rate_and_hasher(0.01,1000,FnvBuildHasher);
+        assert_eq!(b.num_bits(),needed_bits(0.01,1000));
+        for i in 0..1000 {
+            b.insert(&i);
+        }
+        for i in 0..1000 {
+            assert!(b.contains(&i));
+        }
+        let false_positives = (1000..11000).filter(|i| b.contains(i)).count();
+        assert!(false_positives < 200);
+    }
+
+    #[test]
+    fn configured() {
+        let b:BloomFilter = BloomFilter::with_rate(0.01,100);
+        assert_eq!(b.configured_rate(),Some(0.01));
+        assert_eq!(b.expected_items(),Some(100));
+        let b = BloomFilter::with_rate_and_seed(0.05,1000,1);
+        assert_eq!(b.configured_rate(),Some(0.05));
+        assert_eq!(b.expected_items(),Some(1000));
+        assert_eq!(BloomFilter::with_rate_pow2(0.01,100).expected_items(),Some(100));
+
+        let b:BloomFilter = BloomFilter::with_size(1000,3);
+        assert_eq!(b.configured_rate(),None);
+        assert_eq!(b.expected_items(),None);
+        let b = BloomFilter::from_raw(vec![0;32],1000,3);
+        assert_eq!(b.configured_rate(),None);
+    }
+
+    #[test]
+    fn batch() {
+        let mut b = BloomFilter::with_rate_and_seed(0.01,100,2);
+        b.insert_all(vec![1,2,3]);
+        b.insert_all(10..20);
+        assert!(b.contains(&2));
+        assert!(b.contains(&15));
+        assert_eq!(b.num_inserts(),13);
+
+        assert!(b.contains_all(&[1,2,3,12]));
+        assert!(!b.contains_all(&[1,2,100]));
+        assert!(b.contains_any(&[100,101,3]));
+        assert!(!b.contains_any(&[100,101,102]));
+
+        let empty: [i32; 0] = [];
+        assert!(b.contains_all(&empty));
+        assert!(!b.contains_any(&empty));
+    }
+
+    #[test]
+    fn count_present() {
+        let mut b = BloomFilter::with_rate_and_seed(0.01,100,2);
+        b.insert_all(0..10);
+        assert_eq!(b.count_present(&[0,100,5,101,9,102]),3);
+        assert_eq!(b.count_present(&(0..10).collect::<Vec<_>>()),10);
+        let empty: [i32; 0] = [];
+        assert_eq!(b.count_present(&empty),0);
+    }
+
+    #[test]
+    fn hash_indices() {
+        let mut b:BloomFilter = BloomFilter::with_rate(0.01,100);
+        let indices = b.hash_indices(&"bloom");
+        assert_eq!(indices.len(),b.num_hashes() as usize);
+        assert_eq!(indices,b.hash_indices(&"bloom"));
+        assert!(b.is_empty());
+        b.insert(&"bloom");
+        let mut set = indices.clone();
+        set.sort();
+        set.dedup();
+        assert_eq!(b.count_ones(),set.len());
+    }
+
+    #[test]
+    fn sizing_u64() {
+        // -n * ln(0.01) / ln(2)^2 for n = 10 billion
+        let bits = needed_bits_u64(0.01,10_000_000_000);
+        assert_eq!(bits,95850583774);
+        assert_eq!(optimal_num_hashes_u64(bits,10_000_000_000),7);
+        // small filters are sized the same as with the u32 functions
+        assert_eq!(needed_bits_u64(0.01,500000),needed_bits(0.01,500000));
+        assert_eq!(optimal_num_hashes_u64(4792529,500000),
+                   optimal_num_hashes(4792529,500000));
+    }
+
+    #[test]
+    fn needed_bits_f64() {
+        // -n * ln(rate) / ln(2)^2 computed in f64, where rate is
+        // 0.001f32 as an f64.  Doing the math in f32 gives 1437758720
+        assert_eq!(needed_bits(0.001,100_000_000),1437758747);
+        assert_eq!(optimal_num_hashes(1437758747,100_000_000),10);
+    }
+
+    #[test]
+    #[should_panic(expected = "false positive rate must be between 0 and 1")]
+    fn rate_zero() {
+        BloomFilter::with_rate(0.0,100);
+    }
+
+    #[test]
+    #[should_panic(expected = "false positive rate must be between 0 and 1")]
+    fn rate_one() {
+        BloomFilter::with_rate(1.0,100);
+    }
+
+    #[test]
+    #[should_panic(expected = "false positive rate must be between 0 and 1")]
+    fn rate_negative() {
+        BloomFilter::with_rate(-0.01,100);
+    }
+
+    #[test]
+    #[should_panic(expected = "false positive rate must be between 0 and 1")]
+    fn rate_nan() {
+        BloomFilter::with_rate(f32::NAN,100);
+    }
+
+    #[test]
+    fn num_hashes_floor() {
+        // 0.1 bits per item rounds to 0 hashes
+        assert_eq!(optimal_num_hashes(10,100),2);
+        assert_eq!(optimal_num_hashes_capped(10,100,50),2);
+        assert_eq!(optimal_num_hashes_bounded(10,100,1,200),1);
+        // 4 bits per item gives 3, which is above both floors
+        assert_eq!(optimal_num_hashes_bounded(400,100,1,200),3);
+    }
+
+    #[test]
+    fn num_hashes_ceiling() {
+        // 10000 bits per item would want 6931 hashes
+        assert_eq!(optimal_num_hashes(100000,10),200);
+        assert_eq!(optimal_num_hashes_capped(100000,10,50),50);
+        assert_eq!(optimal_num_hashes_capped(100000,10,10000),6931);
+        assert_eq!(optimal_num_hashes_bounded(100000,10,2,6931),6931);
+    }
+
+    #[test]
+    #[should_panic(expected = "at least one hash")]
+    fn num_hashes_zero_min() {
+        optimal_num_hashes_bounded(100,10,0,10);
+    }
+
+    #[test]
+    #[should_panic(expected = "more than max_hashes")]
+    fn num_hashes_min_over_max() {
+        optimal_num_hashes_capped(100,10,1);
+    }
+
+    #[test]
+    fn with_rate_u64() {
+        let mut b = BloomFilter::with_rate_u64(0.01,1000);
+        assert_eq!(b.num_bits(),needed_bits_u64(0.01,1000));
+        b.insert(&1);
+        assert!(b.contains(&1));
+    }
+
+    #[test]
+    fn seeded() {
+        let mut b1 = BloomFilter::with_rate_and_seed(0.01,100,7);
+        let mut b2 = BloomFilter::with_rate_and_seed(0.01,100,7);
+        let mut b3 = BloomFilter::with_rate_and_seed(0.01,100,8);
+        for i in 0..50 {
+            b1.insert(&i);
+            b2.insert(&i);
+            b3.insert(&i);
+        }
+        assert_eq!(b1.as_slice(), b2.as_slice());
+        assert!(b1.as_slice() != b3.as_slice());
+    }
+
+    #[test]
+    fn intersect() {
+        let h1 = RandomState::new();
+        let h2 = RandomState::new();
+        let mut b1 = BloomFilter::with_rate_and_hashers(0.01,20,h1.clone(),h2.clone());
+        b1.insert(&1);
+        b1.insert(&2);
+        let mut b2 = BloomFilter::with_rate_and_hashers(0.01,20,h1,h2);
+        b2.insert(&1);
+
+        b1.intersect(&b2);
+
+        assert!(b1.contains(&1));
+        assert!(!b1.contains(&2));
+    }
+
+    #[test]
+    fn bit_vec_round_trip() {
+        let mut bits = BitVec::from_elem(1000,false);
+        bits.set(3,true);
+        bits.set(999,true);
+        let original = bits.clone();
+        let (h1, h2) = SipBuildHasher::pair_from_seed(35);
+        let b = BloomFilter::from_bit_vec_with_hashers(bits,4,h1,h2);
+        assert_eq!(b.num_bits(),1000);
+        assert_eq!(b.num_hashes(),4);
+        assert_eq!(b.count_ones(),2);
+        assert_eq!(b.into_bit_vec(),original);
+
+        let mut b = BloomFilter::from_bit_vec(original.clone(),4);
+        b.insert(&7);
+        assert!(b.contains(&7));
+        let bits = b.into_bit_vec();
+        assert!(bits[3] && bits[999]);
+        assert!(bits.iter().filter(|&bit| bit).count() > 2);
+    }
+
+    #[test]
+    #[should_panic]
+    fn empty_bit_vec() {
+        BloomFilter::from_bit_vec(BitVec::new(),4);
+    }
+
+    #[test]
+    fn with_rate_and_hashes() {
+        let mut b = BloomFilter::with_rate_and_hashes(0.01,1000,3);
+        assert_eq!(b.num_hashes(),3);
+        assert_eq!(b.num_bits(),BloomFilter::with_rate(0.01,1000).num_bits());
+        assert_eq!(b.configured_rate(),Some(0.01));
+        b.insert(&1);
+        assert!(b.contains(&1));
+        assert!(!b.contains(&2));
+    }
+
+    #[test]
+    fn union_if_nonempty() {
+        let (h1, h2) = SipBuildHasher::pair_from_seed(33);
+        let mut b1 = BloomFilter::with_rate_and_hashers(0.01,20,h1,h2);
+        let mut b2 = BloomFilter::with_rate_and_hashers(0.01,20,h1,h2);
+        b1.insert(&1);
+        assert!(!b1.union_if_nonempty(&b2));
+        assert_eq!(b1.num_inserts(),1);
+        assert!(b1.contains(&1));
+
+        b2.insert(&2);
+        assert!(b1.union_if_nonempty(&b2));
+        assert!(b1.contains(&1));
+        assert!(b1.contains(&2));
+        assert_eq!(b1.num_inserts(),2);
+        assert!(!b1.union_if_nonempty(&b2));
+    }
+
+    #[test]
+    #[should_panic(expected = "different numbers of bits")]
+    fn union_if_nonempty_incompatible() {
+        let (h1, h2) = SipBuildHasher::pair_from_seed(33);
+        let mut b1 = BloomFilter::with_size_and_hashers(100,3,h1,h2);
+        let b2 = BloomFilter::with_size_and_hashers(101,3,h1,h2);
+        b1.union_if_nonempty(&b2);
+    }
+
+    #[test]
+    fn union() {
+        let h1 = RandomState::new();
+        let h2 = RandomState::new();
+        let mut b1 = BloomFilter::with_rate_and_hashers(0.01,20,h1.clone(),h2.clone());
+        b1.insert(&1);
+        let mut b2 = BloomFilter::with_rate_and_hashers(0.01,20,h1,h2);
+        b2.insert(&2);
+
+        b1.union(&b2);
+
+        assert!(b1.contains(&1));
+        assert!(b1.contains(&2));
+    }
+
+    #[test]
+    #[should_panic(expected = "different numbers of hashes")]
+    fn intersect_hashes_mismatch() {
+        let h1 = RandomState::new();
+        let h2 = RandomState::new();
+        let mut b1 = BloomFilter::with_size_and_hashers(100,3,h1.clone(),h2.clone());
+        let b2 = BloomFilter::with_size_and_hashers(100,4,h1,h2);
+        b1.intersect(&b2);
+    }
+
+    #[test]
+    #[should_panic(expected = "different numbers of hashes")]
+    fn union_hashes_mismatch() {
+        let h1 = RandomState::new();
+        let h2 = RandomState::new();
+        let mut b1 = BloomFilter::with_size_and_hashers(100,3,h1.clone(),h2.clone());
+        let b2 = BloomFilter::with_size_and_hashers(100,4,h1,h2);
+        b1.union(&b2);
+    }
+
+    #[test]
+    #[should_panic(expected = "different numbers of bits")]
+    fn union_bits_mismatch() {
+        let h1 = RandomState::new();
+        let h2 = RandomState::new();
+        let mut b1 = BloomFilter::with_size_and_hashers(100,3,h1.clone(),h2.clone());
+        let b2 = BloomFilter::with_size_and_hashers(101,3,h1,h2);
+        b1.union(&b2);
+    }
+
+    #[test]
+    fn with_rate_and_keys() {
+        let mut b1 = BloomFilter::with_rate_and_keys(0.01,100,3,4);
+        let mut b2 = BloomFilter::with_rate_and_keys(0.01,100,3,4);
+        let mut b3 = BloomFilter::with_rate_and_keys(0.01,100,4,3);
+        for i in 0..100 {
+            b1.insert(&i);
+            b2.insert(&i);
+            b3.insert(&i);
+        }
+        assert_eq!(b1,b2);
+        assert!(b1 != b3);
+        // only one key needs to be non-zero
+        BloomFilter::with_rate_and_keys(0.01,100,0,1);
+    }
+
+    #[test]
+    #[should_panic(expected = "needs a non-zero key")]
+    fn with_rate_and_zero_keys() {
+        BloomFilter::with_rate_and_keys(0.01,100,0,0);
+    }
+
+    #[test]
+    fn combine_custom_hashers() {
+        let mut b1 = BloomFilter::with_rate_and_seed(0.01,20,42);
+        b1.insert(&1);
+        b1.insert(&2);
+        let mut b2 = BloomFilter::with_rate_and_seed(0.01,20,42);
+        b2.insert(&2);
+        b2.insert(&3);
+        let mut b3 = BloomFilter::with_rate_and_seed(0.01,20,42);
+        b3.insert(&2);
+
+        assert!(b1.union(&b2));
+        assert!(b1.contains(&1));
+        assert!(b1.contains(&3));
+
+        assert!(b1.intersect(&b3));
+        assert!(b1.contains(&2));
+        assert!(!b1.contains(&1));
+        assert!(!b1.contains(&3));
+    }
+
+    #[test]
+    fn bytes_round_trip() {
+        let h1 = RandomState::new();
+        let h2 = RandomState::new();
+        let mut b = BloomFilter::with_rate_and_hashers(0.01,100,h1.clone(),h2.clone());
+        for i in 0..50 {
+            b.insert(&i);
+        }
+        let bytes = b.to_bytes();
+        assert_eq!(&bytes[0..4], b"BLMF");
+
+        let loaded = BloomFilter::from_bytes_with_hashers(&bytes,h1,h2).unwrap();
+        assert_eq!(loaded.num_bits(), b.num_bits());
+        assert_eq!(loaded.num_hashes(), b.num_hashes());
+        for i in 0..100 {
+            assert_eq!(loaded.contains(&i), b.contains(&i));
+        }
+        assert_eq!(loaded.to_bytes(), bytes);
+    }
+
+    #[test]
+    fn bytes_errors() {
+        let b:BloomFilter = BloomFilter::with_rate(0.01,100);
+        let bytes = b.to_bytes();
+
+        assert_eq!(BloomFilter::from_bytes(&bytes[..10]).err(), Some(ParseError::Truncated));
+        assert_eq!(BloomFilter::from_bytes(&bytes[..bytes.len()-1]).err(), Some(ParseError::Truncated));
+
+        let mut long = bytes.clone();
+        long.push(0);
+        assert_eq!(BloomFilter::from_bytes(&long).err(), Some(ParseError::TrailingBytes));
+
+        let mut bad_magic = bytes.clone();
+        bad_magic[0] = b'X';
+        assert_eq!(BloomFilter::from_bytes(&bad_magic).err(), Some(ParseError::BadMagic));
+
+        let mut zero_bits = bytes.clone();
+        zero_bits.truncate(24);
+        for b in &mut zero_bits[8..16] {
+            *b = 0;
+        }
+        assert_eq!(BloomFilter::from_bytes(&zero_bits).err(), Some(ParseError::ZeroBits));
+
+        let mut bad_version = bytes.clone();
+        bad_version[4] = 99;
+        assert_eq!(BloomFilter::from_bytes(&bad_version).err(), Some(ParseError::UnsupportedVersion(99)));
+
+        let mut bad_flags = bytes.clone();
+        bad_flags[20] = 2;
+        assert_eq!(BloomFilter::from_bytes(&bad_flags).err(), Some(ParseError::UnsupportedFlags(2)));
+    }
+
+    #[test]
+    fn checksum() {
+        // the standard check value for this CRC-32
+        assert_eq!(super::crc32(0,b"123456789"),0xcbf43926);
+        assert_eq!(super::crc32(super::crc32(0,b"1234"),b"56789"),0xcbf43926);
+
+        let (h1, h2) = SipBuildHasher::pair_from_seed(16);
+        let mut b = BloomFilter::with_rate_and_hashers(0.01,100,h1,h2);
+        b.insert_all(0..50);
+        let bytes = b.to_bytes();
+        assert_eq!(&bytes[4..8], &3u32.to_le_bytes());
+
+        let mut flipped = bytes.clone();
+        flipped[30] ^= 1;
+        assert_eq!(BloomFilter::from_bytes_with_hashers(&flipped,h1,h2).err(),
+                   Some(ParseError::ChecksumMismatch));
+        let err = BloomFilter::read_from_with_hashers(&flipped[..],h1,h2).err().unwrap();
+        assert_eq!(err.kind(),ErrorKind::InvalidData);
+
+        // version 2 data has no flags, and version 1 no checksum
+        // either, and both can still be read
+        let mut v2 = bytes[..20].to_vec();
+        v2.extend_from_slice(&bytes[24..bytes.len()-4]);
+        v2[4] = 2;
+        let crc = super::crc32(0,&v2);
+        v2.extend_from_slice(&crc.to_le_bytes());
+        let loaded = BloomFilter::from_bytes_with_hashers(&v2,h1,h2).unwrap();
+        assert_eq!(loaded,b);
+        let read = BloomFilter::read_from_with_hashers(&v2[..],h1,h2).unwrap();
+        assert_eq!(read,b);
+
+        let mut v1 = v2[..v2.len()-4].to_vec();
+        v1[4] = 1;
+        let loaded = BloomFilter::from_bytes_with_hashers(&v1,h1,h2).unwrap();
+        assert_eq!(loaded,b);
+        let read = BloomFilter::read_from_with_hashers(&v1[..],h1,h2).unwrap();
+        assert_eq!(read,b);
+        let mut v1_long = v2.clone();
+        v1_long[4] = 1;
+        assert_eq!(BloomFilter::from_bytes_with_hashers(&v1_long,h1,h2).err(),
+                   Some(ParseError::TrailingBytes));
+    }
+
+    #[test]
+    fn single_hash_round_trip() {
+        let (h1, h2) = SipBuildHasher::pair_from_seed(36);
+        let mut b = BloomFilter::with_rate_single_hash_and_hashers(0.01,1000,h1,h2);
+        b.insert_all(0..1000);
+
+        let loaded = BloomFilter::from_bytes_with_hashers(&b.to_bytes(),h1,h2).unwrap();
+        assert!(loaded.is_single_hash());
+        assert_eq!(loaded,b);
+        for i in 0..1000 {
+            assert!(loaded.contains(&i));
+        }
+
+        let mut out = Vec::new();
+        b.write_to(&mut out).unwrap();
+        let read = BloomFilter::read_from_with_hashers(&out[..],h1,h2).unwrap();
+        assert!(read.is_single_hash());
+        for i in 0..1000 {
+            assert!(read.contains(&i));
+        }
+    }
+
+    #[test]
+    fn guava_bytes() {
+        // Guava's writeTo output for a 128 bit filter using 3 hashes
+        // holding the UTF-8 bytes of FOX, which sets bits 51, 108
+        // and 122
+        let fixture = [1, 3, 0, 0, 0, 2,
+                       0x00, 0x08, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+                       0x04, 0x00, 0x10, 0x00, 0x00, 0x00, 0x00, 0x00];
+        const FOX: &[u8] = b"The quick brown fox jumps over the lazy dog";
+
+        let read = BloomFilter::from_guava_bytes(&fixture).unwrap();
+        assert_eq!(read.num_bits(),128);
+        assert_eq!(read.num_hashes(),3);
+        assert!(read.contains_guava(FOX));
+        assert!(!read.contains_guava(b"The quick brown fox"));
+        assert_eq!(read.to_guava_bytes(),&fixture[..]);
+
+        let (h1, h2) = SipBuildHasher::pair_from_seed(17);
+        let mut b = BloomFilter::with_size_and_hashers(128,3,h1,h2);
+        assert!(b.insert_guava(FOX));
+        assert!(!b.insert_guava(FOX));
+        assert_eq!(b.to_guava_bytes(),&fixture[..]);
+
+        let mut big = BloomFilter::with_size_and_hashers(6400,7,h1,h2);
+        for i in 0..500i32 {
+            big.insert_guava(&i.to_le_bytes());
+        }
+        let read = BloomFilter::from_guava_bytes_with_hashers(&big.to_guava_bytes(),h1,h2).unwrap();
+        assert_eq!(read,big);
+        for i in 0..500i32 {
+            assert!(read.contains_guava(&i.to_le_bytes()));
+        }
+
+        let mut strategy = fixture;
+        strategy[0] = 0;
+        assert_eq!(BloomFilter::from_guava_bytes(&strategy).err(), Some(ParseError::UnsupportedStrategy(0)));
+        assert_eq!(BloomFilter::from_guava_bytes(&fixture[..21]).err(), Some(ParseError::Truncated));
+        assert_eq!(BloomFilter::from_guava_bytes(&fixture[..4]).err(), Some(ParseError::Truncated));
+        let mut long = fixture.to_vec();
+        long.push(0);
+        assert_eq!(BloomFilter::from_guava_bytes(&long).err(), Some(ParseError::TrailingBytes));
+        assert_eq!(BloomFilter::from_guava_bytes(&[1,3,0,0,0,0]).err(), Some(ParseError::ZeroBits));
+    }
+
+    #[test]
+    #[should_panic(expected = "multiple of 64 bits")]
+    fn guava_bytes_odd_size() {
+        BloomFilter::with_size(100,3).to_guava_bytes();
+    }
+
+    #[test]
+    fn default_filter_round_trip() {
+        let mut b = DefaultBloomFilter::with_rate_fixed(0.01,1000);
+        b.insert_all(0..500);
+        let mut other = DefaultBloomFilter::with_rate_fixed(0.01,1000);
+        other.insert_all(0..500);
+        assert_eq!(b,other);
+
+        let reloaded = DefaultBloomFilter::from_bytes_fixed(&b.to_bytes()).unwrap();
+        assert_eq!(reloaded,b);
+        for i in 0..1000 {
+            assert_eq!(reloaded.contains(&i),b.contains(&i));
+        }
+
+        // the keys must never change, or serialized filters would
+        // stop finding their items
+        let mut small = DefaultBloomFilter::with_size_fixed(64,3);
+        small.insert(&"bloom");
+        assert_eq!(small.as_slice(),&[0x0004_0100,0x8000_0000]);
+
+        // integers are hashed as little endian bytes, and usize as 64
+        // bits, so these are the same on every platform too
+        let mut ints = DefaultBloomFilter::with_size_fixed(64,3);
+        ints.insert(&42u32);
+        ints.insert(&-7i64);
+        ints.insert(&1000usize);
+        assert_eq!(ints.as_slice(),&[0x4002_4204,0x0810_0200]);
+    }
+
+    #[test]
+    fn bit_budget() {
+        // 1 MiB for a million items
+        let b = BloomFilter::with_bit_budget(8 << 20,1000000);
+        assert_eq!(b.num_bits(),8 << 20);
+        assert_eq!(b.num_hashes(),optimal_num_hashes(8 << 20,1000000));
+        assert_eq!(b.num_hashes(),6);
+        let k = 6.0f64;
+        let expected = (1.0 - (-k * 1000000.0 / (8 << 20) as f64).exp()).powf(k);
+        assert_eq!(achievable_rate(8 << 20,1000000),expected);
+        assert!((0.015..0.02).contains(&expected));
+
+        // the inverse of needed_bits, up to rounding the hashes
+        for &rate in &[0.1f32, 0.01, 0.001] {
+            let achieved = achievable_rate(needed_bits(rate,10000),10000);
+            assert!((achieved / rate as f64 - 1.0).abs() < 0.05, "{} vs {}", achieved, rate);
+        }
+        assert_eq!(achievable_rate(1000,0),0.0);
+        // more bits can only help
+        assert!(achievable_rate(20000,1000) < achievable_rate(10000,1000));
+    }
+
+    #[test]
+    fn default() {
+        let mut b = BloomFilter::default();
+        assert!(b.insert(&1));
+        assert!(b.contains(&1));
+        assert_eq!(b.configured_rate(),Some(0.01));
+        assert_eq!(b.expected_items(),Some(1000));
+        assert_eq!(b.num_bits(),needed_bits(0.01,1000));
+    }
+
+    #[test]
+    fn reset_to() {
+        let (h1, h2) = SipBuildHasher::pair_from_seed(25);
+        let mut b = BloomFilter::with_rate_and_hashers(0.01,100,h1,h2);
+        b.insert_all(0..100);
+        b.reset_to(0.001,10000);
+        assert_eq!(b.num_bits(),needed_bits(0.001,10000));
+        assert_eq!(b.num_hashes(),optimal_num_hashes(b.num_bits(),10000));
+        assert!(b.is_empty());
+        assert_eq!(b.num_inserts(),0);
+        assert_eq!(b.configured_rate(),Some(0.001));
+        assert_eq!(b.expected_items(),Some(10000));
+        b.insert_all(0..10000);
+        assert!(b.contains_all(&(0..10000).collect::<Vec<_>>()));
+        let mut fresh = BloomFilter::with_rate_and_hashers(0.001,10000,h1,h2);
+        fresh.insert_all(0..10000);
+        assert_eq!(b,fresh);
+    }
+
+    #[test]
+    #[should_panic(expected = "at least one bit")]
+    fn reset_to_zero_bits() {
+        BloomFilter::with_rate(0.01,100).reset_to(0.01,0);
+    }
+
+    #[test]
+    fn check_and_set() {
+        let (h1, h2) = SipBuildHasher::pair_from_seed(26);
+        let mut b = BloomFilter::with_rate_and_hashers(0.01,100,h1,h2);
+        assert!(!b.check_and_set(&1));
+        assert!(b.check_and_set(&1));
+        assert!(b.contains(&1));
+        assert!(!b.check_and_set(&2));
+        assert_eq!(b.num_inserts(),3);
+    }
+
+    #[test]
+    fn contains_with_k() {
+        let (h1, h2) = SipBuildHasher::pair_from_seed(28);
+        let mut b = BloomFilter::with_rate_and_hashers(0.01,1000,h1,h2);
+        b.insert_all(0..1000);
+        let k = b.num_hashes();
+        for i in 0..5000 {
+            assert_eq!(b.contains_with_k(&i,k),b.contains(&i));
+            // fewer bits can only let more items through
+            if b.contains(&i) {
+                assert!(b.contains_with_k(&i,1));
+            }
+        }
+        let full = (1000..11000).filter(|i| b.contains(i)).count();
+        let one = (1000..11000).filter(|i| b.contains_with_k(i,1)).count();
+        assert!(one > full * 5);
+        assert!(b.contains_with_k(&20000,0));
+    }
+
+    #[test]
+    #[should_panic(expected = "can't check")]
+    fn contains_with_too_many_k() {
+        let b = BloomFilter::with_size(1000,3);
+        b.contains_with_k(&1,4);
+    }
+
+    #[test]
+    fn insert_bytes() {
+        let sip = SipBuildHasher::with_keys(5,6);
+        let mut b = BloomFilter::with_rate_and_hashers(0.01,100,FnvBuildHasher,sip);
+        assert!(b.insert_bytes(b"foobar"));
+        assert!(!b.insert_bytes(b"foobar"));
+        assert!(b.contains_bytes(b"foobar"));
+        assert!(!b.contains_bytes(b"foo"));
+        // hashing the slice would also hash its length
+        assert!(!b.contains(&&b"foobar"[..]));
+
+        // the FNV-1a reference value for "foobar", and SipHash of the
+        // bare bytes
+        let mut h = sip.build_hasher();
+        h.write(b"foobar");
+        let mut reference = BloomFilter::with_rate_and_hashers(0.01,100,FnvBuildHasher,sip);
+        reference.insert_hash(0x85944171f73967e8,h.finish());
+        assert_eq!(reference,b);
+    }
+
+    #[test]
+    fn union_all() {
+        let (h1, h2) = SipBuildHasher::pair_from_seed(31);
+        let mut filters = Vec::new();
+        for i in 0..3 {
+            let mut b = BloomFilter::with_rate_and_hashers(0.01,300,h1,h2);
+            b.insert_all(i*100..(i+1)*100);
+            filters.push(b);
+        }
+        let union = super::union_all(&filters).unwrap();
+        for i in 0..300 {
+            assert!(union.contains(&i));
+        }
+        assert_eq!(union.num_inserts(),300);
+        // the inputs are untouched
+        assert!(!filters[0].contains_all(&(100..300).collect::<Vec<_>>()));
+
+        let none: Vec<BloomFilter<SipBuildHasher,SipBuildHasher>> = Vec::new();
+        assert!(super::union_all(&none).is_none());
+    }
+
+    #[test]
+    #[should_panic]
+    fn union_all_incompatible() {
+        let (h1, h2) = SipBuildHasher::pair_from_seed(31);
+        let a = BloomFilter::with_size_and_hashers(1000,4,h1,h2);
+        let b = BloomFilter::with_size_and_hashers(1000,5,h1,h2);
+        super::union_all(vec![&a,&b]);
+    }
+
+    #[test]
+    fn index_histogram() {
+        let (h1, h2) = SipBuildHasher::pair_from_seed(32);
+        let mut b = BloomFilter::with_size_and_hashers(64,3,h1,h2);
+        let items = [1,2,3,4,5];
+        let histogram = b.index_histogram(&items);
+        assert_eq!(histogram.len(),64);
+        assert_eq!(histogram.iter().sum::<u32>(),5*3);
+        // nothing was inserted
+        assert!(b.is_empty());
+
+        // the indices hit are the bits insert sets
+        b.insert_all(items.iter());
+        for (i,&hits) in histogram.iter().enumerate() {
+            assert_eq!(hits > 0,b.bits[i]);
+        }
+    }
+
+    #[test]
+    fn take() {
+        let (h1, h2) = SipBuildHasher::pair_from_seed(23);
+        let mut b = BloomFilter::with_rate_and_hashers(0.01,100,h1,h2);
+        b.insert_all(0..50);
+        let old = b.take();
+        assert!(old.contains_all(&(0..50).collect::<Vec<_>>()));
+        assert_eq!(old.num_inserts(),50);
+        assert_eq!(b.count_ones(),0);
+        assert_eq!(b.num_inserts(),0);
+        assert_eq!(b.num_bits(),old.num_bits());
+        assert_eq!(b.num_hashes(),old.num_hashes());
+        assert!(b.is_compatible_with(&old));
+        b.insert(&7);
+        assert!(b.contains(&7));
+    }
+
+    #[test]
+    fn hash_map_key() {
+        let (h1, h2) = SipBuildHasher::pair_from_seed(18);
+        let mut b = BloomFilter::with_rate_and_hashers(0.01,100,h1,h2);
+        b.insert_all(0..50);
+        let mut map = HashMap::new();
+        map.insert(b,"first");
+
+        // the same contents, built separately
+        let mut copy = BloomFilter::with_rate_and_hashers(0.01,100,h1,h2);
+        copy.insert_all(0..50);
+        assert_eq!(map.get(&copy),Some(&"first"));
+
+        copy.insert(&100);
+        assert_eq!(map.get(&copy),None);
+        map.insert(copy,"second");
+        assert_eq!(map.len(),2);
+    }
+
+    #[test]
+    fn stream_round_trip() {
+        let (h1, h2) = SipBuildHasher::pair_from_seed(15);
+        // big enough to need several chunks
+        let mut b = BloomFilter::with_rate_and_hashers(0.01,10000,h1,h2);
+        b.insert_all(0..10000);
+        let mut out = Cursor::new(Vec::new());
+        b.write_to(&mut out).unwrap();
+        assert_eq!(out.get_ref(),&b.to_bytes());
+
+        // a second filter after the first is left in the reader
+        let small = BloomFilter::with_rate_and_hashers(0.01,10,h1,h2);
+        small.write_to(&mut out).unwrap();
+        let mut input = Cursor::new(out.into_inner());
+        let read = BloomFilter::read_from_with_hashers(&mut input,h1,h2).unwrap();
+        assert_eq!(read,b);
+        for i in 0..10000 {
+            assert!(read.contains(&i));
+        }
+        let read_small = BloomFilter::read_from(&mut input).unwrap();
+        assert_eq!(read_small.num_bits(),small.num_bits());
+
+        let bytes = b.to_bytes();
+        let err = BloomFilter::read_from(&bytes[..bytes.len()-1]).err().unwrap();
+        assert_eq!(err.kind(),ErrorKind::InvalidData);
+        let err = BloomFilter::read_from(&bytes[..10]).err().unwrap();
+        assert_eq!(err.kind(),ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn stream_huge_header() {
+        let b = BloomFilter::with_size(100,3);
+        let mut bytes = b.to_bytes();
+        // claim far more bits than could ever be allocated
+        bytes[8..16].copy_from_slice(&(1u64 << 62).to_le_bytes());
+        let err = BloomFilter::read_from(Cursor::new(&bytes[..])).err().unwrap();
+        assert_eq!(err.kind(),ErrorKind::InvalidData);
+
+        bytes[8..16].copy_from_slice(&u64::MAX.to_le_bytes());
+        let err = BloomFilter::read_from(Cursor::new(&bytes[..])).err().unwrap();
+        assert_eq!(err.kind(),ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn raw_round_trip() {
+        let h1 = RandomState::new();
+        let h2 = RandomState::new();
+        let mut b = BloomFilter::with_rate_and_hashers(0.01,100,h1.clone(),h2.clone());
+        for i in 0..50 {
+            b.insert(&i);
+        }
+        let words = b.as_slice().to_vec();
+        assert_eq!(words.len(), b.num_bits().div_ceil(32));
+
+        let raw = BloomFilter::from_raw_with_hashers(words,b.num_bits(),b.num_hashes(),h1,h2);
+        assert_eq!(raw.as_slice(), b.as_slice());
+        for i in 0..100 {
+            assert_eq!(raw.contains(&i), b.contains(&i));
+        }
+    }
+
+    #[test]
+    #[should_panic(expected = "at least one bit")]
+    fn zero_bits() {
+        BloomFilter::with_size(0,3);
+    }
+
+    #[test]
+    #[should_panic(expected = "at least one bit")]
+    fn zero_bits_with_hashers() {
+        BloomFilter::with_size_and_hashers(0,3,RandomState::new(),RandomState::new());
+    }
+
+    #[test]
+    #[should_panic(expected = "at least one bit")]
+    fn zero_bits_raw() {
+        BloomFilter::from_raw(vec![],0,3);
+    }
+
+    #[test]
+    #[should_panic]
+    fn raw_too_short() {
+        BloomFilter::from_raw(vec![0,0],65,3);
+    }
+
+    #[test]
+    fn raw_extra_bits() {
+        // bits past num_bits, and words past the last, are dropped
+        let b = BloomFilter::from_raw(vec![!0,!0,!0],40,3);
+        assert_eq!(b.count_ones(),40);
+        assert_eq!(b.as_slice(),&[!0,0xff]);
+    }
+
+    #[test]
+    fn save_load_path() {
+        let path = env::temp_dir().join(format!("bloom-save-load-{}.bin", rand::random::<u64>()));
+        let h1 = RandomState::new();
+        let h2 = RandomState::new();
+        let mut b = BloomFilter::with_rate_and_hashers(0.01,1000,h1.clone(),h2.clone());
+        for i in 0..500 {
+            b.insert(&i);
+        }
+        b.save_to_path(&path).unwrap();
+        let loaded = BloomFilter::load_from_path_with_hashers(&path,h1,h2);
+
+        // a file whose header claims far more bits than it holds
+        let mut header = b.to_bytes();
+        header.truncate(8);
+        header.extend_from_slice(&(1u64 << 40).to_le_bytes());
+        header.extend_from_slice(&7u32.to_le_bytes());
+        File::create(&path).unwrap().write_all(&header).unwrap();
+        let corrupt = BloomFilter::load_from_path(&path);
+        fs::remove_file(&path).unwrap();
+
+        let loaded = loaded.unwrap();
+        for i in 0..1000 {
+            assert_eq!(loaded.contains(&i), b.contains(&i));
+        }
+        assert_eq!(corrupt.err().unwrap().kind(), ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn union_new() {
+        let h1 = RandomState::new();
+        let h2 = RandomState::new();
+        let mut b1 = BloomFilter::with_rate_and_hashers(0.01,20,h1.clone(),h2.clone());
+        b1.insert(&1);
+        let mut b2 = BloomFilter::with_rate_and_hashers(0.01,20,h1,h2);
+        b2.insert(&2);
+        let (bits1, bits2) = (b1.as_slice().to_vec(), b2.as_slice().to_vec());
+
+        let b3 = b1.union_new(&b2);
+
+        assert!(b3.contains(&1));
+        assert!(b3.contains(&2));
+        assert_eq!(b1.as_slice(), &bits1[..]);
+        assert_eq!(b2.as_slice(), &bits2[..]);
+        assert_eq!(b3.num_inserts(),2);
+        assert!(!b1.contains(&2));
+        assert!(!b2.contains(&1));
+    }
+
+    #[test]
+    #[should_panic(expected = "different numbers of hashes")]
+    fn union_new_mismatch() {
+        let h1 = RandomState::new();
+        let h2 = RandomState::new();
+        let b1 = BloomFilter::with_size_and_hashers(100,3,h1.clone(),h2.clone());
+        let b2 = BloomFilter::with_size_and_hashers(100,4,h1,h2);
+        b1.union_new(&b2);
+    }
+
+    #[test]
+    fn jaccard() {
+        let h1 = RandomState::new();
+        let h2 = RandomState::new();
+        let mut a = BloomFilter::with_rate_and_hashers(0.01,2000,h1.clone(),h2.clone());
+        let mut b = BloomFilter::with_rate_and_hashers(0.01,2000,h1,h2);
+        assert_eq!(jaccard_similarity(&a,&b),1.0);
+
+        a.extend(0..1000);
+        b.extend(500..1500);
+        // 500 shared items out of 1500 total
+        let j = jaccard_similarity(&a,&b);
+        assert!((j - 1.0/3.0).abs() < 0.05);
+        assert!((jaccard_similarity(&a,&a) - 1.0).abs() < 1e-9);
+
+        b.clear();
+        b.extend(5000..6000);
+        assert!(jaccard_similarity(&a,&b) < 0.05);
+    }
+
+    #[test]
+    fn compatible() {
+        let (h1, h2) = SipBuildHasher::pair_from_seed(13);
+        let a = BloomFilter::with_size_and_hashers(100,3,h1,h2);
+        assert!(a.is_compatible_with(&a));
+        assert!(a.is_compatible_with(&BloomFilter::with_size_and_hashers(100,3,h1,h2)));
+        assert!(!a.is_compatible_with(&BloomFilter::with_size_and_hashers(101,3,h1,h2)));
+        assert!(!a.is_compatible_with(&BloomFilter::with_size_and_hashers(100,4,h1,h2)));
+
+        // different hashers can't be detected
+        let (h3, h4) = SipBuildHasher::pair_from_seed(14);
+        assert!(a.is_compatible_with(&BloomFilter::with_size_and_hashers(100,3,h3,h4)));
+
+        let single = BloomFilter::with_rate_single_hash(0.01,100);
+        let double = BloomFilter::with_size(single.num_bits(),single.num_hashes());
+        assert!(!single.is_compatible_with(&double));
+    }
+
+    #[test]
+    fn operators() {
+        let (h1, h2) = SipBuildHasher::pair_from_seed(12);
+        let mut a = BloomFilter::with_rate_and_hashers(0.01,200,h1,h2);
+        let mut b = BloomFilter::with_rate_and_hashers(0.01,200,h1,h2);
+        a.insert_all(0..100);
+        b.insert_all(50..150);
+
+        let or = &a | &b;
+        assert_eq!(or,a.union_new(&b));
+        for i in 0..150 {
+            assert!(or.contains(&i));
+        }
+        let and = &a & &b;
+        for i in 50..100 {
+            assert!(and.contains(&i));
+        }
+        assert!(and.count_ones() <= a.count_ones());
+
+        let mut c = BloomFilter::with_rate_and_hashers(0.01,200,h1,h2);
+        c |= &a;
+        c |= &b;
+        assert_eq!(c,or);
+        c &= &b;
+        assert_eq!(c.as_slice(),b.as_slice());
+        a &= &b;
+        assert_eq!(a,and);
+    }
+
+    #[test]
+    #[should_panic(expected = "different numbers of hashes")]
+    fn operator_mismatch() {
+        let (h1, h2) = SipBuildHasher::pair_from_seed(12);
+        let a = BloomFilter::with_size_and_hashers(100,3,h1,h2);
+        let b = BloomFilter::with_size_and_hashers(100,4,h1,h2);
+        let _ = &a & &b;
+    }
+
+    #[test]
+    fn difference() {
+        let (h1, h2) = SipBuildHasher::pair_from_seed(11);
+        let mut a = BloomFilter::with_rate_and_hashers(0.01,2000,h1,h2);
+        let mut b = BloomFilter::with_rate_and_hashers(0.01,2000,h1,h2);
+        a.insert_all(0..1000);
+        b.insert_all(950..1050);
+        assert!(a.difference(&b));
+        for i in 950..1000 {
+            assert!(!a.contains(&i));
+        }
+        // items only in a survive unless they share a bit with b, so
+        // most, but not necessarily all, are still there
+        let survivors = (0..950).filter(|i| a.contains(i)).count();
+        assert!(survivors > 650);
+        assert!(!a.difference(&b));
+    }
+
+    #[test]
+    #[should_panic(expected = "different numbers of bits")]
+    fn difference_mismatch() {
+        let mut a:BloomFilter = BloomFilter::with_size(100,3);
+        let b:BloomFilter = BloomFilter::with_size(200,3);
+        a.difference(&b);
+    }
+
+    #[test]
+    fn union_estimate() {
+        let (h1, h2) = SipBuildHasher::pair_from_seed(10);
+        let mut a = BloomFilter::with_rate_and_hashers(0.01,5000,h1,h2);
+        let mut b = BloomFilter::with_rate_and_hashers(0.01,5000,h1,h2);
+        assert_eq!(union_cardinality(&a,&b),0.0);
+
+        a.extend(0..2000);
+        b.extend(1000..3000);
+        let union = union_cardinality(&a,&b);
+        assert!((union - 3000.0).abs() < 3000.0 * 0.05);
+        let intersection = a.estimate_count() as f64 + b.estimate_count() as f64 - union;
+        assert!((intersection - 1000.0).abs() < 1000.0 * 0.1);
+        assert_eq!(union_cardinality(&a,&a).round() as u64,a.estimate_count());
+    }
+
+    #[test]
+    fn small_fpr_test() {
+        // a small filter at a low rate, where correlated double
+        // hashes show up as extra false positives
+        let cnt = 1000;
+        let rate: f32 = 0.001;
+        let mut false_positives = 0;
+        let mut probes = 0;
+        for seed in 0..10 {
+            let mut b = BloomFilter::with_rate_and_seed(rate,cnt,seed);
+            for i in 0..cnt {
+                b.insert(&i);
+            }
+            for i in cnt..cnt+100000 {
+                if b.contains(&i) {
+                    false_positives += 1;
+                }
+                probes += 1;
+            }
+        }
+        let actual_rate = false_positives as f32 / probes as f32;
+        assert!(actual_rate < rate * 1.5);
     }
 
     #[test]
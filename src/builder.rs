@@ -0,0 +1,282 @@
+// This program is free software; you can redistribute it and/or
+// modify it under the terms of the GNU General Public License as
+// published by the Free Software Foundation; either version 2 of the
+// License, or (at your option) any later version.
+
+// This program is distributed in the hope that it will be useful, but
+// WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the GNU
+// General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with this program; if not, write to the Free Software
+// Foundation, Inc., 51 Franklin Street, Fifth Floor, Boston, MA
+// 02110-1301, USA.
+
+use std::collections::hash_map::RandomState;
+use std::error::Error;
+use std::fmt;
+use std::hash::BuildHasher;
+
+use super::bloom::{BloomFilter,needed_bits,optimal_num_hashes};
+
+/// Builds a BloomFilter from whichever settings are known.
+///
+/// The filter is sized either from a false positive `rate` and the
+/// number of `expected_items`, as for `BloomFilter::with_rate`, or
+/// from an explicit `num_bits`.  `num_hashes` defaults to the
+/// optimal number for the size and expected items, and the hashers
+/// default to `RandomState`s.
+///
+/// # Example Usage
+///
+/// ```rust
+/// use bloom::{ASMS,BloomFilterBuilder};
+/// use bloom::hashers::SipBuildHasher;
+///
+/// let mut filter = BloomFilterBuilder::new()
+///     .rate(0.01)
+///     .expected_items(1000)
+///     .hashers(SipBuildHasher::with_keys(1,2),SipBuildHasher::with_keys(3,4))
+///     .build()
+///     .unwrap();
+/// filter.insert(&1);
+/// assert!(filter.contains(&1));
+/// ```
+#[derive(Debug, Clone)]
+pub struct BloomFilterBuilder<R = RandomState, S = RandomState> {
+    rate: Option<f32>,
+    expected_items: Option<u32>,
+    num_bits: Option<usize>,
+    num_hashes: Option<u32>,
+    hash_builder_one: R,
+    hash_builder_two: S,
+}
+
+impl BloomFilterBuilder<RandomState, RandomState> {
+    /// Create a BloomFilterBuilder with nothing set, which will use
+    /// `RandomState` hashers unless `hashers` is called
+    pub fn new() -> BloomFilterBuilder<RandomState, RandomState> {
+        BloomFilterBuilder {
+            rate: None,
+            expected_items: None,
+            num_bits: None,
+            num_hashes: None,
+            hash_builder_one: RandomState::new(),
+            hash_builder_two: RandomState::new(),
+        }
+    }
+}
+
+impl Default for BloomFilterBuilder<RandomState, RandomState> {
+    fn default() -> BloomFilterBuilder<RandomState, RandomState> {
+        BloomFilterBuilder::new()
+    }
+}
+
+impl<R,S> BloomFilterBuilder<R,S> {
+    /// Set the false positive rate the filter should have once it
+    /// holds `expected_items` items.  Can't be combined with
+    /// `num_bits`.
+    pub fn rate(mut self, rate: f32) -> BloomFilterBuilder<R,S> {
+        self.rate = Some(rate);
+        self
+    }
+
+    /// Set the number of items the filter is expected to hold
+    pub fn expected_items(mut self, expected_items: u32) -> BloomFilterBuilder<R,S> {
+        self.expected_items = Some(expected_items);
+        self
+    }
+
+    /// Set the number of bits the filter uses.  Can't be combined
+    /// with `rate`.
+    pub fn num_bits(mut self, num_bits: usize) -> BloomFilterBuilder<R,S> {
+        self.num_bits = Some(num_bits);
+        self
+    }
+
+    /// Set the number of hash functions the filter uses, rather than
+    /// the optimal number for its size
+    pub fn num_hashes(mut self, num_hashes: u32) -> BloomFilterBuilder<R,S> {
+        self.num_hashes = Some(num_hashes);
+        self
+    }
+
+    /// Hash items with `hash_builder_one` and `hash_builder_two`.
+    /// These MUST provide independent hash values, as for
+    /// `BloomFilter::with_size_and_hashers`.
+    pub fn hashers<R2,S2>(self, hash_builder_one: R2, hash_builder_two: S2) -> BloomFilterBuilder<R2,S2> {
+        BloomFilterBuilder {
+            rate: self.rate,
+            expected_items: self.expected_items,
+            num_bits: self.num_bits,
+            num_hashes: self.num_hashes,
+            hash_builder_one: hash_builder_one,
+            hash_builder_two: hash_builder_two,
+        }
+    }
+}
+
+impl<R,S> BloomFilterBuilder<R,S>
+    where R: BuildHasher, S: BuildHasher
+{
+    /// Build the BloomFilter.
+    ///
+    /// Returns an error if the settings contradict each other (both
+    /// `num_bits` and `rate`), don't say how big the filter should
+    /// be, or would give a filter with no bits.  A filter sized from
+    /// a `rate` reports it and `expected_items` through
+    /// `configured_rate` and `expected_items`, as one from
+    /// `BloomFilter::with_rate` does.
+    pub fn build(self) -> Result<BloomFilter<R,S>, BuildError> {
+        let num_bits = match (self.num_bits, self.rate, self.expected_items) {
+            (Some(_), Some(_), _) => { return Err(BuildError::ConflictingSize); }
+            (Some(bits), None, _) => bits,
+            (None, Some(rate), Some(items)) => {
+                if !(rate > 0.0 && rate < 1.0) {
+                    return Err(BuildError::InvalidRate(rate));
+                }
+                needed_bits(rate,items)
+            }
+            (None, Some(_), None) => { return Err(BuildError::MissingExpectedItems); }
+            (None, None, _) => { return Err(BuildError::MissingSize); }
+        };
+        if num_bits == 0 {
+            return Err(BuildError::ZeroBits);
+        }
+        let num_hashes = match (self.num_hashes, self.expected_items) {
+            (Some(hashes), _) => hashes,
+            (None, Some(items)) => optimal_num_hashes(num_bits,items),
+            (None, None) => { return Err(BuildError::MissingNumHashes); }
+        };
+        match (self.rate, self.expected_items) {
+            // sized from the rate, so the filter knows what it was
+            // configured for
+            (Some(rate), Some(items)) => {
+                Ok(BloomFilter::with_rate_hashes_and_hashers(rate,items,num_hashes,
+                                                             self.hash_builder_one,self.hash_builder_two))
+            }
+            _ => {
+                Ok(BloomFilter::with_size_and_hashers(num_bits,num_hashes,
+                                                      self.hash_builder_one,self.hash_builder_two))
+            }
+        }
+    }
+}
+
+/// Errors that can occur when building a BloomFilter with a
+/// `BloomFilterBuilder`
+#[derive(Debug, Clone, PartialEq)]
+pub enum BuildError {
+    /// Both `num_bits` and `rate` were set, which each determine the
+    /// size of the filter
+    ConflictingSize,
+    /// Neither `num_bits` nor `rate` was set
+    MissingSize,
+    /// `rate` was set without `expected_items`
+    MissingExpectedItems,
+    /// `num_bits` was set without `num_hashes` or `expected_items`,
+    /// so the number of hashes can't be chosen
+    MissingNumHashes,
+    /// The rate was not strictly between 0 and 1
+    InvalidRate(f32),
+    /// The settings give a filter with no bits
+    ZeroBits,
+}
+
+impl fmt::Display for BuildError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            BuildError::ConflictingSize => write!(f, "only one of num_bits and rate can be set"),
+            BuildError::MissingSize => write!(f, "one of num_bits or rate must be set"),
+            BuildError::MissingExpectedItems => write!(f, "rate needs expected_items to be set"),
+            BuildError::MissingNumHashes => {
+                write!(f, "num_bits needs num_hashes or expected_items to be set")
+            }
+            BuildError::InvalidRate(rate) => {
+                write!(f, "false positive rate must be between 0 and 1 (exclusive), got {}", rate)
+            }
+            BuildError::ZeroBits => write!(f, "BloomFilter must have at least one bit"),
+        }
+    }
+}
+
+impl Error for BuildError {}
+
+#[cfg(test)]
+mod tests {
+    use super::{BloomFilterBuilder,BuildError};
+    use bloom::{BloomFilter,needed_bits,optimal_num_hashes};
+    use hashers::SipBuildHasher;
+    use ASMS;
+
+    #[test]
+    fn rate_and_items() {
+        let mut b = BloomFilterBuilder::new().rate(0.01).expected_items(1000).build().unwrap();
+        assert_eq!(b.num_bits(),needed_bits(0.01,1000));
+        assert_eq!(b.num_hashes(),optimal_num_hashes(needed_bits(0.01,1000),1000));
+        assert_eq!(b.configured_rate(),Some(0.01));
+        assert_eq!(b.expected_items(),Some(1000));
+        b.insert(&1);
+        assert!(b.contains(&1));
+    }
+
+    #[test]
+    fn rate_items_and_hashes() {
+        let b = BloomFilterBuilder::new().rate(0.01).expected_items(1000).num_hashes(3).build().unwrap();
+        assert_eq!(b.num_bits(),needed_bits(0.01,1000));
+        assert_eq!(b.num_hashes(),3);
+        assert_eq!(b.configured_rate(),Some(0.01));
+        assert_eq!(b.expected_items(),Some(1000));
+    }
+
+    #[test]
+    fn bits_and_hashes() {
+        let b = BloomFilterBuilder::new().num_bits(1000).num_hashes(4).build().unwrap();
+        assert_eq!(b.num_bits(),1000);
+        assert_eq!(b.num_hashes(),4);
+        assert_eq!(b.configured_rate(),None);
+        assert_eq!(b.expected_items(),None);
+    }
+
+    #[test]
+    fn bits_and_items() {
+        let b = BloomFilterBuilder::new().num_bits(1000).expected_items(100).build().unwrap();
+        assert_eq!(b.num_bits(),1000);
+        assert_eq!(b.num_hashes(),optimal_num_hashes(1000,100));
+    }
+
+    #[test]
+    fn hashers() {
+        let (one, two) = SipBuildHasher::pair_from_seed(4);
+        let mut b1 = BloomFilterBuilder::new().rate(0.01).expected_items(100)
+            .hashers(one,two).build().unwrap();
+        let mut b2 = BloomFilter::with_rate_and_hashers(0.01,100,one,two);
+        for i in 0..50 {
+            b1.insert(&i);
+            b2.insert(&i);
+        }
+        assert_eq!(b1.as_slice(),b2.as_slice());
+    }
+
+    #[test]
+    fn errors() {
+        assert_eq!(BloomFilterBuilder::new().num_bits(1000).rate(0.01).expected_items(100).build().unwrap_err(),
+                   BuildError::ConflictingSize);
+        assert_eq!(BloomFilterBuilder::new().num_hashes(3).build().unwrap_err(),
+                   BuildError::MissingSize);
+        assert_eq!(BloomFilterBuilder::new().expected_items(100).build().unwrap_err(),
+                   BuildError::MissingSize);
+        assert_eq!(BloomFilterBuilder::new().rate(0.01).build().unwrap_err(),
+                   BuildError::MissingExpectedItems);
+        assert_eq!(BloomFilterBuilder::new().num_bits(1000).build().unwrap_err(),
+                   BuildError::MissingNumHashes);
+        assert_eq!(BloomFilterBuilder::new().rate(1.0).expected_items(100).build().unwrap_err(),
+                   BuildError::InvalidRate(1.0));
+        assert_eq!(BloomFilterBuilder::new().num_bits(0).num_hashes(3).build().unwrap_err(),
+                   BuildError::ZeroBits);
+        assert_eq!(BloomFilterBuilder::new().rate(0.01).expected_items(0).build().unwrap_err(),
+                   BuildError::ZeroBits);
+    }
+}
@@ -0,0 +1,266 @@
+// This program is free software; you can redistribute it and/or
+// modify it under the terms of the GNU General Public License as
+// published by the Free Software Foundation; either version 2 of the
+// License, or (at your option) any later version.
+
+// This program is distributed in the hope that it will be useful, but
+// WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the GNU
+// General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with this program; if not, write to the Free Software
+// Foundation, Inc., 51 Franklin Street, Fifth Floor, Boston, MA
+// 02110-1301, USA.
+
+use std::collections::hash_map::RandomState;
+use std::hash::{BuildHasher,Hash};
+use std::sync::atomic::{AtomicU64,Ordering};
+
+use super::{ASMS,Resettable};
+use super::bloom::{needed_bits,optimal_num_hashes};
+use super::hashing::HashIter;
+
+/// A BloomFilter that can be shared between threads without a lock.
+///
+/// The bits are stored in atomic words, so `insert` and `contains`
+/// both take `&self` and can be called from many threads at once,
+/// for example through an `Arc`.  Each bit is set with a relaxed
+/// atomic OR, so an item inserted on one thread will be seen by
+/// `contains` on another thread eventually, but not necessarily
+/// straight away, and inserts are not ordered with respect to other
+/// memory operations.  Once a thread has seen all of an item's bits,
+/// `contains` will keep returning `true` for it.  The return value
+/// of `insert` is also only approximate when two threads insert
+/// items sharing bits at the same time.
+///
+/// `clear` needs exclusive access (`&mut self`), as clearing while
+/// other threads are inserting would lose bits.
+///
+/// # Example Usage
+///
+/// ```rust
+/// use std::sync::Arc;
+/// use std::thread;
+/// use bloom::ConcurrentBloomFilter;
+///
+/// let filter: Arc<ConcurrentBloomFilter> = Arc::new(ConcurrentBloomFilter::with_rate(0.01,1000));
+/// let f = filter.clone();
+/// thread::spawn(move || { f.insert(&1); }).join().unwrap();
+/// assert!(filter.contains(&1));
+/// ```
+pub struct ConcurrentBloomFilter<R = RandomState, S = RandomState> {
+    words: Vec<AtomicU64>,
+    num_bits: usize,
+    num_hashes: u32,
+    hash_builder_one: R,
+    hash_builder_two: S,
+}
+
+impl ConcurrentBloomFilter<RandomState, RandomState> {
+    /// Create a new ConcurrentBloomFilter with the specified number
+    /// of bits, and hashes
+    ///
+    /// # Panics
+    /// Panics if `num_bits` is 0
+    pub fn with_size(num_bits: usize, num_hashes: u32) -> ConcurrentBloomFilter<RandomState, RandomState> {
+        ConcurrentBloomFilter::with_size_and_hashers(num_bits,num_hashes,
+                                                     RandomState::new(),RandomState::new())
+    }
+
+    /// create a ConcurrentBloomFilter that expects to hold
+    /// `expected_num_items`.  The filter will be sized to have a
+    /// false positive rate of the value specified in `rate`.
+    pub fn with_rate(rate: f32, expected_num_items: u32) -> ConcurrentBloomFilter<RandomState, RandomState> {
+        let bits = needed_bits(rate,expected_num_items);
+        ConcurrentBloomFilter::with_size(bits,optimal_num_hashes(bits,expected_num_items))
+    }
+}
+
+impl<R,S> ConcurrentBloomFilter<R,S>
+    where R: BuildHasher, S: BuildHasher
+{
+    /// Create a new ConcurrentBloomFilter with the specified number
+    /// of bits, hashes, and the two specified HashBuilders.  The
+    /// HashBuilders MUST provide independent hash values, as for
+    /// `BloomFilter::with_size_and_hashers`.
+    ///
+    /// # Panics
+    /// Panics if `num_bits` is 0
+    pub fn with_size_and_hashers(num_bits: usize, num_hashes: u32,
+                                 hash_builder_one: R, hash_builder_two: S) -> ConcurrentBloomFilter<R,S> {
+        if num_bits == 0 {
+            panic!("ConcurrentBloomFilter must have at least one bit");
+        }
+        ConcurrentBloomFilter {
+            words: (0..num_bits.div_ceil(64)).map(|_| AtomicU64::new(0)).collect(),
+            num_bits: num_bits,
+            num_hashes: num_hashes,
+            hash_builder_one: hash_builder_one,
+            hash_builder_two: hash_builder_two,
+        }
+    }
+
+    /// Create a ConcurrentBloomFilter that expects to hold
+    /// `expected_num_items` with a false positive rate of `rate`,
+    /// hashing items with `hash_builder_one` and `hash_builder_two`.
+    pub fn with_rate_and_hashers(rate: f32, expected_num_items: u32,
+                                 hash_builder_one: R, hash_builder_two: S) -> ConcurrentBloomFilter<R,S> {
+        let bits = needed_bits(rate,expected_num_items);
+        ConcurrentBloomFilter::with_size_and_hashers(bits,optimal_num_hashes(bits,expected_num_items),
+                                                     hash_builder_one,hash_builder_two)
+    }
+
+    /// Get the number of bits this ConcurrentBloomFilter is using
+    pub fn num_bits(&self) -> usize {
+        self.num_bits
+    }
+
+    /// Get the number of hash functions this ConcurrentBloomFilter
+    /// is using
+    pub fn num_hashes(&self) -> u32 {
+        self.num_hashes
+    }
+
+    /// Insert item into this ConcurrentBloomFilter.  This only needs
+    /// a shared reference, so can be called from many threads at
+    /// once.
+    ///
+    /// If the filter did not have this value present, `true` is
+    /// returned.
+    ///
+    /// If the filter did have this value present, `false` is
+    /// returned.
+    pub fn insert<T: Hash>(&self, item: &T) -> bool {
+        let mut contained = true;
+        for h in self.hashes(item) {
+            let (word, bit) = self.position(h);
+            let prev = self.words[word].fetch_or(bit,Ordering::Relaxed);
+            if prev & bit == 0 {
+                contained = false;
+            }
+        }
+        !contained
+    }
+
+    /// Check if the item has been inserted into this
+    /// ConcurrentBloomFilter.  This function can return false
+    /// positives.  It won't return false negatives for items inserted
+    /// on this thread, or whose inserts on other threads have become
+    /// visible to it.
+    pub fn contains<T: Hash>(&self, item: &T) -> bool {
+        for h in self.hashes(item) {
+            let (word, bit) = self.position(h);
+            if self.words[word].load(Ordering::Relaxed) & bit == 0 {
+                return false;
+            }
+        }
+        true
+    }
+
+    /// Remove all values from this ConcurrentBloomFilter
+    pub fn clear(&mut self) {
+        for word in self.words.iter_mut() {
+            *word.get_mut() = 0;
+        }
+    }
+
+    // the hash values for item
+    fn hashes<T: Hash>(&self, item: &T) -> HashIter {
+        HashIter::from(item,
+                       self.num_hashes,
+                       &self.hash_builder_one,
+                       &self.hash_builder_two)
+    }
+
+    // the word holding the bit h selects, and a mask for that bit
+    fn position(&self, h: u64) -> (usize, u64) {
+        let idx = (h % self.num_bits as u64) as usize;
+        (idx / 64, 1 << (idx % 64))
+    }
+}
+
+impl<R,S> ASMS for ConcurrentBloomFilter<R,S>
+    where R: BuildHasher, S: BuildHasher {
+    /// Insert item into this ConcurrentBloomFilter.  See the
+    /// inherent `insert`, which only needs `&self`.
+    fn insert<T: Hash>(&mut self, item: &T) -> bool {
+        ConcurrentBloomFilter::insert(self,item)
+    }
+
+    /// Check if the item has been inserted into this
+    /// ConcurrentBloomFilter
+    fn contains<T: Hash>(&self, item: &T) -> bool {
+        ConcurrentBloomFilter::contains(self,item)
+    }
+
+    /// Remove all values from this ConcurrentBloomFilter
+    fn clear(&mut self) {
+        ConcurrentBloomFilter::clear(self)
+    }
+
+    /// The number of bits this ConcurrentBloomFilter is using
+    fn capacity_bits(&self) -> usize {
+        self.num_bits
+    }
+
+    /// The number of hash functions this ConcurrentBloomFilter is
+    /// using
+    fn num_hashes(&self) -> u32 {
+        self.num_hashes
+    }
+}
+
+impl<R,S> Resettable for ConcurrentBloomFilter<R,S>
+    where R: BuildHasher, S: BuildHasher {
+    /// Remove all values from this ConcurrentBloomFilter
+    fn reset(&mut self) {
+        self.clear();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+    use std::thread;
+    use super::ConcurrentBloomFilter;
+    use hashers::SipBuildHasher;
+
+    #[test]
+    fn simple() {
+        let (one, two) = SipBuildHasher::pair_from_seed(1);
+        let mut b = ConcurrentBloomFilter::with_rate_and_hashers(0.01,100,one,two);
+        assert!(b.insert(&1));
+        assert!(!b.insert(&1));
+        assert!(b.contains(&1));
+        assert!(!b.contains(&2));
+        b.clear();
+        assert!(!b.contains(&1));
+    }
+
+    #[test]
+    #[should_panic(expected = "at least one bit")]
+    fn zero_bits() {
+        ConcurrentBloomFilter::with_size(0,3);
+    }
+
+    #[test]
+    fn threads() {
+        let per_thread = 10000;
+        let b: Arc<ConcurrentBloomFilter> = Arc::new(ConcurrentBloomFilter::with_rate(0.01,4*per_thread));
+        let handles: Vec<_> = (0..4).map(|t| {
+            let b = b.clone();
+            thread::spawn(move || {
+                for i in t*per_thread..(t+1)*per_thread {
+                    b.insert(&i);
+                }
+            })
+        }).collect();
+        for h in handles {
+            h.join().unwrap();
+        }
+        for i in 0..4*per_thread {
+            assert!(b.contains(&i));
+        }
+    }
+}
@@ -1,8 +1,13 @@
 
+use std::cmp::max;
+use std::convert::TryFrom;
+use std::fmt;
+use std::mem;
 use std::hash::{BuildHasher,Hash};
 use std::collections::hash_map::RandomState;
 use super::ValueVec;
-use super::ASMS;
+use super::bloom::{BloomFilter,DEFAULT_EXPECTED_ITEMS,DEFAULT_RATE};
+use super::{ASMS,Intersectable,Resettable,Unionable};
 use super::hashing::HashIter;
 
 /// A standard counting bloom filter that uses a fixed number of bits
@@ -20,16 +25,14 @@ pub struct CountingBloomFilter<R = RandomState, S = RandomState> {
 impl CountingBloomFilter<RandomState,RandomState> {
     /// Create a new CountingBloomFilter that will hold `num_entries`
     /// items, uses `bits_per_entry` per item, and `num_hashes` hashes
+    ///
+    /// # Panics
+    /// Panics if `num_entries` is 0
     pub fn with_size(num_entries: usize,
                      bits_per_entry: usize,
                      num_hashes: u32) -> CountingBloomFilter<RandomState,RandomState> {
-        CountingBloomFilter {
-            counters: ValueVec::new(bits_per_entry, num_entries),
-            num_entries: num_entries as u64,
-            num_hashes: num_hashes,
-            hash_builder_one: RandomState::new(),
-            hash_builder_two: RandomState::new(),
-        }
+        CountingBloomFilter::with_size_and_hashers(num_entries,bits_per_entry,num_hashes,
+                                                   RandomState::new(),RandomState::new())
     }
 
     /// create a CountingBloomFilter that uses `bits_per_entry`
@@ -43,6 +46,51 @@ impl CountingBloomFilter<RandomState,RandomState> {
                                        super::bloom::optimal_num_hashes(entries,expected_num_items))
     }
 
+    /// Create a CountingBloomFilter whose counters can count up to
+    /// at least `max_count`, sized for a false positive rate of
+    /// `rate` with `expected_num_items` items.  This is `with_rate`
+    /// with `bits_for_max(max_count)` bits per entry.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use bloom::CountingBloomFilter;
+    /// let cbf = CountingBloomFilter::with_rate_for_max_count(10,0.01,1000);
+    /// assert_eq!(cbf.bits_per_entry(),CountingBloomFilter::bits_for_max(10));
+    /// ```
+    pub fn with_rate_for_max_count(max_count: u32, rate: f32, expected_num_items: u32) -> CountingBloomFilter<RandomState, RandomState> {
+        CountingBloomFilter::with_rate(CountingBloomFilter::bits_for_max(max_count),
+                                       rate,
+                                       expected_num_items)
+    }
+
+    /// Create a CountingBloomFilter with `bits_per_entry` bits per
+    /// counter, sized like `with_rate` for a false positive rate of
+    /// `rate` with as many items as `items` yields, and insert them
+    /// all.  Repeated items are counted.
+    ///
+    /// # Panics
+    /// Panics if `items` has more than `u32::MAX` items
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use bloom::CountingBloomFilter;
+    /// let cbf = CountingBloomFilter::from_rate_iter(4,0.01,vec!["a","b","a"]);
+    /// // estimates are upper bounds
+    /// assert!(cbf.estimate_count(&"a") >= 2);
+    /// ```
+    pub fn from_rate_iter<I>(bits_per_entry: usize, rate: f32, items: I) -> CountingBloomFilter<RandomState, RandomState>
+        where I: IntoIterator, I::IntoIter: ExactSizeIterator, I::Item: Hash
+    {
+        let iter = items.into_iter();
+        let expected_num_items = u32::try_from(max(iter.len(),1))
+            .unwrap_or_else(|_| panic!("can't size a CountingBloomFilter for {} items",iter.len()));
+        let mut filter = CountingBloomFilter::with_rate(bits_per_entry,rate,expected_num_items);
+        filter.extend(iter);
+        filter
+    }
+
     /// Return the number of bits needed to hold values up to and
     /// including `max`
     ///
@@ -67,6 +115,20 @@ impl CountingBloomFilter<RandomState,RandomState> {
     }
 }
 
+/// The number of bits per counter of `CountingBloomFilter::default()`
+pub const DEFAULT_BITS_PER_ENTRY: usize = 4;
+
+impl Default for CountingBloomFilter<RandomState,RandomState> {
+    /// Create a small CountingBloomFilter with
+    /// `DEFAULT_BITS_PER_ENTRY` (4) bits per counter, sized like
+    /// `BloomFilter::default()` for `DEFAULT_EXPECTED_ITEMS` (1000)
+    /// items at a false positive rate of `DEFAULT_RATE` (0.01), with
+    /// `RandomState` hashers
+    fn default() -> CountingBloomFilter<RandomState,RandomState> {
+        CountingBloomFilter::with_rate(DEFAULT_BITS_PER_ENTRY,DEFAULT_RATE,DEFAULT_EXPECTED_ITEMS)
+    }
+}
+
 impl<R,S> CountingBloomFilter<R,S>
     where R: BuildHasher, S: BuildHasher
 {
@@ -76,10 +138,16 @@ impl<R,S> CountingBloomFilter<R,S>
     /// Passing two HashBuilders that produce the same or correlated
     /// hash values will break the false positive guarantees of the
     /// CountingBloomFilter.
+    ///
+    /// # Panics
+    /// Panics if `num_entries` is 0
     pub fn with_size_and_hashers(num_entries: usize,
                                  bits_per_entry: usize,
                                  num_hashes: u32,
                                  hash_builder_one: R, hash_builder_two: S) -> CountingBloomFilter<R,S> {
+        if num_entries == 0 {
+            panic!("CountingBloomFilter must have at least one entry");
+        }
         CountingBloomFilter {
             counters: ValueVec::new(bits_per_entry, num_entries),
             num_entries: num_entries as u64,
@@ -106,30 +174,54 @@ impl<R,S> CountingBloomFilter<R,S>
                                                    hash_builder_one,hash_builder_two)
     }
 
+    /// Get the number of counters this CountingBloomFilter is using
+    pub fn num_entries(&self) -> usize {
+        self.num_entries as usize
+    }
+
+    /// Get the number of bits in each counter
+    pub fn bits_per_entry(&self) -> usize {
+        self.counters.bits_per_val()
+    }
+
+    /// Get the number of hash functions this CountingBloomFilter is
+    /// using
+    pub fn num_hashes(&self) -> u32 {
+        self.num_hashes
+    }
+
     /// Remove an item.  Returns an upper bound of the number of times
     /// this item had been inserted previously (i.e. the count before
     /// this remove).  Returns 0 if item was never inserted.
+    ///
+    /// If `contains` is a false positive for item, this still lowers
+    /// its counters, which may remove other items.  Counters never
+    /// drop below 0.
     pub fn remove<T: Hash>(&mut self, item: &T) ->  u32 {
-        if !(self as &CountingBloomFilter<R,S>).contains(item) {
-            return 0;
+        self.remove_n(item,1)
+    }
+
+    /// Remove an item `n` times in a single pass over its counters.
+    /// This behaves like calling `remove` `n` times: the item's
+    /// counters are lowered by `n`, or by its estimated count if that
+    /// is smaller, so they never drop below 0.  Returns the estimated
+    /// count before this remove, which is 0 if item was never
+    /// inserted.
+    pub fn remove_n<T: Hash>(&mut self, item: &T, n: u32) -> u32 {
+        let count = self.estimate_count(item);
+        let dec = if n < count { n } else { count } as u64;
+        if dec == 0 {
+            return count;
         }
-        let mut min = u32::max_value();
         for h in HashIter::from(item,
                                 self.num_hashes,
                                 &self.hash_builder_one,
                                 &self.hash_builder_two) {
             let idx = (h % self.num_entries) as usize;
             let cur = self.counters.get(idx);
-            if cur < min {
-                min = cur;
-            }
-            if cur > 0 {
-                self.counters.set(idx,cur-1);
-            } else {
-                panic!("Contains returned true but a counter is 0");
-            }
+            self.counters.set(idx,cur.saturating_sub(dec));
         }
-        min
+        count
     }
 
     /// Return an estimate of the number of times `item` has been
@@ -137,7 +229,7 @@ impl<R,S> CountingBloomFilter<R,S>
     /// count, meaning the item has been inserted *at most* this many
     /// times, but possibly fewer.
     pub fn estimate_count<T: Hash>(&self, item: &T) -> u32 {
-        let mut min = u32::max_value();
+        let mut min = u64::MAX;
         for h in HashIter::from(item,
                                 self.num_hashes,
                                 &self.hash_builder_one,
@@ -148,14 +240,218 @@ impl<R,S> CountingBloomFilter<R,S>
                 min = cur;
             }
         }
-        min
+        clamp_count(min)
+    }
+
+    /// Like `estimate_count`, but returns `None` when any of the
+    /// item's counters is 0, meaning it has definitely not been
+    /// inserted.  Otherwise returns `Some` of the estimate.
+    pub fn estimate_count_opt<T: Hash>(&self, item: &T) -> Option<u32> {
+        match self.estimate_count(item) {
+            0 => None,
+            count => Some(count),
+        }
+    }
+
+    /// Get the fraction of this CountingBloomFilter's counters that
+    /// are non-zero, the counting equivalent of a BloomFilter's
+    /// `count_ones() / num_bits()`.  As it approaches 1 the false
+    /// positive rate rises quickly, so this can be used to tell when
+    /// the filter needs rebuilding larger.
+    pub fn load_factor(&self) -> f64 {
+        let nonzero = self.counters.iter().filter(|&count| count > 0).count();
+        nonzero as f64 / self.num_entries as f64
+    }
+
+    /// Halve every counter, rounding down, so older inserts count
+    /// for less than newer ones.  Estimated counts roughly halve,
+    /// and items inserted only once may be removed.
+    pub fn decay(&mut self) {
+        self.update_counters(|count| count >> 1);
+    }
+
+    /// Subtract `n` from every counter, stopping at 0.  Items whose
+    /// counters were all at most `n` are removed.
+    pub fn decay_by(&mut self, n: u32) {
+        self.update_counters(|count| count.saturating_sub(n as u64));
+    }
+
+    // replace each non-zero counter with f(counter)
+    fn update_counters<F: Fn(u64) -> u64>(&mut self, f: F) {
+        for i in 0..self.num_entries as usize {
+            let cur = self.counters.get(i);
+            if cur > 0 {
+                self.counters.set(i,f(cur));
+            }
+        }
+    }
+
+    /// Iterate over the non-zero counters of this
+    /// CountingBloomFilter, yielding the index and value of each in
+    /// index order.  Values too large for a `u32` are reported as
+    /// `u32::MAX`.
+    pub fn iter_counters(&self) -> impl Iterator<Item=(usize, u32)> + '_ {
+        self.counters.iter()
+            .enumerate()
+            .filter(|&(_, count)| count > 0)
+            .map(|(i, count)| (i, clamp_count(count)))
+    }
+
+    /// Estimate the total number of inserts into this
+    /// CountingBloomFilter, counting repeats, as the sum of all the
+    /// counters divided by the number of hashes.  Removes are
+    /// subtracted, and inserts that hit a saturated counter are
+    /// undercounted.
+    pub fn estimate_total_insertions(&self) -> u64 {
+        if self.num_hashes == 0 {
+            return 0;
+        }
+        self.counters.sum() / self.num_hashes as u64
+    }
+
+    /// Get the number of bytes this CountingBloomFilter is using,
+    /// counting both the struct itself and the storage for its
+    /// counters.  Any heap memory owned by the hashers is not
+    /// included.
+    pub fn memory_bytes(&self) -> usize {
+        // counters.memory_bytes includes the ValueVec struct, which
+        // is already part of the size of self
+        mem::size_of::<CountingBloomFilter<R,S>>() - mem::size_of::<ValueVec>() +
+            self.counters.memory_bytes()
+    }
+
+    /// Convert this CountingBloomFilter into a plain BloomFilter,
+    /// with a bit set wherever a counter is non-zero.  The result has
+    /// the same number of bits as this filter has entries, the same
+    /// number of hashes and copies of the same hashers, so it
+    /// contains the same items.  Counts are not preserved, and items
+    /// can no longer be removed.
+    pub fn to_bloom(&self) -> BloomFilter<R,S>
+        where R: Clone, S: Clone
+    {
+        let num_entries = self.num_entries as usize;
+        let mut words = vec![0u32; num_entries.div_ceil(32)];
+        for i in 0..num_entries {
+            if self.counters.get(i) > 0 {
+                words[i / 32] |= 1 << (i % 32);
+            }
+        }
+        BloomFilter::from_raw_with_hashers(words,num_entries,self.num_hashes,
+                                           self.hash_builder_one.clone(),
+                                           self.hash_builder_two.clone())
+    }
+
+    /// Calculates the union of two CountingBloomFilters like `union`,
+    /// but sets each counter to the larger of the two counters rather
+    /// than their sum.  This is useful when the same items may have
+    /// been counted in both filters.  Returns true if self changed.
+    ///
+    /// # Panics
+    /// Panics if the filters don't have the same number of entries,
+    /// bits per entry and hashes
+    pub fn union_max(&mut self, other: &CountingBloomFilter<R,S>) -> bool {
+        self.combine(other, |a, b, _| if b > a { b } else { a })
+    }
+
+    /// Subtracts the counters of `other` from those of `self`,
+    /// stopping at 0, so items will remain in `self` only if they
+    /// were inserted into it more times than into `other`.  Returns
+    /// true if self changed.
+    ///
+    /// # Panics
+    /// Panics if the filters don't have the same number of entries,
+    /// bits per entry and hashes
+    pub fn difference(&mut self, other: &CountingBloomFilter<R,S>) -> bool {
+        self.combine(other, |a, b, _| a.saturating_sub(b))
+    }
+
+    // panic unless other has the same shape as self
+    fn check_compatible(&self, other: &CountingBloomFilter<R,S>) {
+        if self.num_entries != other.num_entries ||
+            self.counters.bits_per_val() != other.counters.bits_per_val() ||
+            self.num_hashes != other.num_hashes {
+                panic!("CountingBloomFilters are not compatible: {:?} vs {:?}", self, other);
+            }
+    }
+
+    // replace each counter in self with f(self_count, other_count,
+    // max_value), returning true if any counter changed
+    fn combine<F>(&mut self, other: &CountingBloomFilter<R,S>, f: F) -> bool
+        where F: Fn(u64, u64, u64) -> u64
+    {
+        self.check_compatible(other);
+        let max = self.counters.max_value();
+        let mut changed = false;
+        for i in 0..self.num_entries as usize {
+            let cur = self.counters.get(i);
+            let new = f(cur, other.counters.get(i), max);
+            if new != cur {
+                self.counters.set(i,new);
+                changed = true;
+            }
+        }
+        changed
     }
 
     /// Inserts an item, returns the estimated count of the number of
     /// times this item had previously been inserted (not counting
     /// this insertion)
     pub fn insert_get_count<T: Hash>(&mut self, item: &T) -> u32 {
-        let mut min = u32::max_value();
+        self.insert_n(item,1)
+    }
+
+    /// Inserts an item using conservative update (also called
+    /// minimum increase): only the item's counters that are at its
+    /// current estimated count are incremented, as the others already
+    /// count other items colliding with it.  Estimates stay upper
+    /// bounds, but are much tighter for colliding items, especially
+    /// with skewed data.  Returns the estimated count before this
+    /// insert, like `insert_get_count`.
+    ///
+    /// Counters no longer count every insert, so `remove` should not
+    /// be used on a filter filled this way: it can lower another
+    /// item's counters below its count, causing false negatives.
+    pub fn insert_conservative<T: Hash>(&mut self, item: &T) -> u32 {
+        let indices: Vec<usize> = HashIter::from(item,
+                                                 self.num_hashes,
+                                                 &self.hash_builder_one,
+                                                 &self.hash_builder_two)
+            .map(|h| (h % self.num_entries) as usize)
+            .collect();
+        let min = indices.iter().map(|&i| self.counters.get(i)).min().unwrap_or(u64::MAX);
+        if min < self.counters.max_value() {
+            for &i in &indices {
+                if self.counters.get(i) == min {
+                    self.counters.set(i,min + 1);
+                }
+            }
+        }
+        clamp_count(min)
+    }
+
+    /// Inserts an item `n` times, adding `n` to each of its counters
+    /// (stopping at the largest value a counter can hold).  Returns
+    /// the estimated count of the number of times this item had
+    /// previously been inserted, like `insert_get_count`.
+    pub fn insert_n<T: Hash>(&mut self, item: &T, n: u32) -> u32 {
+        self.add(item,n).0
+    }
+
+    /// Inserts an item like `insert_get_count`, also returning
+    /// whether any of the item's counters was already at
+    /// `max_value`, in which case it couldn't be incremented and
+    /// estimated counts for the item may be too low.
+    pub fn insert_checked<T: Hash>(&mut self, item: &T) -> (u32, bool) {
+        self.add(item,1)
+    }
+
+    // add n to each of item's counters, stopping at the max value.
+    // Returns the estimated count before adding, and whether any
+    // counter was already at the max
+    fn add<T: Hash>(&mut self, item: &T, n: u32) -> (u32, bool) {
+        let max = self.counters.max_value();
+        let mut min = u64::MAX;
+        let mut saturated = false;
         for h in HashIter::from(item,
                                 self.num_hashes,
                                 &self.hash_builder_one,
@@ -165,11 +461,45 @@ impl<R,S> CountingBloomFilter<R,S>
             if cur < min {
                 min = cur;
             }
-            if cur < self.counters.max_value() {
-                self.counters.set(idx,cur+1);
+            if cur == max {
+                saturated = true;
             }
+            let new = cur.saturating_add(n as u64);
+            self.counters.set(idx,if new > max { max } else { new });
         }
-        min
+        (clamp_count(min), saturated)
+    }
+}
+
+/// Estimate the number of inserts common to `a` and `b`, counting
+/// each item the smaller number of times it was inserted into either
+/// filter.  This is the sum over counters of the smaller of the two
+/// counters, divided by the number of hashes, so like
+/// `estimate_total_insertions` it is approximate: collisions raise
+/// it, and saturated counters lower it.
+///
+/// # Panics
+/// Panics if the filters don't have the same number of entries, bits
+/// per entry and hashes
+pub fn intersection_count<R,S>(a: &CountingBloomFilter<R,S>, b: &CountingBloomFilter<R,S>) -> u64
+    where R: BuildHasher, S: BuildHasher
+{
+    a.check_compatible(b);
+    if a.num_hashes == 0 {
+        return 0;
+    }
+    let common = a.counters.iter().zip(b.counters.iter())
+        .fold(0u64, |sum, (x, y)| sum.saturating_add(x.min(y)));
+    common / a.num_hashes as u64
+}
+
+// counters can be wider than the u32 counts reported by the filter,
+// so saturate at u32::MAX
+fn clamp_count(count: u64) -> u32 {
+    if count > u32::MAX as u64 {
+        u32::MAX
+    } else {
+        count as u32
     }
 }
 
@@ -178,21 +508,7 @@ impl<R,S> ASMS for CountingBloomFilter<R,S>
     /// Inserts an item, returns true if this item was already in the
     /// filter any number of times
     fn insert<T: Hash>(&mut self, item: &T) -> bool {
-        let mut min = u32::max_value();
-        for h in HashIter::from(item,
-                                self.num_hashes,
-                                &self.hash_builder_one,
-                                &self.hash_builder_two) {
-            let idx = (h % self.num_entries) as usize;
-            let cur = self.counters.get(idx);
-            if cur < min {
-                min = cur;
-            }
-            if cur < self.counters.max_value() {
-                self.counters.set(idx,cur+1);
-            }
-        }
-        min > 0
+        self.insert_n(item,1) > 0
     }
 
 
@@ -217,13 +533,94 @@ impl<R,S> ASMS for CountingBloomFilter<R,S>
     fn clear(&mut self) {
         self.counters.clear();
     }
+
+    /// The number of bits used by all the counters, `num_entries *
+    /// bits_per_entry`
+    fn capacity_bits(&self) -> usize {
+        self.counters.count() * self.counters.bits_per_val()
+    }
+
+    /// The number of hash functions this CountingBloomFilter is
+    /// using
+    fn num_hashes(&self) -> u32 {
+        self.num_hashes
+    }
 }
 
+impl<R,S> Resettable for CountingBloomFilter<R,S>
+    where R: BuildHasher, S: BuildHasher {
+    /// Remove all values from this CountingBloomFilter
+    fn reset(&mut self) {
+        self.clear();
+    }
+}
+impl<R,S> Intersectable for CountingBloomFilter<R,S>
+    where R: BuildHasher, S: BuildHasher {
+    /// Calculates the intersection of two CountingBloomFilters.  Each
+    /// counter in `self` becomes the smaller of the two counters, so
+    /// only items inserted into both filters will still be present in
+    /// `self`, with a count of at most the smaller of their two
+    /// counts.  Returns true if self changed.
+    ///
+    /// # Panics
+    /// Panics if the filters don't have the same number of entries,
+    /// bits per entry and hashes
+    fn intersect(&mut self, other: &CountingBloomFilter<R,S>) -> bool {
+        self.combine(other, |a, b, _| if b < a { b } else { a })
+    }
+}
+
+impl<R,S> Unionable for CountingBloomFilter<R,S>
+    where R: BuildHasher, S: BuildHasher {
+    /// Calculates the union of two CountingBloomFilters.  Each
+    /// counter in `self` becomes the sum of the two counters,
+    /// saturating at the maximum value a counter can hold, so counts
+    /// of items inserted into both filters are added together.
+    /// Returns true if self changed.
+    ///
+    /// # Panics
+    /// Panics if the filters don't have the same number of entries,
+    /// bits per entry and hashes
+    fn union(&mut self, other: &CountingBloomFilter<R,S>) -> bool {
+        self.combine(other, |a, b, max| {
+            if max - a < b { max } else { a + b }
+        })
+    }
+}
+
+impl<T,R,S> Extend<T> for CountingBloomFilter<R,S>
+    where T: Hash, R: BuildHasher, S: BuildHasher {
+    /// Insert every item from `iter` into this CountingBloomFilter,
+    /// counting each repeat
+    fn extend<I: IntoIterator<Item=T>>(&mut self, iter: I) {
+        for item in iter {
+            self.insert(&item);
+        }
+    }
+}
+
+impl<R,S> fmt::Debug for CountingBloomFilter<R,S> {
+    /// Shows the size of the filter and how many counters are
+    /// non-zero, but not the counters themselves or the hashers
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let nonzero = (0..self.num_entries as usize).filter(|&i| self.counters.get(i) > 0).count();
+        f.debug_struct("CountingBloomFilter")
+            .field("num_entries", &self.num_entries)
+            .field("bits_per_entry", &self.counters.bits_per_val())
+            .field("num_hashes", &self.num_hashes)
+            .field("nonzero_entries", &nonzero)
+            .finish()
+    }
+}
 
 #[cfg(test)]
 mod tests {
-    use super::CountingBloomFilter;
-    use ASMS;
+    use std::collections::hash_map::RandomState;
+    use std::mem;
+    use super::{CountingBloomFilter,intersection_count};
+    use bloom::needed_bits;
+    use hashers::SipBuildHasher;
+    use {ASMS,Intersectable,Unionable};
 
     #[test]
     fn simple() {
@@ -233,6 +630,31 @@ mod tests {
         assert!(!cbf.contains(&2));
     }
 
+    #[test]
+    fn default() {
+        let mut cbf = CountingBloomFilter::default();
+        cbf.insert(&1);
+        cbf.insert(&1);
+        assert!(cbf.estimate_count(&1) >= 2);
+        assert_eq!(cbf.bits_per_entry(),4);
+        assert_eq!(cbf.num_entries(),needed_bits(0.01,1000));
+    }
+
+    #[test]
+    fn getters() {
+        let cbf:CountingBloomFilter = CountingBloomFilter::with_size(1000,4,3);
+        assert_eq!(cbf.num_entries(),1000);
+        assert_eq!(cbf.bits_per_entry(),4);
+        assert_eq!(cbf.num_hashes(),3);
+    }
+
+    #[test]
+    fn memory_bytes() {
+        // 1000 4 bit counters need 4000 bits, rounded up to 63 u64s
+        let cbf:CountingBloomFilter = CountingBloomFilter::with_size(1000,4,3);
+        assert_eq!(cbf.memory_bytes(), mem::size_of::<CountingBloomFilter>() + 504);
+    }
+
     #[test]
     fn remove() {
         let mut cbf:CountingBloomFilter = CountingBloomFilter::with_rate(CountingBloomFilter::bits_for_max(10)
@@ -247,6 +669,225 @@ mod tests {
         assert!(!cbf.contains(&2));
     }
 
+    #[test]
+    fn extend() {
+        let (h1, h2) = SipBuildHasher::pair_from_seed(9);
+        let mut cbf = CountingBloomFilter::with_rate_and_hashers(4,0.01,100,h1,h2);
+        cbf.extend(vec![1,2,1,3,1]);
+        assert_eq!(cbf.estimate_count(&1),3);
+        assert_eq!(cbf.estimate_count(&2),1);
+        assert_eq!(cbf.estimate_count(&3),1);
+        assert!(!cbf.contains(&4));
+
+        // random hashers, so collisions can raise the estimates
+        let cbf = CountingBloomFilter::from_rate_iter(4,0.01,vec!["a","b","a","a"]);
+        assert_eq!(cbf.to_bloom().num_bits(),needed_bits(0.01,4));
+        assert!(cbf.estimate_count(&"a") >= 3);
+        assert!(cbf.estimate_count(&"b") >= 1);
+    }
+
+    #[test]
+    #[cfg(target_pointer_width = "64")]
+    #[should_panic(expected = "can't size a CountingBloomFilter")]
+    fn from_rate_iter_too_long() {
+        CountingBloomFilter::from_rate_iter(4,0.01,0..u32::MAX as usize + 1);
+    }
+
+    #[test]
+    fn iter_counters() {
+        let (h1, h2) = SipBuildHasher::pair_from_seed(20);
+        let mut cbf = CountingBloomFilter::with_size_and_hashers(1000,4,3,h1,h2);
+        assert_eq!(cbf.iter_counters().count(),0);
+        cbf.insert_n(&"a",2);
+        cbf.insert(&"b");
+        let counters: Vec<(usize, u32)> = cbf.iter_counters().collect();
+        assert!(counters.len() <= 6 && counters.len() >= 3);
+        assert!(counters.windows(2).all(|w| w[0].0 < w[1].0));
+        // each insert adds to 3 counters
+        assert_eq!(counters.iter().map(|&(_, c)| c).sum::<u32>(),9);
+        for &(i, c) in &counters {
+            assert_eq!(c as u64,cbf.counters.get(i));
+        }
+    }
+
+    #[test]
+    fn load_factor() {
+        let (h1, h2) = SipBuildHasher::pair_from_seed(22);
+        let mut cbf = CountingBloomFilter::with_rate_and_hashers(4,0.01,100,h1,h2);
+        assert_eq!(cbf.load_factor(),0.0);
+        let mut last = 0.0;
+        for chunk in 0..5 {
+            cbf.extend(chunk*40..(chunk+1)*40);
+            let load = cbf.load_factor();
+            assert!(load > last);
+            last = load;
+        }
+        assert!(last < 1.0);
+        // repeats don't add non-zero counters
+        cbf.extend(0..200);
+        assert_eq!(cbf.load_factor(),last);
+    }
+
+    #[test]
+    fn intersection() {
+        let (h1, h2) = SipBuildHasher::pair_from_seed(24);
+        let mut a = CountingBloomFilter::with_rate_and_hashers(8,0.01,1000,h1,h2);
+        let mut b = CountingBloomFilter::with_rate_and_hashers(8,0.01,1000,h1,h2);
+        // 0..300 are common, inserted twice into a and three times
+        // into b, so they count twice each
+        for i in 0..500 {
+            a.insert_n(&i,2);
+        }
+        for i in 0..300 {
+            b.insert_n(&i,3);
+        }
+        for i in 1000..1200 {
+            b.insert(&i);
+        }
+        let common = intersection_count(&a,&b);
+        // collisions can only raise the estimate
+        assert!((600..700).contains(&common), "common {}", common);
+        assert!(intersection_count(&a,&a) >= 1000);
+        a.clear();
+        assert_eq!(intersection_count(&a,&b),0);
+    }
+
+    #[test]
+    #[should_panic(expected = "not compatible")]
+    fn intersection_incompatible() {
+        let (h1, h2) = SipBuildHasher::pair_from_seed(24);
+        let a = CountingBloomFilter::with_size_and_hashers(1000,4,3,h1,h2);
+        let b = CountingBloomFilter::with_size_and_hashers(1000,8,3,h1,h2);
+        intersection_count(&a,&b);
+    }
+
+    #[test]
+    fn insert_conservative() {
+        // a small filter, so items collide a lot
+        let (h1, h2) = SipBuildHasher::pair_from_seed(27);
+        let mut standard = CountingBloomFilter::with_size_and_hashers(200,8,3,h1,h2);
+        let mut conservative = CountingBloomFilter::with_size_and_hashers(200,8,3,h1,h2);
+        // skewed counts: item i is inserted 1 + i % 5 times
+        for i in 0..100 {
+            for _ in 0..1 + i % 5 {
+                standard.insert(&i);
+                assert_eq!(conservative.insert_conservative(&i),conservative.estimate_count(&i) - 1);
+            }
+        }
+        let mut standard_error = 0;
+        let mut conservative_error = 0;
+        for i in 0..100 {
+            let truth = 1 + i % 5;
+            let s = standard.estimate_count(&i);
+            let c = conservative.estimate_count(&i);
+            assert!(c >= truth);
+            assert!(c <= s);
+            standard_error += s - truth;
+            conservative_error += c - truth;
+        }
+        assert!(conservative_error * 2 < standard_error,
+                "conservative {} standard {}", conservative_error, standard_error);
+    }
+
+    #[test]
+    fn decay() {
+        let (h1, h2) = SipBuildHasher::pair_from_seed(21);
+        let mut cbf = CountingBloomFilter::with_rate_and_hashers(8,0.01,100,h1,h2);
+        cbf.insert_n(&1,40);
+        cbf.insert_n(&2,7);
+        cbf.insert(&3);
+        cbf.decay();
+        assert_eq!(cbf.estimate_count(&1),20);
+        assert_eq!(cbf.estimate_count(&2),3);
+        assert!(!cbf.contains(&3));
+
+        cbf.decay_by(5);
+        assert_eq!(cbf.estimate_count(&1),15);
+        assert!(!cbf.contains(&2));
+        cbf.decay_by(100);
+        assert_eq!(cbf.iter_counters().count(),0);
+    }
+
+    #[test]
+    fn insert_n() {
+        let (h1, h2) = SipBuildHasher::pair_from_seed(6);
+        let mut cbf = CountingBloomFilter::with_rate_and_hashers(4,0.01,100,h1,h2);
+        assert_eq!(cbf.insert_n(&1,5),0);
+        assert_eq!(cbf.estimate_count(&1),5);
+        assert_eq!(cbf.insert_n(&1,5),5);
+        assert_eq!(cbf.insert_get_count(&1),10);
+        // 4 bit counters stop at 15
+        assert_eq!(cbf.insert_n(&1,100),11);
+        assert_eq!(cbf.estimate_count(&1),15);
+        assert_eq!(cbf.insert_n(&2,0),0);
+        assert!(!cbf.contains(&2));
+    }
+
+    #[test]
+    fn insert_checked() {
+        let (h1, h2) = SipBuildHasher::pair_from_seed(8);
+        let mut cbf = CountingBloomFilter::with_rate_and_hashers(2,0.01,100,h1,h2);
+        // 2 bit counters hold up to 3
+        assert_eq!(cbf.insert_checked(&1),(0,false));
+        assert_eq!(cbf.insert_checked(&1),(1,false));
+        assert_eq!(cbf.insert_checked(&1),(2,false));
+        assert_eq!(cbf.insert_checked(&1),(3,true));
+        assert_eq!(cbf.insert_checked(&1),(3,true));
+        assert_eq!(cbf.insert_checked(&2),(0,false));
+    }
+
+    #[test]
+    fn remove_false_positive() {
+        // with one entry every item is contained, and both hashes of
+        // an item hit the same counter.  Saturating at 15 leaves an
+        // odd count, so a remove eventually finds the counter at 1
+        let mut cbf:CountingBloomFilter = CountingBloomFilter::with_size(1,4,2);
+        for _ in 0..8 {
+            cbf.insert(&1);
+        }
+        for _ in 0..7 {
+            assert!(cbf.contains(&2));
+            assert!(cbf.remove(&2) > 0);
+        }
+        assert_eq!(cbf.estimate_count(&1),1);
+        assert_eq!(cbf.remove(&2),1);
+        assert!(!cbf.contains(&1));
+        assert_eq!(cbf.remove(&2),0);
+    }
+
+    #[test]
+    fn remove_n() {
+        let (h1, h2) = SipBuildHasher::pair_from_seed(6);
+        let mut cbf = CountingBloomFilter::with_rate_and_hashers(4,0.01,100,h1,h2);
+        cbf.insert_n(&1,10);
+        cbf.insert(&2);
+        assert_eq!(cbf.remove_n(&1,3),10);
+        assert_eq!(cbf.estimate_count(&1),7);
+        // removing more than were inserted stops at 0
+        assert_eq!(cbf.remove_n(&1,20),7);
+        assert!(!cbf.contains(&1));
+        assert_eq!(cbf.remove_n(&1,1),0);
+        assert_eq!(cbf.estimate_count(&2),1);
+    }
+
+    #[test]
+    fn to_bloom() {
+        let (h1, h2) = SipBuildHasher::pair_from_seed(5);
+        let mut cbf = CountingBloomFilter::with_rate_and_hashers(4,0.01,1000,h1,h2);
+        for i in 0..1000 {
+            cbf.insert(&i);
+        }
+        cbf.insert(&1);
+        cbf.remove(&2);
+        let bf = cbf.to_bloom();
+        assert_eq!(bf.num_bits(),cbf.num_entries());
+        assert_eq!(bf.num_hashes(),cbf.num_hashes());
+        for i in 0..10000 {
+            assert_eq!(bf.contains(&i),cbf.contains(&i));
+        }
+        assert!(bf.contains(&1));
+    }
+
     #[test]
     fn estimate_count() {
         let mut cbf:CountingBloomFilter = CountingBloomFilter::with_rate(4,0.01,100);
@@ -257,5 +898,143 @@ mod tests {
         assert_eq!(cbf.insert_get_count(&1),1);
         assert_eq!(cbf.estimate_count(&1),2);
     }
-}
 
+    #[test]
+    fn estimate_total_insertions() {
+        let (h1, h2) = SipBuildHasher::pair_from_seed(7);
+        let mut cbf = CountingBloomFilter::with_rate_and_hashers(4,0.01,1000,h1,h2);
+        assert_eq!(cbf.estimate_total_insertions(),0);
+        for i in 0..500 {
+            cbf.insert(&i);
+        }
+        cbf.insert(&1);
+        assert_eq!(cbf.estimate_total_insertions(),501);
+        cbf.remove(&1);
+        cbf.remove(&2);
+        assert_eq!(cbf.estimate_total_insertions(),499);
+    }
+
+    #[test]
+    fn estimate_count_opt() {
+        let (h1, h2) = SipBuildHasher::pair_from_seed(7);
+        let mut cbf = CountingBloomFilter::with_rate_and_hashers(4,0.01,100,h1,h2);
+        cbf.insert(&1);
+        cbf.insert(&1);
+        assert_eq!(cbf.estimate_count_opt(&1),Some(2));
+        assert_eq!(cbf.estimate_count_opt(&2),None);
+        cbf.remove_n(&1,2);
+        assert_eq!(cbf.estimate_count_opt(&1),None);
+    }
+
+    #[test]
+    fn union() {
+        let h1 = RandomState::new();
+        let h2 = RandomState::new();
+        let mut cbf1 = CountingBloomFilter::with_rate_and_hashers(4,0.01,100,h1.clone(),h2.clone());
+        let mut cbf2 = CountingBloomFilter::with_rate_and_hashers(4,0.01,100,h1.clone(),h2.clone());
+        cbf1.insert(&1);
+        cbf1.insert(&1);
+        cbf1.insert(&2);
+        cbf2.insert(&1);
+        cbf2.insert(&3);
+
+        let mut max = CountingBloomFilter::with_rate_and_hashers(4,0.01,100,h1,h2);
+        max.union(&cbf1);
+        assert!(max.union_max(&cbf2));
+
+        assert!(cbf1.union(&cbf2));
+        assert_eq!(cbf1.estimate_count(&1),3);
+        assert_eq!(cbf1.estimate_count(&2),1);
+        assert_eq!(cbf1.estimate_count(&3),1);
+        assert_eq!(max.estimate_count(&1),2);
+        assert_eq!(max.estimate_count(&3),1);
+
+        // sums saturate at the largest value a counter can hold
+        for _ in 0..13 {
+            cbf2.insert(&1);
+        }
+        cbf1.union(&cbf2);
+        assert_eq!(cbf1.estimate_count(&1),15);
+    }
+
+    #[test]
+    fn intersect() {
+        let h1 = RandomState::new();
+        let h2 = RandomState::new();
+        let mut cbf1 = CountingBloomFilter::with_rate_and_hashers(4,0.01,100,h1.clone(),h2.clone());
+        let mut cbf2 = CountingBloomFilter::with_rate_and_hashers(4,0.01,100,h1,h2);
+        cbf1.insert(&1);
+        cbf1.insert(&1);
+        cbf1.insert(&2);
+        cbf2.insert(&1);
+        cbf2.insert(&3);
+
+        assert!(cbf1.intersect(&cbf2));
+        assert!(cbf1.contains(&1));
+        assert!(!cbf1.contains(&2));
+        assert!(!cbf1.contains(&3));
+        assert_eq!(cbf1.estimate_count(&1),1);
+    }
+
+    #[test]
+    fn difference() {
+        // fixed hashers, so collisions between the keys below can't
+        // change the surviving counts
+        let (h1, h2) = SipBuildHasher::pair_from_seed(3);
+        let mut cbf1 = CountingBloomFilter::with_rate_and_hashers(4,0.01,100,h1,h2);
+        let mut cbf2 = CountingBloomFilter::with_rate_and_hashers(4,0.01,100,h1,h2);
+        for _ in 0..3 {
+            cbf1.insert(&1);
+        }
+        cbf1.insert(&2);
+        cbf1.insert(&4);
+        cbf2.insert(&1);
+        cbf2.insert(&2);
+        cbf2.insert(&2);
+        cbf2.insert(&3);
+
+        assert!(cbf1.difference(&cbf2));
+        assert_eq!(cbf1.estimate_count(&1),2);
+        assert!(!cbf1.contains(&2));
+        assert!(!cbf1.contains(&3));
+        assert_eq!(cbf1.estimate_count(&4),1);
+    }
+
+    #[test]
+    #[should_panic]
+    fn intersect_mismatch() {
+        let h1 = RandomState::new();
+        let h2 = RandomState::new();
+        let mut cbf1 = CountingBloomFilter::with_size_and_hashers(100,4,3,h1.clone(),h2.clone());
+        let cbf2 = CountingBloomFilter::with_size_and_hashers(100,4,4,h1,h2);
+        cbf1.intersect(&cbf2);
+    }
+
+    #[test]
+    #[should_panic(expected = "at least one entry")]
+    fn zero_entries() {
+        CountingBloomFilter::with_size(0,4,3);
+    }
+
+    #[test]
+    #[should_panic]
+    fn union_mismatch() {
+        let h1 = RandomState::new();
+        let h2 = RandomState::new();
+        let mut cbf1 = CountingBloomFilter::with_size_and_hashers(100,4,3,h1.clone(),h2.clone());
+        let cbf2 = CountingBloomFilter::with_size_and_hashers(100,3,3,h1,h2);
+        cbf1.union(&cbf2);
+    }
+
+    #[test]
+    fn with_rate_for_max_count() {
+        let mut cbf = CountingBloomFilter::with_rate_for_max_count(10,0.01,1000);
+        assert_eq!(cbf.bits_per_entry(),CountingBloomFilter::bits_for_max(10));
+        assert_eq!(cbf.num_entries(),
+                   CountingBloomFilter::with_rate(4,0.01,1000).num_entries());
+        for _ in 0..10 {
+            cbf.insert(&1);
+        }
+        assert_eq!(cbf.estimate_count(&1),10);
+    }
+}
@@ -0,0 +1,540 @@
+// This program is free software; you can redistribute it and/or
+// modify it under the terms of the GNU General Public License as
+// published by the Free Software Foundation; either version 2 of the
+// License, or (at your option) any later version.
+
+// This program is distributed in the hope that it will be useful, but
+// WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the GNU
+// General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with this program; if not, write to the Free Software
+// Foundation, Inc., 51 Franklin Street, Fifth Floor, Boston, MA
+// 02110-1301, USA.
+
+use std::collections::hash_map::RandomState;
+use std::error::Error;
+use std::fmt;
+use std::hash::{BuildHasher,Hash};
+use std::mem;
+
+use super::{ASMS,Resettable};
+use super::hashing::HashIter;
+
+/// Number of fingerprints each bucket holds
+pub const BUCKET_SIZE: usize = 4;
+/// How many fingerprints an insert will move before giving up
+const MAX_KICKS: u32 = 500;
+/// The fraction of slots that can be filled before the filter is
+/// likely to be full
+const MAX_LOAD: f64 = 0.95;
+/// Largest supported fingerprint, as fingerprints are stored in u16s
+const MAX_FINGERPRINT_BITS: u32 = 16;
+/// Smallest supported fingerprint
+const MIN_FINGERPRINT_BITS: u32 = 4;
+
+/// A Cuckoo Filter.  Like a BloomFilter it answers `contains` with
+/// no false negatives and a small chance of false positives, but it
+/// also supports removing items, using much less memory than a
+/// CountingBloomFilter.
+///
+/// Each item is stored as a small fingerprint in one of two buckets.
+/// The second bucket is computed from the first and the fingerprint
+/// alone (partial-key cuckoo hashing), so when both buckets are full
+/// a fingerprint already in the filter can be moved to its other
+/// bucket to make room.  If no room is found after a bounded number
+/// of moves, the last fingerprint moved is kept in a single victim
+/// slot and the filter reports itself full.
+///
+/// `insert` follows `ASMS`, so inserting an item that seems to be
+/// present already stores nothing and returns `false`, and repeated
+/// inserts of one item can't fill the filter.  `try_insert` instead
+/// stores a fingerprint every time, so each copy needs its own
+/// `remove`, and removing one can't cause false negatives for other
+/// items that share a fingerprint.  With either, an item should only
+/// be removed if it was inserted; after `insert`, an item whose
+/// fingerprint was a false positive shares the stored copy, and is
+/// lost along with it.
+///
+/// # Example Usage
+///
+/// ```rust
+/// use bloom::{ASMS,CuckooFilter};
+///
+/// let mut filter = CuckooFilter::with_rate(0.01,1000);
+/// filter.insert(&1);
+/// assert!(filter.contains(&1));
+/// assert!(filter.remove(&1));
+/// assert!(!filter.contains(&1));
+/// ```
+pub struct CuckooFilter<R = RandomState, S = RandomState> {
+    buckets: Vec<u16>,
+    num_buckets: usize,
+    fingerprint_bits: u32,
+    len: usize,
+    victim: Option<(usize, u16)>,
+    kick_state: u64,
+    hash_builder_one: R,
+    hash_builder_two: S,
+}
+
+impl CuckooFilter<RandomState, RandomState> {
+    /// Create a CuckooFilter with room for at least `num_items`
+    /// items, using 16 bit fingerprints.  This gives a false positive
+    /// rate of about 0.0001.
+    pub fn with_capacity(num_items: usize) -> CuckooFilter<RandomState, RandomState> {
+        CuckooFilter::with_capacity_and_hashers(num_items,RandomState::new(),RandomState::new())
+    }
+
+    /// Create a CuckooFilter with room for at least `num_items`
+    /// items, with fingerprints just large enough for a false
+    /// positive rate of `rate` when full.
+    ///
+    /// # Panics
+    /// Panics if `rate` is not strictly between 0 and 1
+    pub fn with_rate(rate: f32, num_items: usize) -> CuckooFilter<RandomState, RandomState> {
+        CuckooFilter::with_rate_and_hashers(rate,num_items,RandomState::new(),RandomState::new())
+    }
+}
+
+impl<R,S> CuckooFilter<R,S>
+    where R: BuildHasher, S: BuildHasher
+{
+    /// Create a CuckooFilter like `with_capacity`, hashing items
+    /// with `hash_builder_one` and `hash_builder_two`.  These MUST
+    /// provide independent hash values.
+    pub fn with_capacity_and_hashers(num_items: usize,
+                                     hash_builder_one: R, hash_builder_two: S) -> CuckooFilter<R,S> {
+        CuckooFilter::new(num_items,MAX_FINGERPRINT_BITS,hash_builder_one,hash_builder_two)
+    }
+
+    /// Create a CuckooFilter like `with_rate`, hashing items with
+    /// `hash_builder_one` and `hash_builder_two`.  These MUST
+    /// provide independent hash values.
+    ///
+    /// # Panics
+    /// Panics if `rate` is not strictly between 0 and 1
+    pub fn with_rate_and_hashers(rate: f32, num_items: usize,
+                                 hash_builder_one: R, hash_builder_two: S) -> CuckooFilter<R,S> {
+        if !(rate > 0.0 && rate < 1.0) {
+            panic!("false positive rate must be between 0 and 1 (exclusive), got {}", rate);
+        }
+        // a lookup compares against 2 * BUCKET_SIZE fingerprints, so
+        // the rate is about 2 * BUCKET_SIZE / 2^fingerprint_bits
+        let bits = ((2 * BUCKET_SIZE) as f64 / rate as f64).log2().ceil() as u32;
+        let bits = bits.clamp(MIN_FINGERPRINT_BITS,MAX_FINGERPRINT_BITS);
+        CuckooFilter::new(num_items,bits,hash_builder_one,hash_builder_two)
+    }
+
+    fn new(num_items: usize, fingerprint_bits: u32,
+           hash_builder_one: R, hash_builder_two: S) -> CuckooFilter<R,S> {
+        // the alternate bucket is found with an xor, so the number of
+        // buckets must be a power of two
+        let mut num_buckets = num_items.div_ceil(BUCKET_SIZE).max(1).next_power_of_two();
+        if num_items as f64 > (num_buckets * BUCKET_SIZE) as f64 * MAX_LOAD {
+            num_buckets *= 2;
+        }
+        CuckooFilter {
+            buckets: vec![0; num_buckets * BUCKET_SIZE],
+            num_buckets: num_buckets,
+            fingerprint_bits: fingerprint_bits,
+            len: 0,
+            victim: None,
+            kick_state: 0x9e3779b97f4a7c15,
+            hash_builder_one: hash_builder_one,
+            hash_builder_two: hash_builder_two,
+        }
+    }
+
+    /// Get the number of items this CuckooFilter has room for
+    pub fn capacity(&self) -> usize {
+        self.buckets.len()
+    }
+
+    /// Get the number of items stored in this CuckooFilter
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    /// Returns true if this CuckooFilter holds no items
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Get the number of bits in each fingerprint
+    pub fn fingerprint_bits(&self) -> u32 {
+        self.fingerprint_bits
+    }
+
+    /// Returns true if this CuckooFilter is full, which happens when
+    /// an insert couldn't find room after moving `MAX_KICKS`
+    /// fingerprints.  Inserts into a full filter fail until an item
+    /// is removed.
+    pub fn is_full(&self) -> bool {
+        self.victim.is_some()
+    }
+
+    /// Insert item into this CuckooFilter, returning an error if the
+    /// filter is full.  Otherwise returns `true` if the filter did
+    /// not have this value present, and `false` if it did.  Unlike
+    /// `insert`, the item is stored either way, so the filter holds
+    /// one copy per call.
+    pub fn try_insert<T: Hash>(&mut self, item: &T) -> Result<bool, CuckooFilterFull> {
+        let (i1, fp) = self.index_and_fingerprint(item);
+        let i2 = self.alt_index(i1,fp);
+        let present = self.contains_at(i1,i2,fp);
+        if self.put(i1,fp) || self.put(i2,fp) {
+            self.len += 1;
+            return Ok(!present);
+        }
+        if self.victim.is_some() {
+            return Err(CuckooFilterFull);
+        }
+
+        let start = if self.next_kick() & 1 == 0 { i1 } else { i2 };
+        self.victim = self.relocate(start,fp);
+        self.len += 1;
+        Ok(!present)
+    }
+
+    /// Remove one copy of item from this CuckooFilter.  Returns
+    /// `true` if a matching fingerprint was found and removed.
+    ///
+    /// Only items that have been inserted should be removed, as
+    /// removing an item that wasn't inserted may remove a different
+    /// item that happens to share its fingerprint.
+    pub fn remove<T: Hash>(&mut self, item: &T) -> bool {
+        let (i1, fp) = self.index_and_fingerprint(item);
+        let i2 = self.alt_index(i1,fp);
+        if let Some((vi, vfp)) = self.victim {
+            if vfp == fp && (vi == i1 || vi == i2) {
+                self.victim = None;
+                self.len -= 1;
+                return true;
+            }
+        }
+        if self.take(i1,fp) || self.take(i2,fp) {
+            self.len -= 1;
+            // there is room now, so try to place the victim
+            if let Some((vi, vfp)) = self.victim.take() {
+                let alt = self.alt_index(vi,vfp);
+                if !(self.put(vi,vfp) || self.put(alt,vfp)) {
+                    self.victim = self.relocate(vi,vfp);
+                }
+            }
+            return true;
+        }
+        false
+    }
+
+    // place fp in bucket idx, kicking fingerprints to their other
+    // bucket until one fits.  If there is still no room after
+    // MAX_KICKS moves, returns the last fingerprint kicked out and
+    // the bucket it belongs in, so nothing already inserted is lost
+    fn relocate(&mut self, idx: usize, fp: u16) -> Option<(usize, u16)> {
+        let mut idx = idx;
+        let mut fp = fp;
+        for _ in 0..MAX_KICKS {
+            let slot = idx * BUCKET_SIZE + (self.next_kick() as usize % BUCKET_SIZE);
+            mem::swap(&mut self.buckets[slot],&mut fp);
+            idx = self.alt_index(idx,fp);
+            if self.put(idx,fp) {
+                return None;
+            }
+        }
+        Some((idx, fp))
+    }
+
+    // the first bucket for item, and its fingerprint.  Fingerprints
+    // are never 0, as that marks an empty slot
+    fn index_and_fingerprint<T: Hash>(&self, item: &T) -> (usize, u16) {
+        let mut hashes = HashIter::from(item,2,&self.hash_builder_one,&self.hash_builder_two);
+        let h1 = hashes.next().unwrap_or(0);
+        let h2 = hashes.next().unwrap_or(0);
+        let idx = (h1 as usize) & (self.num_buckets - 1);
+        let fp = (h2 >> (64 - self.fingerprint_bits)) as u16;
+        (idx, if fp == 0 { 1 } else { fp })
+    }
+
+    // the other bucket a fingerprint in bucket idx could be in.
+    // Applying this twice gives back idx
+    fn alt_index(&self, idx: usize, fp: u16) -> usize {
+        (idx ^ (fp as u64).wrapping_mul(0x5bd1e995) as usize) & (self.num_buckets - 1)
+    }
+
+    fn contains_at(&self, i1: usize, i2: usize, fp: u16) -> bool {
+        if let Some((vi, vfp)) = self.victim {
+            if vfp == fp && (vi == i1 || vi == i2) {
+                return true;
+            }
+        }
+        self.bucket(i1).contains(&fp) || self.bucket(i2).contains(&fp)
+    }
+
+    fn bucket(&self, idx: usize) -> &[u16] {
+        &self.buckets[idx * BUCKET_SIZE..(idx + 1) * BUCKET_SIZE]
+    }
+
+    // put fp in an empty slot of bucket idx, returning false if the
+    // bucket is full
+    fn put(&mut self, idx: usize, fp: u16) -> bool {
+        for slot in self.buckets[idx * BUCKET_SIZE..(idx + 1) * BUCKET_SIZE].iter_mut() {
+            if *slot == 0 {
+                *slot = fp;
+                return true;
+            }
+        }
+        false
+    }
+
+    // remove one copy of fp from bucket idx, returning false if it
+    // isn't there
+    fn take(&mut self, idx: usize, fp: u16) -> bool {
+        for slot in self.buckets[idx * BUCKET_SIZE..(idx + 1) * BUCKET_SIZE].iter_mut() {
+            if *slot == fp {
+                *slot = 0;
+                return true;
+            }
+        }
+        false
+    }
+
+    // xorshift, to pick which fingerprint to move
+    fn next_kick(&mut self) -> u64 {
+        let mut x = self.kick_state;
+        x ^= x << 13;
+        x ^= x >> 7;
+        x ^= x << 17;
+        self.kick_state = x;
+        x
+    }
+}
+
+impl<R,S> ASMS for CuckooFilter<R,S>
+    where R: BuildHasher, S: BuildHasher {
+    /// Insert item into this CuckooFilter.
+    ///
+    /// If the filter did not have this value present, `true` is
+    /// returned.
+    ///
+    /// If the filter did have this value present, `false` is
+    /// returned and nothing is stored.  Use `try_insert` to store
+    /// another copy.
+    ///
+    /// # Panics
+    /// Panics if the item isn't present and the filter is full.  Use
+    /// `try_insert` to handle a full filter.
+    fn insert<T: Hash>(&mut self, item: &T) -> bool {
+        if self.contains(item) {
+            return false;
+        }
+        match self.try_insert(item) {
+            Ok(inserted) => inserted,
+            Err(e) => panic!("{}", e),
+        }
+    }
+
+    /// Check if the item has been inserted into this CuckooFilter.
+    /// This function can return false positives, but not false
+    /// negatives.
+    fn contains<T: Hash>(&self, item: &T) -> bool {
+        let (i1, fp) = self.index_and_fingerprint(item);
+        self.contains_at(i1,self.alt_index(i1,fp),fp)
+    }
+
+    /// Remove all values from this CuckooFilter
+    fn clear(&mut self) {
+        for slot in self.buckets.iter_mut() {
+            *slot = 0;
+        }
+        self.victim = None;
+        self.len = 0;
+    }
+
+    /// The number of bits used by all the fingerprint slots,
+    /// `capacity` times `fingerprint_bits`.  Fingerprints are kept in
+    /// `u16`s, so the memory used can be larger.
+    fn capacity_bits(&self) -> usize {
+        self.buckets.len() * self.fingerprint_bits as usize
+    }
+
+    /// Always 2: one hash picks an item's first bucket, and the other
+    /// its fingerprint, from which the second bucket is derived
+    fn num_hashes(&self) -> u32 {
+        2
+    }
+}
+
+impl<R,S> Resettable for CuckooFilter<R,S>
+    where R: BuildHasher, S: BuildHasher {
+    /// Remove all values from this CuckooFilter, including the number
+    /// of items stored
+    fn reset(&mut self) {
+        self.clear();
+    }
+}
+
+impl<R,S> fmt::Debug for CuckooFilter<R,S> {
+    /// Shows the size of the filter and how many items it holds, but
+    /// not the fingerprints or the hashers
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("CuckooFilter")
+            .field("capacity", &self.buckets.len())
+            .field("fingerprint_bits", &self.fingerprint_bits)
+            .field("len", &self.len)
+            .finish()
+    }
+}
+
+/// The error returned by `CuckooFilter::try_insert` when the filter
+/// is full
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CuckooFilterFull;
+
+impl fmt::Display for CuckooFilterFull {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "CuckooFilter is full")
+    }
+}
+
+impl Error for CuckooFilterFull {}
+
+#[cfg(test)]
+mod tests {
+    use super::{CuckooFilter,CuckooFilterFull};
+    use hashers::SipBuildHasher;
+    use ASMS;
+
+    #[test]
+    fn simple() {
+        let (h1, h2) = SipBuildHasher::pair_from_seed(11);
+        let mut cf = CuckooFilter::with_capacity_and_hashers(100,h1,h2);
+        assert!(cf.insert(&1));
+        assert!(!cf.insert(&1));
+        assert!(cf.contains(&1));
+        assert!(!cf.contains(&2));
+        assert_eq!(cf.len(),1);
+        cf.clear();
+        assert!(!cf.contains(&1));
+        assert!(cf.is_empty());
+    }
+
+    #[test]
+    fn remove() {
+        let (h1, h2) = SipBuildHasher::pair_from_seed(11);
+        let mut cf = CuckooFilter::with_capacity_and_hashers(1000,h1,h2);
+        for i in 0..500 {
+            cf.insert(&i);
+        }
+        for i in 0..250 {
+            assert!(cf.remove(&i));
+        }
+        for i in 250..500 {
+            assert!(cf.contains(&i));
+        }
+        assert_eq!(cf.len(),250);
+
+        // a second copy survives one remove
+        cf.try_insert(&1000).unwrap();
+        cf.try_insert(&1000).unwrap();
+        assert!(cf.remove(&1000));
+        assert!(cf.contains(&1000));
+        assert!(cf.remove(&1000));
+        assert!(!cf.contains(&1000));
+    }
+
+    #[test]
+    fn rate() {
+        let cf = CuckooFilter::with_rate(0.01,1000);
+        // 2 * 4 / 2^10 < 0.01
+        assert_eq!(cf.fingerprint_bits(),10);
+        assert!(cf.capacity() >= 1000);
+        assert_eq!(CuckooFilter::with_rate(0.5,10).fingerprint_bits(),4);
+        assert_eq!(CuckooFilter::with_rate(1e-9,10).fingerprint_bits(),16);
+    }
+
+    #[test]
+    #[should_panic(expected = "false positive rate must be between 0 and 1")]
+    fn bad_rate() {
+        CuckooFilter::with_rate(0.0,10);
+    }
+
+    #[test]
+    fn fills() {
+        let (h1, h2) = SipBuildHasher::pair_from_seed(11);
+        let mut cf = CuckooFilter::with_capacity_and_hashers(1000,h1,h2);
+        let mut inserted: usize = 0;
+        loop {
+            match cf.try_insert(&inserted) {
+                Ok(_) => { inserted += 1; }
+                Err(e) => {
+                    assert_eq!(e,CuckooFilterFull);
+                    break;
+                }
+            }
+        }
+        assert!(cf.is_full());
+        assert_eq!(cf.len(),inserted);
+        // buckets of 4 fill to well over 90% before an insert fails
+        assert!(inserted as f64 > cf.capacity() as f64 * 0.9);
+        for i in 0..inserted {
+            assert!(cf.contains(&i));
+        }
+
+        // removing makes room again, and nothing inserted is lost
+        for i in 0..10usize {
+            assert!(cf.remove(&i));
+        }
+        assert!(!cf.is_full());
+        assert!(cf.try_insert(&inserted).is_ok());
+        for i in 10..inserted+1 {
+            assert!(cf.contains(&i));
+        }
+    }
+
+    #[test]
+    fn insert_repeated() {
+        let (h1, h2) = SipBuildHasher::pair_from_seed(11);
+        let mut cf = CuckooFilter::with_capacity_and_hashers(1000,h1,h2);
+        assert!(cf.insert(&"key"));
+        for _ in 0..100 {
+            assert!(!cf.insert(&"key"));
+        }
+        assert_eq!(cf.len(),1);
+        assert!(!cf.is_full());
+        assert!(cf.remove(&"key"));
+        assert!(!cf.contains(&"key"));
+
+        // try_insert stores a copy each time, so eight fill both
+        // buckets
+        for _ in 0..8 {
+            cf.try_insert(&"key").unwrap();
+        }
+        assert_eq!(cf.len(),8);
+        assert!(!cf.insert(&"key"));
+        assert_eq!(cf.len(),8);
+    }
+
+    #[test]
+    #[should_panic(expected = "CuckooFilter is full")]
+    fn insert_full() {
+        let (h1, h2) = SipBuildHasher::pair_from_seed(11);
+        let mut cf = CuckooFilter::with_capacity_and_hashers(10,h1,h2);
+        for i in 0..1000 {
+            cf.insert(&i);
+        }
+    }
+
+    #[test]
+    fn fpr() {
+        let (h1, h2) = SipBuildHasher::pair_from_seed(11);
+        let mut cf = CuckooFilter::with_capacity_and_hashers(10000,h1,h2);
+        for i in 0..10000 {
+            cf.insert(&i);
+        }
+        let false_positives = (10000..110000).filter(|i| cf.contains(i)).count();
+        // 2 * 4 / 2^16 is about 0.00012
+        assert!(false_positives < 30);
+    }
+}
@@ -0,0 +1,413 @@
+// This program is free software; you can redistribute it and/or
+// modify it under the terms of the GNU General Public License as
+// published by the Free Software Foundation; either version 2 of the
+// License, or (at your option) any later version.
+
+// This program is distributed in the hope that it will be useful, but
+// WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the GNU
+// General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with this program; if not, write to the Free Software
+// Foundation, Inc., 51 Franklin Street, Fifth Floor, Boston, MA
+// 02110-1301, USA.
+
+use std::fmt;
+use std::hash::{BuildHasher,Hash};
+
+use super::bloom::ParseError;
+use super::hashers::SipBuildHasher;
+
+/// Magic number that starts every serialized GolombSet
+const MAGIC: [u8; 4] = *b"BLMG";
+/// Length of the header: magic, number of items, rice bits
+const HEADER_LEN: usize = 13;
+/// Largest supported number of low bits per value, which allows false
+/// positive rates down to 2^-32
+const MAX_RICE_BITS: u32 = 32;
+
+/// A Golomb-coded set.  Like a BloomFilter it answers `contains` with
+/// no false negatives for the items it was built from, and a small
+/// chance of false positives, but it is much smaller, which makes it
+/// suited to sending a fixed set over the network.
+///
+/// Each item is hashed to a value in `0..len * 2^k`, where `2^-k` is
+/// the false positive rate.  The values are sorted, and the gaps
+/// between them are Rice coded: the gap divided by `2^k` in unary,
+/// then its low `k` bits.  This takes about `k + 1.5` bits per item,
+/// where a BloomFilter needs about `1.44 * k`.  The price is that a
+/// GolombSet is immutable, and `contains` has to decode the values up
+/// to the one it's looking for, so takes time linear in the size of
+/// the set.
+///
+/// `from_slice` and `from_bytes` hash with `SipBuildHasher::default_pair`,
+/// whose keys are fixed, so a set serialized with `to_bytes` can be
+/// read back in any process.
+///
+/// # Example Usage
+///
+/// ```rust
+/// use bloom::GolombSet;
+///
+/// let set = GolombSet::from_slice(&["a","b","c"],0.01);
+/// let copy = GolombSet::from_bytes(&set.to_bytes()).unwrap();
+/// assert!(copy.contains(&"b"));
+/// ```
+pub struct GolombSet<R = SipBuildHasher> {
+    data: Vec<u8>,
+    len: u64,
+    rice_bits: u32,
+    hash_builder: R,
+}
+
+impl GolombSet<SipBuildHasher> {
+    /// Create a GolombSet holding `items`, with a false positive rate
+    /// of at most `rate`.  The rate is rounded down to a power of two.
+    ///
+    /// # Panics
+    /// Panics if `rate` is not strictly between 0 and 1, or is below
+    /// 2^-32
+    pub fn from_slice<T: Hash>(items: &[T], rate: f64) -> GolombSet<SipBuildHasher> {
+        GolombSet::from_slice_with_hasher(items,rate,SipBuildHasher::default_pair().0)
+    }
+
+    /// Read a GolombSet written by `to_bytes` from a set created with
+    /// `from_slice`
+    pub fn from_bytes(bytes: &[u8]) -> Result<GolombSet<SipBuildHasher>, ParseError> {
+        GolombSet::from_bytes_with_hasher(bytes,SipBuildHasher::default_pair().0)
+    }
+}
+
+impl<R> GolombSet<R>
+    where R: BuildHasher
+{
+    /// Create a GolombSet like `from_slice`, hashing items with
+    /// `hash_builder`.  Repeated items are only stored once.
+    ///
+    /// # Panics
+    /// Panics if `rate` is not strictly between 0 and 1, or is below
+    /// 2^-32
+    pub fn from_slice_with_hasher<T: Hash>(items: &[T], rate: f64, hash_builder: R) -> GolombSet<R> {
+        if !(rate > 0.0 && rate < 1.0) {
+            panic!("false positive rate must be between 0 and 1 (exclusive), got {}", rate);
+        }
+        let rice_bits = ((1.0 / rate).log2().ceil() as u32).max(1);
+        if rice_bits > MAX_RICE_BITS {
+            panic!("false positive rate {} is below the smallest a GolombSet supports, 2^-{}",
+                   rate,MAX_RICE_BITS);
+        }
+
+        let mut hashes: Vec<u64> = items.iter().map(|item| hash_builder.hash_one(item)).collect();
+        hashes.sort_unstable();
+        hashes.dedup();
+        let len = hashes.len() as u64;
+        let range = len << rice_bits;
+        let mut values: Vec<u64> = hashes.into_iter().map(|h| reduce(h,range)).collect();
+        values.sort_unstable();
+
+        let mut writer = BitWriter::new();
+        let mut last = 0;
+        for value in values {
+            let gap = value - last;
+            for _ in 0..gap >> rice_bits {
+                writer.write_bit(true);
+            }
+            writer.write_bit(false);
+            writer.write_bits(gap,rice_bits);
+            last = value;
+        }
+        GolombSet {
+            data: writer.into_bytes(),
+            len: len,
+            rice_bits: rice_bits,
+            hash_builder: hash_builder,
+        }
+    }
+
+    /// Read a GolombSet written by `to_bytes`, hashing items with
+    /// `hash_builder`, which must hash items the same way as the one
+    /// the set was created with.
+    pub fn from_bytes_with_hasher(bytes: &[u8], hash_builder: R) -> Result<GolombSet<R>, ParseError> {
+        if bytes.len() < HEADER_LEN {
+            return Err(ParseError::Truncated);
+        }
+        if bytes[0..4] != MAGIC {
+            return Err(ParseError::BadMagic);
+        }
+        let mut len_bytes = [0; 8];
+        len_bytes.copy_from_slice(&bytes[4..12]);
+        let len = u64::from_le_bytes(len_bytes);
+        let rice_bits = bytes[12] as u32;
+        if rice_bits == 0 || rice_bits > MAX_RICE_BITS || len.leading_zeros() < rice_bits {
+            return Err(ParseError::InvalidParameters);
+        }
+        let set = GolombSet {
+            data: bytes[HEADER_LEN..].to_vec(),
+            len: len,
+            rice_bits: rice_bits,
+            hash_builder: hash_builder,
+        };
+
+        // every value must decode, and nothing may follow the last
+        let mut values = set.values();
+        for _ in 0..len {
+            if values.next().is_none() {
+                return Err(ParseError::Truncated);
+            }
+        }
+        if set.data.len() > values.reader.pos.div_ceil(8) {
+            return Err(ParseError::TrailingBytes);
+        }
+        Ok(set)
+    }
+
+    /// Check if an item is in this GolombSet.  Items it was created
+    /// from are always found, while other items are found with a
+    /// probability of about `false_positive_rate()`.
+    pub fn contains<T: Hash>(&self, item: &T) -> bool {
+        if self.len == 0 {
+            return false;
+        }
+        let target = reduce(self.hash_builder.hash_one(item),self.len << self.rice_bits);
+        for value in self.values() {
+            if value >= target {
+                return value == target;
+            }
+        }
+        false
+    }
+}
+
+impl<R> GolombSet<R> {
+    /// Write this GolombSet out as bytes, which can be read back
+    /// with `from_bytes`
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(HEADER_LEN + self.data.len());
+        bytes.extend_from_slice(&MAGIC);
+        bytes.extend_from_slice(&self.len.to_le_bytes());
+        bytes.push(self.rice_bits as u8);
+        bytes.extend_from_slice(&self.data);
+        bytes
+    }
+
+    /// Get the number of distinct items this GolombSet was created
+    /// from
+    pub fn len(&self) -> usize {
+        self.len as usize
+    }
+
+    /// Returns true if this GolombSet was created from no items
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// The false positive rate of this GolombSet, `2^-rice_bits`
+    pub fn false_positive_rate(&self) -> f64 {
+        1.0 / (1u64 << self.rice_bits) as f64
+    }
+
+    /// Get the number of low bits of each gap stored after its
+    /// unary coded high part
+    pub fn rice_bits(&self) -> u32 {
+        self.rice_bits
+    }
+
+    // the sorted values, decoded from their gaps
+    fn values(&self) -> Values<'_> {
+        Values {
+            reader: BitReader { bytes: &self.data, pos: 0 },
+            rice_bits: self.rice_bits,
+            last: 0,
+        }
+    }
+}
+
+impl<R> fmt::Debug for GolombSet<R> {
+    /// Shows the size of the set and how many items it holds, but not
+    /// the coded values or the hasher
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("GolombSet")
+            .field("len", &self.len)
+            .field("rice_bits", &self.rice_bits)
+            .field("bytes", &self.data.len())
+            .finish()
+    }
+}
+
+// map h onto 0..range without a division
+fn reduce(h: u64, range: u64) -> u64 {
+    ((h as u128 * range as u128) >> 64) as u64
+}
+
+// appends bits to bytes, most significant bit first
+struct BitWriter {
+    bytes: Vec<u8>,
+    pos: usize,
+}
+
+impl BitWriter {
+    fn new() -> BitWriter {
+        BitWriter { bytes: Vec::new(), pos: 0 }
+    }
+
+    fn write_bit(&mut self, bit: bool) {
+        if self.pos.is_multiple_of(8) {
+            self.bytes.push(0);
+        }
+        if bit {
+            let last = self.bytes.len() - 1;
+            self.bytes[last] |= 0x80 >> (self.pos % 8);
+        }
+        self.pos += 1;
+    }
+
+    // the low num_bits bits of value
+    fn write_bits(&mut self, value: u64, num_bits: u32) {
+        for i in (0..num_bits).rev() {
+            self.write_bit(value >> i & 1 == 1);
+        }
+    }
+
+    fn into_bytes(self) -> Vec<u8> {
+        self.bytes
+    }
+}
+
+struct BitReader<'a> {
+    bytes: &'a [u8],
+    pos: usize,
+}
+
+impl BitReader<'_> {
+    fn read_bit(&mut self) -> Option<bool> {
+        let byte = self.bytes.get(self.pos / 8)?;
+        let bit = byte & (0x80 >> (self.pos % 8)) != 0;
+        self.pos += 1;
+        Some(bit)
+    }
+
+    fn read_bits(&mut self, num_bits: u32) -> Option<u64> {
+        let mut value = 0;
+        for _ in 0..num_bits {
+            value = value << 1 | self.read_bit()? as u64;
+        }
+        Some(value)
+    }
+}
+
+// decodes gaps into the values they separate.  Padding at the end of
+// the last byte decodes as more values, so callers must stop after
+// len of them
+struct Values<'a> {
+    reader: BitReader<'a>,
+    rice_bits: u32,
+    last: u64,
+}
+
+impl Iterator for Values<'_> {
+    type Item = u64;
+
+    fn next(&mut self) -> Option<u64> {
+        let mut quotient: u64 = 0;
+        while self.reader.read_bit()? {
+            quotient += 1;
+        }
+        let remainder = self.reader.read_bits(self.rice_bits)?;
+        self.last = self.last.wrapping_add(quotient << self.rice_bits | remainder);
+        Some(self.last)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::GolombSet;
+    use bloom::{BloomFilter,ParseError};
+    use hashers::SipBuildHasher;
+
+    #[test]
+    fn membership() {
+        let items: Vec<u64> = (0..1000).collect();
+        let (h1, _) = SipBuildHasher::pair_from_seed(34);
+        let set = GolombSet::from_slice_with_hasher(&items,0.01,h1);
+        for i in &items {
+            assert!(set.contains(i));
+        }
+        assert_eq!(set.len(),1000);
+        // 0.01 rounds down to 1/128
+        assert_eq!(set.rice_bits(),7);
+        assert_eq!(set.false_positive_rate(),1.0/128.0);
+    }
+
+    #[test]
+    fn absence() {
+        let items: Vec<u64> = (0..1000).collect();
+        let (h1, _) = SipBuildHasher::pair_from_seed(34);
+        let set = GolombSet::from_slice_with_hasher(&items,0.01,h1);
+        let false_positives = (1000..21000u64).filter(|i| set.contains(i)).count();
+        // 1/128 of 20000 is about 156
+        assert!((100..220).contains(&false_positives),"{} false positives",false_positives);
+    }
+
+    #[test]
+    fn smaller_than_bloom() {
+        let items: Vec<u64> = (0..10000).collect();
+        let (h1, _) = SipBuildHasher::pair_from_seed(34);
+        let set = GolombSet::from_slice_with_hasher(&items,0.01,h1);
+        let bloom:BloomFilter = BloomFilter::with_rate(0.01,10000);
+        assert!(set.to_bytes().len() < bloom.num_bits() / 8);
+        // about k + 1.5 bits per item
+        assert!(set.to_bytes().len() < 10000 * 9 / 8);
+    }
+
+    #[test]
+    fn bytes_round_trip() {
+        let set = GolombSet::from_slice(&["one","two","three","two"],0.001);
+        assert_eq!(set.len(),3);
+        let bytes = set.to_bytes();
+        let copy = GolombSet::from_bytes(&bytes).unwrap();
+        assert_eq!(copy.len(),3);
+        assert_eq!(copy.rice_bits(),set.rice_bits());
+        assert!(copy.contains(&"one"));
+        assert!(copy.contains(&"two"));
+        assert!(copy.contains(&"three"));
+        assert_eq!(copy.to_bytes(),bytes);
+    }
+
+    #[test]
+    fn bad_bytes() {
+        let items: Vec<u64> = (0..100).collect();
+        let (h1, _) = SipBuildHasher::pair_from_seed(34);
+        let bytes = GolombSet::from_slice_with_hasher(&items,0.01,h1).to_bytes();
+        let from = |b: &[u8]| GolombSet::from_bytes_with_hasher(b,SipBuildHasher::pair_from_seed(34).0).err();
+        assert!(from(&bytes).is_none());
+        assert_eq!(from(&bytes[..10]),Some(ParseError::Truncated));
+        assert_eq!(from(&bytes[..bytes.len()-20]),Some(ParseError::Truncated));
+        let mut long = bytes.clone();
+        long.push(0);
+        assert_eq!(from(&long),Some(ParseError::TrailingBytes));
+        let mut magic = bytes.clone();
+        magic[0] = b'X';
+        assert_eq!(from(&magic),Some(ParseError::BadMagic));
+        let mut rice = bytes.clone();
+        rice[12] = 0;
+        assert_eq!(from(&rice),Some(ParseError::InvalidParameters));
+    }
+
+    #[test]
+    fn empty() {
+        let (h1, _) = SipBuildHasher::pair_from_seed(34);
+        let set = GolombSet::from_slice_with_hasher(&[0u64; 0],0.01,h1);
+        assert!(set.is_empty());
+        assert!(!set.contains(&1));
+        let copy = GolombSet::from_bytes_with_hasher(&set.to_bytes(),SipBuildHasher::pair_from_seed(34).0).unwrap();
+        assert!(copy.is_empty());
+    }
+
+    #[test]
+    #[should_panic(expected = "false positive rate must be between 0 and 1")]
+    fn bad_rate() {
+        let (h1, _) = SipBuildHasher::pair_from_seed(34);
+        GolombSet::from_slice_with_hasher(&[1],1.0,h1);
+    }
+}
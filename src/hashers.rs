@@ -0,0 +1,547 @@
+// This program is free software; you can redistribute it and/or
+// modify it under the terms of the GNU General Public License as
+// published by the Free Software Foundation; either version 2 of the
+// License, or (at your option) any later version.
+
+// This program is distributed in the hope that it will be useful, but
+// WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the GNU
+// General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with this program; if not, write to the Free Software
+// Foundation, Inc., 51 Franklin Street, Fifth Floor, Boston, MA
+// 02110-1301, USA.
+
+//! Deterministic hashers that can be passed to the `*_and_hashers`
+//! constructors.  Unlike `RandomState`, these produce the same hash
+//! values in every process, so filters built with them can be
+//! rebuilt or queried elsewhere.  Integers are hashed as little
+//! endian bytes, and `usize` and `isize` (including the lengths of
+//! slices and strings) as 64 bit values, so the hash values are also
+//! the same on every platform.
+//!
+//! A filter needs two independent hashers, for example an
+//! `FnvBuildHasher` and a keyed `SipBuildHasher`:
+//!
+//! ```rust
+//! use bloom::{ASMS,BloomFilter};
+//! use bloom::hashers::{FnvBuildHasher,SipBuildHasher};
+//!
+//! let mut filter = BloomFilter::with_rate_and_hashers(0.01,100,FnvBuildHasher,
+//!                                                     SipBuildHasher::with_keys(1,2));
+//! filter.insert(&"item");
+//! assert!(filter.contains(&"item"));
+//! ```
+
+use core::hash::{BuildHasher,Hasher};
+
+use super::hashing::fmix64;
+
+// Hasher's default integer writes hash native endian bytes, and
+// usize at the platform's pointer width, so the same item would hash
+// differently on big endian or 32 bit targets.  These hash little
+// endian bytes, with usize and isize widened to 64 bits, so hash
+// values are the same everywhere.
+macro_rules! fixed_width_writes {
+    () => {
+        fn write_u16(&mut self, i: u16) {
+            self.write(&i.to_le_bytes());
+        }
+
+        fn write_u32(&mut self, i: u32) {
+            self.write(&i.to_le_bytes());
+        }
+
+        fn write_u64(&mut self, i: u64) {
+            self.write(&i.to_le_bytes());
+        }
+
+        fn write_u128(&mut self, i: u128) {
+            self.write(&i.to_le_bytes());
+        }
+
+        fn write_usize(&mut self, i: usize) {
+            self.write(&(i as u64).to_le_bytes());
+        }
+
+        fn write_isize(&mut self, i: isize) {
+            self.write(&(i as i64).to_le_bytes());
+        }
+    }
+}
+
+/// An implementation of SipHash-1-3 with explicit keys.
+#[derive(Debug, Clone, Copy)]
+pub struct SipHasher13 {
+    v0: u64,
+    v1: u64,
+    v2: u64,
+    v3: u64,
+    tail: u64,
+    ntail: usize,
+    length: usize,
+}
+
+impl SipHasher13 {
+    /// Create a SipHasher13 keyed with `k0` and `k1`
+    pub fn new_with_keys(k0: u64, k1: u64) -> SipHasher13 {
+        SipHasher13 {
+            v0: k0 ^ 0x736f6d6570736575,
+            v1: k1 ^ 0x646f72616e646f6d,
+            v2: k0 ^ 0x6c7967656e657261,
+            v3: k1 ^ 0x7465646279746573,
+            tail: 0,
+            ntail: 0,
+            length: 0,
+        }
+    }
+
+    fn round(&mut self) {
+        self.v0 = self.v0.wrapping_add(self.v1);
+        self.v1 = self.v1.rotate_left(13);
+        self.v1 ^= self.v0;
+        self.v0 = self.v0.rotate_left(32);
+        self.v2 = self.v2.wrapping_add(self.v3);
+        self.v3 = self.v3.rotate_left(16);
+        self.v3 ^= self.v2;
+        self.v0 = self.v0.wrapping_add(self.v3);
+        self.v3 = self.v3.rotate_left(21);
+        self.v3 ^= self.v0;
+        self.v2 = self.v2.wrapping_add(self.v1);
+        self.v1 = self.v1.rotate_left(17);
+        self.v1 ^= self.v2;
+        self.v2 = self.v2.rotate_left(32);
+    }
+
+    fn compress(&mut self, m: u64) {
+        self.v3 ^= m;
+        self.round();
+        self.v0 ^= m;
+    }
+}
+
+impl Hasher for SipHasher13 {
+    fixed_width_writes!();
+
+    fn write(&mut self, msg: &[u8]) {
+        self.length += msg.len();
+        let mut i = 0;
+
+        // fill up any partial word left from the last write
+        if self.ntail != 0 {
+            while self.ntail < 8 && i < msg.len() {
+                self.tail |= (msg[i] as u64) << (8*self.ntail);
+                self.ntail += 1;
+                i += 1;
+            }
+            if self.ntail < 8 {
+                return;
+            }
+            let m = self.tail;
+            self.compress(m);
+            self.tail = 0;
+            self.ntail = 0;
+        }
+
+        while i + 8 <= msg.len() {
+            let mut word = [0u8; 8];
+            word.copy_from_slice(&msg[i..i+8]);
+            self.compress(u64::from_le_bytes(word));
+            i += 8;
+        }
+
+        while i < msg.len() {
+            self.tail |= (msg[i] as u64) << (8*self.ntail);
+            self.ntail += 1;
+            i += 1;
+        }
+    }
+
+    fn finish(&self) -> u64 {
+        let mut state = *self;
+        let b = ((self.length as u64 & 0xff) << 56) | self.tail;
+        state.compress(b);
+        state.v2 ^= 0xff;
+        state.round();
+        state.round();
+        state.round();
+        state.v0 ^ state.v1 ^ state.v2 ^ state.v3
+    }
+}
+
+/// A BuildHasher producing `SipHasher13`s with fixed keys.  Two
+/// SipBuildHashers with different keys produce independent hash
+/// values, so a pair of them can be used as the two hashers of a
+/// filter.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SipBuildHasher {
+    k0: u64,
+    k1: u64,
+}
+
+impl SipBuildHasher {
+    /// Create a SipBuildHasher whose hashers are keyed with `k0` and
+    /// `k1`
+    pub fn with_keys(k0: u64, k1: u64) -> SipBuildHasher {
+        SipBuildHasher {
+            k0: k0,
+            k1: k1,
+        }
+    }
+
+    /// Derive two independently keyed SipBuildHashers from `seed`.
+    /// The same seed always produces the same pair.
+    pub fn pair_from_seed(seed: u64) -> (SipBuildHasher, SipBuildHasher) {
+        let mut state = seed;
+        let mut next = || {
+            state = state.wrapping_add(0x9e3779b97f4a7c15);
+            mix64(state)
+        };
+        let one = SipBuildHasher::with_keys(next(),next());
+        let two = SipBuildHasher::with_keys(next(),next());
+        (one, two)
+    }
+
+    /// The pair of SipBuildHashers `DefaultBloomFilter` uses.  Their
+    /// keys are fixed by this crate, and integers are written as
+    /// little endian bytes, so they give the same hash values in
+    /// every process and on every platform.
+    pub fn default_pair() -> (SipBuildHasher, SipBuildHasher) {
+        SipBuildHasher::pair_from_seed(DEFAULT_SEED)
+    }
+}
+
+// the seed for SipBuildHasher::default_pair.  Changing it would
+// break every serialized DefaultBloomFilter
+const DEFAULT_SEED: u64 = 0x626c6f6f6d2d7273;
+
+impl BuildHasher for SipBuildHasher {
+    type Hasher = SipHasher13;
+
+    fn build_hasher(&self) -> SipHasher13 {
+        SipHasher13::new_with_keys(self.k0,self.k1)
+    }
+}
+
+/// The 64 bit FNV-1a hash.  It is fast and unkeyed, so it gives the
+/// same values everywhere; pair it with a `SipBuildHasher` to get
+/// two independent hashers.
+#[derive(Debug, Clone, Copy)]
+pub struct FnvHasher {
+    hash: u64,
+}
+
+impl Default for FnvHasher {
+    fn default() -> FnvHasher {
+        FnvHasher {
+            hash: 0xcbf29ce484222325,
+        }
+    }
+}
+
+impl Hasher for FnvHasher {
+    fixed_width_writes!();
+
+    fn write(&mut self, msg: &[u8]) {
+        for b in msg {
+            self.hash ^= *b as u64;
+            self.hash = self.hash.wrapping_mul(0x100000001b3);
+        }
+    }
+
+    fn finish(&self) -> u64 {
+        self.hash
+    }
+}
+
+/// A BuildHasher producing `FnvHasher`s
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct FnvBuildHasher;
+
+impl BuildHasher for FnvBuildHasher {
+    type Hasher = FnvHasher;
+
+    fn build_hasher(&self) -> FnvHasher {
+        FnvHasher::default()
+    }
+}
+
+/// The djb2 string hash, `hash * 33 + c` over each byte, starting
+/// from 5381.  It is very fast but weak, so it should only be paired
+/// with a stronger hasher such as `SipBuildHasher`.
+#[derive(Debug, Clone, Copy)]
+pub struct Djb2Hasher {
+    hash: u64,
+}
+
+impl Default for Djb2Hasher {
+    fn default() -> Djb2Hasher {
+        Djb2Hasher {
+            hash: 5381,
+        }
+    }
+}
+
+impl Hasher for Djb2Hasher {
+    fixed_width_writes!();
+
+    fn write(&mut self, msg: &[u8]) {
+        for b in msg {
+            self.hash = self.hash.wrapping_mul(33).wrapping_add(*b as u64);
+        }
+    }
+
+    fn finish(&self) -> u64 {
+        self.hash
+    }
+}
+
+/// A BuildHasher producing `Djb2Hasher`s
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Djb2BuildHasher;
+
+impl BuildHasher for Djb2BuildHasher {
+    type Hasher = Djb2Hasher;
+
+    fn build_hasher(&self) -> Djb2Hasher {
+        Djb2Hasher::default()
+    }
+}
+
+/// Wraps another BuildHasher, writing a salt into each Hasher it
+/// builds before the item is hashed.  Two SaltedBuildHashers wrapping
+/// the same BuildHasher with different salts give different hash
+/// values, so one BuildHasher can serve as both of a filter's
+/// hashers.  How independent the two hash values are depends on the
+/// quality of the wrapped hasher.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SaltedBuildHasher<H> {
+    inner: H,
+    salt: u64,
+}
+
+impl<H> SaltedBuildHasher<H> {
+    /// Create a SaltedBuildHasher that salts hashers built by
+    /// `inner` with `salt`
+    pub fn new(inner: H, salt: u64) -> SaltedBuildHasher<H> {
+        SaltedBuildHasher {
+            inner: inner,
+            salt: salt,
+        }
+    }
+}
+
+impl<H: Clone> SaltedBuildHasher<H> {
+    /// Create two SaltedBuildHashers wrapping `inner` with different
+    /// salts, suitable for passing to the `*_and_hashers`
+    /// constructors
+    pub fn pair(inner: H) -> (SaltedBuildHasher<H>, SaltedBuildHasher<H>) {
+        (SaltedBuildHasher::new(inner.clone(),mix64(1)),
+         SaltedBuildHasher::new(inner,mix64(2)))
+    }
+}
+
+impl<H: BuildHasher> BuildHasher for SaltedBuildHasher<H> {
+    type Hasher = H::Hasher;
+
+    fn build_hasher(&self) -> H::Hasher {
+        let mut hasher = self.inner.build_hasher();
+        hasher.write_u64(self.salt);
+        hasher
+    }
+}
+
+/// The 128 bit x64 variant of MurmurHash3 of `data`, as its low and
+/// high 64 bits.  These are the two halves of Guava's
+/// `Hashing.murmur3_128(seed)`, and with a seed of 0 they are the
+/// hash Guava's BloomFilter uses.
+pub fn murmur3_x64_128(data: &[u8], seed: u32) -> (u64, u64) {
+    const C1: u64 = 0x87c37b91114253d5;
+    const C2: u64 = 0x4cf5ad432745937f;
+    let mut h1 = seed as u64;
+    let mut h2 = seed as u64;
+    let mut blocks = data.chunks_exact(16);
+    for block in &mut blocks {
+        let mut k1 = read_u64_le(&block[..8]);
+        let mut k2 = read_u64_le(&block[8..]);
+        k1 = k1.wrapping_mul(C1).rotate_left(31).wrapping_mul(C2);
+        h1 ^= k1;
+        h1 = h1.rotate_left(27).wrapping_add(h2).wrapping_mul(5).wrapping_add(0x52dce729);
+        k2 = k2.wrapping_mul(C2).rotate_left(33).wrapping_mul(C1);
+        h2 ^= k2;
+        h2 = h2.rotate_left(31).wrapping_add(h1).wrapping_mul(5).wrapping_add(0x38495ab5);
+    }
+    let tail = blocks.remainder();
+    if tail.len() > 8 {
+        let k2 = read_u64_le(&tail[8..]);
+        h2 ^= k2.wrapping_mul(C2).rotate_left(33).wrapping_mul(C1);
+    }
+    if !tail.is_empty() {
+        let k1 = read_u64_le(&tail[..tail.len().min(8)]);
+        h1 ^= k1.wrapping_mul(C1).rotate_left(31).wrapping_mul(C2);
+    }
+    h1 ^= data.len() as u64;
+    h2 ^= data.len() as u64;
+    h1 = h1.wrapping_add(h2);
+    h2 = h2.wrapping_add(h1);
+    h1 = fmix64(h1);
+    h2 = fmix64(h2);
+    h1 = h1.wrapping_add(h2);
+    h2 = h2.wrapping_add(h1);
+    (h1, h2)
+}
+
+// up to 8 bytes as a little-endian u64
+fn read_u64_le(bytes: &[u8]) -> u64 {
+    bytes.iter().rev().fold(0, |acc, &b| (acc << 8) | b as u64)
+}
+
+// the splitmix64 finalizer
+fn mix64(x: u64) -> u64 {
+    let mut z = x;
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d049bb133111eb);
+    z ^ (z >> 31)
+}
+
+#[cfg(test)]
+mod tests {
+    use core::hash::{BuildHasher,Hash,Hasher};
+    use super::{Djb2BuildHasher,Djb2Hasher,FnvBuildHasher,FnvHasher,SaltedBuildHasher,SipBuildHasher,SipHasher13,
+                murmur3_x64_128};
+    use bloom::BloomFilter;
+    use ASMS;
+
+    fn hash_with<B: BuildHasher, T: Hash>(b: &B, item: &T) -> u64 {
+        let mut h = b.build_hasher();
+        item.hash(&mut h);
+        h.finish()
+    }
+
+    #[test]
+    fn sip_split_writes() {
+        let data: Vec<u8> = (0..100).collect();
+        let mut whole = SipHasher13::new_with_keys(1,2);
+        whole.write(&data);
+        for split in 0..data.len() {
+            let mut parts = SipHasher13::new_with_keys(1,2);
+            parts.write(&data[..split]);
+            parts.write(&data[split..]);
+            assert_eq!(parts.finish(), whole.finish());
+        }
+    }
+
+    #[test]
+    fn sip_keys() {
+        let a = SipBuildHasher::with_keys(1,2);
+        assert_eq!(hash_with(&a,&"bloom"), hash_with(&a.clone(),&"bloom"));
+        assert!(hash_with(&a,&"bloom") != hash_with(&SipBuildHasher::with_keys(2,1),&"bloom"));
+        assert!(hash_with(&a,&"bloom") != hash_with(&a,&"bloom!"));
+    }
+
+    // integers hash as their little endian bytes, and usize and isize
+    // as 64 bit values, whatever the platform
+    fn check_fixed_width<B: BuildHasher>(b: &B) {
+        let bytes = |bytes: &[u8]| {
+            let mut h = b.build_hasher();
+            h.write(bytes);
+            h.finish()
+        };
+        assert_eq!(hash_with(b,&0x0102u16),bytes(&[2,1]));
+        assert_eq!(hash_with(b,&0x01020304u32),bytes(&[4,3,2,1]));
+        assert_eq!(hash_with(b,&0x0102030405060708u64),bytes(&[8,7,6,5,4,3,2,1]));
+        assert_eq!(hash_with(b,&1u128),bytes(&[1,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0]));
+        assert_eq!(hash_with(b,&-2i32),bytes(&[0xfe,0xff,0xff,0xff]));
+        assert_eq!(hash_with(b,&5usize),bytes(&[5,0,0,0,0,0,0,0]));
+        assert_eq!(hash_with(b,&-1isize),bytes(&[0xff; 8]));
+        // slices are prefixed with their length as a usize
+        assert_eq!(hash_with(b,&&[7u8][..]),bytes(&[1,0,0,0,0,0,0,0,7]));
+    }
+
+    #[test]
+    fn fixed_width() {
+        check_fixed_width(&SipBuildHasher::with_keys(1,2));
+        check_fixed_width(&FnvBuildHasher);
+        check_fixed_width(&Djb2BuildHasher);
+    }
+
+    #[test]
+    fn pair_from_seed() {
+        let (a1,a2) = SipBuildHasher::pair_from_seed(42);
+        let (b1,b2) = SipBuildHasher::pair_from_seed(42);
+        assert_eq!(a1, b1);
+        assert_eq!(a2, b2);
+        assert!(a1 != a2);
+        assert!(SipBuildHasher::pair_from_seed(43).0 != a1);
+    }
+
+    #[test]
+    fn salted() {
+        let (a, b) = SaltedBuildHasher::pair(FnvBuildHasher);
+        assert!(a != b);
+        assert!(hash_with(&a,&"bloom") != hash_with(&b,&"bloom"));
+        assert!(hash_with(&a,&"bloom") != hash_with(&FnvBuildHasher,&"bloom"));
+        let mut h = FnvHasher::default();
+        h.write_u64(7);
+        "bloom".hash(&mut h);
+        assert_eq!(hash_with(&SaltedBuildHasher::new(FnvBuildHasher,7),&"bloom"),h.finish());
+    }
+
+    #[test]
+    fn djb2() {
+        let mut h = Djb2Hasher::default();
+        assert_eq!(h.finish(),5381);
+        h.write(b"ab");
+        assert_eq!(h.finish(),(5381*33 + 97)*33 + 98);
+    }
+
+    #[test]
+    fn djb2_filter() {
+        let mut b = BloomFilter::with_rate_and_hashers(0.01,100,Djb2BuildHasher,
+                                                       SipBuildHasher::with_keys(1,2));
+        for i in 0..100 {
+            b.insert(&format!("item {}", i));
+        }
+        for i in 0..100 {
+            assert!(b.contains(&format!("item {}", i)));
+        }
+        let false_positives = (100..1100).filter(|i| b.contains(&format!("item {}", i))).count();
+        assert!(false_positives < 30);
+    }
+
+    #[test]
+    fn fnv() {
+        // test vectors from the FNV reference
+        let mut h = FnvHasher::default();
+        assert_eq!(h.finish(),0xcbf29ce484222325);
+        h.write(b"a");
+        assert_eq!(h.finish(),0xaf63dc4c8601ec8c);
+        let mut h = FnvHasher::default();
+        h.write(b"foobar");
+        assert_eq!(h.finish(),0x85944171f73967e8);
+    }
+
+    #[test]
+    fn murmur3() {
+        assert_eq!(murmur3_x64_128(b"",0),(0,0));
+        // Guava's Hashing.murmur3_128() of this string is
+        // 6c1b07bc7bbc4be347939ac4a93c437a
+        assert_eq!(murmur3_x64_128(b"The quick brown fox jumps over the lazy dog",0),
+                   (0xe34bbc7bbc071b6c,0x7a433ca9c49a9347));
+    }
+
+    #[test]
+    fn fnv_sip_filter() {
+        let mut b = BloomFilter::with_rate_and_hashers(0.01,100,FnvBuildHasher,
+                                                       SipBuildHasher::with_keys(3,4));
+        for i in 0..100 {
+            b.insert(&i);
+        }
+        for i in 0..100 {
+            assert!(b.contains(&i));
+        }
+        let false_positives = (100..1100).filter(|i| b.contains(i)).count();
+        assert!(false_positives < 30);
+    }
+}
@@ -1,10 +1,35 @@
 
-use std::hash::{BuildHasher,Hash,Hasher};
 // utilities for hashing
 
+use core::hash::{BuildHasher,Hash,Hasher};
+
+/// An iterator over the hash values for an item, as used by the
+/// filters in this crate to pick which bits or counters an item maps
+/// to.
+///
+/// The item is hashed to two base values `h1` and `h2`, and the
+/// `i`-th value yielded is `h1 + i*h2 + (i^3-i)/6` (with wrapping
+/// arithmetic).  This is enhanced double hashing (Dillinger and
+/// Manolios); the cubic term keeps items whose `h1` and `h2` collide
+/// modulo the filter size from always hitting the same bits.  Take
+/// each value modulo the size of your structure to get an index.
+///
+/// # Example
+///
+/// ```rust
+/// use std::collections::hash_map::RandomState;
+/// use bloom::HashIter;
+///
+/// let one = RandomState::new();
+/// let two = RandomState::new();
+/// let indices: Vec<usize> = HashIter::from(&"item",4,&one,&two)
+///     .map(|h| (h % 1000) as usize)
+///     .collect();
+/// assert_eq!(indices.len(),4);
+/// ```
 pub struct HashIter {
-    h1: u64,
-    h2: u64,
+    x: u64,
+    y: u64,
     i: u32,
     count: u32,
 }
@@ -16,32 +41,131 @@ impl Iterator for HashIter {
         if self.i == self.count {
             return None;
         }
-        let r = match self.i {
-            0 => { self.h1 }
-            1 => { self.h2 }
-            _ => {
-                let p1 = self.h1.wrapping_add(self.i as u64);
-                p1.wrapping_mul(self.h2)
-            }
-        };
+        let r = self.x;
         self.i+=1;
+        self.x = self.x.wrapping_add(self.y);
+        self.y = self.y.wrapping_add(self.i as u64);
         Some(r)
     }
 }
 
 impl HashIter {
+    /// Create a HashIter that yields `count` hash values for `item`,
+    /// hashing it once with a hasher from each of `build_hasher_one`
+    /// and `build_hasher_two`.  The same item and hashers always
+    /// produce the same values.  As for the filters, the two
+    /// BuildHashers MUST produce independent hash values.
     pub fn from<T: Hash, R: BuildHasher, S: BuildHasher>(item: T, count: u32, build_hasher_one:&R, build_hasher_two:&S) -> HashIter {
         let mut hasher_one = build_hasher_one.build_hasher();
         let mut hasher_two = build_hasher_two.build_hasher();
         item.hash(&mut hasher_one);
         item.hash(&mut hasher_two);
-        let h1 = hasher_one.finish();
-        let h2 = hasher_two.finish();
+        HashIter::from_hashes(hasher_one.finish(),hasher_two.finish(),count)
+    }
+
+    /// Create a HashIter like `from`, but hashing `item` only once.
+    /// The second base value comes from writing a salt byte into the
+    /// same hasher after taking the first, which is much cheaper than
+    /// hashing a large item twice.
+    pub fn from_single<T: Hash, R: BuildHasher>(item: T, count: u32, build_hasher: &R) -> HashIter {
+        let mut hasher = build_hasher.build_hasher();
+        item.hash(&mut hasher);
+        let h1 = hasher.finish();
+        hasher.write_u8(SINGLE_HASH_SALT);
+        let h2 = hasher.finish();
+        HashIter::from_hashes(h1,h2,count)
+    }
+
+    /// Create a HashIter like `from`, but for raw bytes, which are
+    /// written straight into the hashers with `Hasher::write`.
+    /// Hashing a `&[u8]` with `Hash` would also write its length, so
+    /// this gives the plain hash of the bytes, as computed by other
+    /// implementations of the same hash function.
+    pub fn from_bytes<R: BuildHasher, S: BuildHasher>(bytes: &[u8], count: u32, build_hasher_one:&R, build_hasher_two:&S) -> HashIter {
+        let mut hasher_one = build_hasher_one.build_hasher();
+        let mut hasher_two = build_hasher_two.build_hasher();
+        hasher_one.write(bytes);
+        hasher_two.write(bytes);
+        HashIter::from_hashes(hasher_one.finish(),hasher_two.finish(),count)
+    }
+
+    /// Create a HashIter like `from_single`, but for raw bytes, as
+    /// for `from_bytes`
+    pub fn from_single_bytes<R: BuildHasher>(bytes: &[u8], count: u32, build_hasher: &R) -> HashIter {
+        let mut hasher = build_hasher.build_hasher();
+        hasher.write(bytes);
+        let h1 = hasher.finish();
+        hasher.write_u8(SINGLE_HASH_SALT);
+        let h2 = hasher.finish();
+        HashIter::from_hashes(h1,h2,count)
+    }
+
+    /// Create a HashIter that yields `count` hash values from the
+    /// base values `h1` and `h2`, for items that have already been
+    /// hashed.  `h1` and `h2` must be independent hashes of the item.
+    pub fn from_hashes(h1: u64, h2: u64, count: u32) -> HashIter {
         HashIter {
-            h1: h1,
-            h2: h2,
+            x: h1,
+            y: h2,
             i: 0,
             count: count,
         }
     }
 }
+
+// written after the item to derive the second hash in from_single
+const SINGLE_HASH_SALT: u8 = 0x5b;
+
+/// The MurmurHash3 finalizer, which mixes every bit of `x` into every
+/// bit of the result.
+pub fn fmix64(x: u64) -> u64 {
+    let mut k = x;
+    k ^= k >> 33;
+    k = k.wrapping_mul(0xff51afd7ed558ccd);
+    k ^= k >> 33;
+    k = k.wrapping_mul(0xc4ceb9fe1a85ec53);
+    k ^ (k >> 33)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::hash_map::RandomState;
+    use std::hash::{BuildHasher,Hash,Hasher};
+    use super::HashIter;
+
+    fn hash_with<B: BuildHasher>(b: &B, item: &u32) -> u64 {
+        let mut h = b.build_hasher();
+        item.hash(&mut h);
+        h.finish()
+    }
+
+    #[test]
+    fn enhanced_double_hashing() {
+        let s1 = RandomState::new();
+        let s2 = RandomState::new();
+        let h1 = hash_with(&s1,&7);
+        let h2 = hash_with(&s2,&7);
+        let hashes: Vec<u64> = HashIter::from(7,20,&s1,&s2).collect();
+        assert_eq!(hashes.len(),20);
+        for (i,h) in hashes.iter().enumerate() {
+            let i = i as u64;
+            let expected = h1.wrapping_add(i.wrapping_mul(h2))
+                .wrapping_add((i*i*i-i)/6);
+            assert_eq!(*h,expected);
+        }
+    }
+
+    #[test]
+    fn single() {
+        let s = RandomState::new();
+        let a: Vec<u64> = HashIter::from_single("bloom",5,&s).collect();
+        let b: Vec<u64> = HashIter::from_single("bloom",5,&s).collect();
+        let c: Vec<u64> = HashIter::from_single("bloom!",5,&s).collect();
+        assert_eq!(a.len(),5);
+        assert_eq!(a,b);
+        assert!(a != c);
+        // the two base hashes must differ, or every index would be
+        // the same cubic offset from h1
+        assert!(a[0] != a[1].wrapping_sub(a[0]));
+    }
+}
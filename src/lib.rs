@@ -18,8 +18,15 @@
 
 
 //! An implementation of various Approximate Set Membership structures
-//! in Rust.  Currently included are a standard Bloom Filter, and the
-//! simplest kind of Counting Bloom Filter.
+//! in Rust.  Currently included are a standard Bloom Filter, the
+//! simplest kind of Counting Bloom Filter (with bit-packed or native
+//! integer counters), a Scalable Bloom Filter that grows as items
+//! are inserted, a cache friendly Blocked Bloom Filter, a
+//! Partitioned Bloom Filter with one slice of bits per hash, a Bloom
+//! Filter that can be shared between threads, a Cuckoo Filter that
+//! supports removing items, a Stable Bloom Filter for recent
+//! membership in unbounded streams, and an XOR Filter and a
+//! Golomb-coded set for sets that never change.
 //!
 //! # Usage
 //!
@@ -38,6 +45,32 @@
 //! extern crate bloom;
 //! ```
 //!
+//! # no_std
+//!
+//! The standard BloomFilter, `ValueVec` and `HashIter` can be used
+//! without the standard library, as long as an allocator is
+//! available, by turning off the default `std` feature:
+//!
+//! ```toml
+//! [dependencies]
+//! bloom = { version = "0.3", default-features = false }
+//! ```
+//!
+//! Without `std` there is no `RandomState`, and the sizing functions
+//! need floating point functions from `std`, so filters must be
+//! created with an explicit size and hashers, for example those in
+//! the `hashers` module:
+//!
+//! ```rust
+//! use bloom::{ASMS,BloomFilter};
+//! use bloom::hashers::SipBuildHasher;
+//!
+//! let (one, two) = SipBuildHasher::pair_from_seed(42);
+//! let mut filter = BloomFilter::with_size_and_hashers(9586,7,one,two);
+//! filter.insert(&1);
+//! assert!(filter.contains(&1));
+//! ```
+//!
 //! # Bloom Filters
 //!
 //! A Bloom Filter is an Approximate Set Membership structure, which
@@ -103,27 +136,122 @@
 #![crate_type = "rlib"]
 
 #![cfg_attr(feature = "do-bench", feature(test))]
+#![cfg_attr(not(feature = "std"), no_std)]
 
+// core is already in scope without std
+#[cfg(feature = "std")]
 extern crate core;
 extern crate bit_vec;
-use std::hash::Hash;
+#[cfg(not(feature = "std"))]
+#[macro_use]
+extern crate alloc;
+// so alloc::vec::Vec and friends can be named the same way with or
+// without std
+#[cfg(feature = "std")]
+extern crate std as alloc;
+use core::hash::Hash;
 
 mod hashing;
+pub use hashing::HashIter;
+mod popcount;
+pub mod hashers;
+
+// the hashers filters use when none are given.  Without std there is
+// no RandomState, so these are only a default for the type
+// parameters, and hashers must be passed to the constructors.
+#[cfg(feature = "std")]
+type DefaultBuildHasher = std::collections::hash_map::RandomState;
+#[cfg(not(feature = "std"))]
+type DefaultBuildHasher = hashers::SipBuildHasher;
 
 pub mod bloom;
-pub use bloom::{BloomFilter,optimal_num_hashes,needed_bits};
+pub use bloom::{BloomFilter,DefaultBloomFilter,ParseError,union_all};
+#[cfg(feature = "std")]
+pub use bloom::{optimal_num_hashes,needed_bits,optimal_num_hashes_u64,needed_bits_u64,jaccard_similarity};
+#[cfg(feature = "std")]
+pub use bloom::{optimal_num_hashes_capped,optimal_num_hashes_bounded,MIN_NUM_HASHES,MAX_NUM_HASHES};
+#[cfg(feature = "std")]
+pub use bloom::{union_cardinality,achievable_rate};
+#[cfg(feature = "std")]
+pub use bloom::{DEFAULT_RATE,DEFAULT_EXPECTED_ITEMS};
+
+#[cfg(feature = "std")]
+pub mod builder;
+#[cfg(feature = "std")]
+pub use builder::{BloomFilterBuilder,BuildError};
 
+#[cfg(feature = "std")]
 pub mod counting;
-pub use counting::CountingBloomFilter;
+#[cfg(feature = "std")]
+pub use counting::{CountingBloomFilter,intersection_count};
+
+#[cfg(feature = "std")]
+pub mod native_counting;
+#[cfg(feature = "std")]
+pub use native_counting::{Counter,NativeCountingBloomFilter};
 
 pub mod valuevec;
-pub use valuevec::ValueVec;
+pub use valuevec::{ValueVec,ValueVecError};
+
+#[cfg(feature = "std")]
+pub mod scalable;
+#[cfg(feature = "std")]
+pub use scalable::ScalableBloomFilter;
+
+#[cfg(feature = "std")]
+pub mod blocked;
+#[cfg(feature = "std")]
+pub use blocked::BlockedBloomFilter;
+
+#[cfg(feature = "std")]
+pub mod partitioned;
+#[cfg(feature = "std")]
+pub use partitioned::PartitionedBloomFilter;
+
+#[cfg(feature = "std")]
+pub mod concurrent;
+#[cfg(feature = "std")]
+pub use concurrent::ConcurrentBloomFilter;
+
+#[cfg(feature = "std")]
+pub mod cuckoo;
+#[cfg(feature = "std")]
+pub use cuckoo::{CuckooFilter,CuckooFilterFull};
+
+#[cfg(feature = "std")]
+pub mod stable;
+#[cfg(feature = "std")]
+pub use stable::StableBloomFilter;
+
+#[cfg(feature = "std")]
+pub mod xor;
+#[cfg(feature = "std")]
+pub use xor::XorFilter;
+
+#[cfg(feature = "std")]
+pub mod golomb;
+#[cfg(feature = "std")]
+pub use golomb::GolombSet;
 
 /// Stanard filter functions
 pub trait ASMS {
     fn insert<T: Hash>(& mut self,item: &T) -> bool;
     fn contains<T: Hash>(&self, item: &T) -> bool;
     fn clear(&mut self);
+
+    /// The number of bits of storage the filter's bits, counters or
+    /// fingerprints take up, not counting any per-value padding
+    fn capacity_bits(&self) -> usize;
+
+    /// The number of hash values computed for each item
+    fn num_hashes(&self) -> u32;
+}
+
+/// Structures that can be emptied in place, to be reused without
+/// allocating again.  `reset` sets every bit or counter back to 0, and
+/// also clears any counts of what has been inserted.
+pub trait Resettable {
+    fn reset(&mut self);
 }
 
 /// Filters that implement this trait can be intersected with filters
@@ -153,3 +281,76 @@ pub trait Unionable {
 /// Filters than are Combineable can be unioned and intersected
 pub trait Combineable: Intersectable + Unionable {}
 impl<T> Combineable for T where T: Intersectable + Unionable {}
+
+#[cfg(test)]
+mod tests {
+    use {ASMS,Resettable,ValueVec,BlockedBloomFilter,BloomFilter,ConcurrentBloomFilter,CountingBloomFilter,CuckooFilter,
+         NativeCountingBloomFilter,PartitionedBloomFilter,ScalableBloomFilter,StableBloomFilter};
+
+    // empty anything that can be reset
+    fn reset_all<F: Resettable>(filters: &mut [F]) {
+        for f in filters.iter_mut() {
+            f.reset();
+        }
+    }
+
+    #[test]
+    fn reset() {
+        let mut blooms:Vec<BloomFilter> = (0..3).map(|_| BloomFilter::with_rate(0.01,100)).collect();
+        for b in blooms.iter_mut() {
+            b.insert(&1);
+        }
+        reset_all(&mut blooms);
+        for b in &blooms {
+            assert!(b.is_empty());
+            assert_eq!(b.num_inserts(),0);
+        }
+
+        let mut counting:Vec<CountingBloomFilter> = vec![CountingBloomFilter::with_rate(4,0.01,100)];
+        counting[0].insert(&1);
+        counting[0].insert(&1);
+        reset_all(&mut counting);
+        assert_eq!(counting[0].estimate_count(&1),0);
+
+        let mut values = vec![ValueVec::new(4,10)];
+        values[0].set(3,9);
+        reset_all(&mut values);
+        assert_eq!(values[0].iter().max(),Some(0));
+    }
+
+    // what generic code can learn about a filter's shape
+    fn shape<F: ASMS>(filter: &F) -> (usize, u32) {
+        (filter.capacity_bits(), filter.num_hashes())
+    }
+
+    #[test]
+    fn asms_shape() {
+        let b:BloomFilter = BloomFilter::with_size(1000,4);
+        assert_eq!(shape(&b),(1000,4));
+        let cbf:CountingBloomFilter = CountingBloomFilter::with_size(1000,4,3);
+        assert_eq!(shape(&cbf),(4000,3));
+        let ncbf:NativeCountingBloomFilter<u16> = NativeCountingBloomFilter::with_size(1000,3);
+        assert_eq!(shape(&ncbf),(16000,3));
+        let blocked:BlockedBloomFilter = BlockedBloomFilter::with_size(1024,5);
+        // one more hash picks the block
+        assert_eq!(blocked.num_hashes(),5);
+        assert_eq!(shape(&blocked),(blocked.num_bits(),6));
+        let partitioned:PartitionedBloomFilter = PartitionedBloomFilter::with_size(1000,4);
+        assert_eq!(shape(&partitioned),(1000,4));
+        let concurrent:ConcurrentBloomFilter = ConcurrentBloomFilter::with_size(1000,4);
+        assert_eq!(shape(&concurrent),(1000,4));
+        let stable:StableBloomFilter = StableBloomFilter::with_size(1000,3,4,10);
+        assert_eq!(shape(&stable),(3000,4));
+        let cuckoo:CuckooFilter = CuckooFilter::with_capacity(1000);
+        assert_eq!(shape(&cuckoo),(cuckoo.capacity() * 16,2));
+
+        let mut scalable = ScalableBloomFilter::new(10,0.01,2);
+        let (bits, hashes) = shape(&scalable);
+        assert_eq!(bits,scalable.num_bits());
+        for i in 0..100 {
+            scalable.insert(&i);
+        }
+        let (more_bits, more_hashes) = shape(&scalable);
+        assert!(more_bits > bits && more_hashes > hashes);
+    }
+}
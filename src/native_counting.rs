@@ -0,0 +1,361 @@
+// This program is free software; you can redistribute it and/or
+// modify it under the terms of the GNU General Public License as
+// published by the Free Software Foundation; either version 2 of the
+// License, or (at your option) any later version.
+
+// This program is distributed in the hope that it will be useful, but
+// WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the GNU
+// General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with this program; if not, write to the Free Software
+// Foundation, Inc., 51 Franklin Street, Fifth Floor, Boston, MA
+// 02110-1301, USA.
+
+use std::collections::hash_map::RandomState;
+use std::hash::{BuildHasher,Hash};
+use std::mem;
+
+use super::{ASMS,Resettable};
+use super::bloom::{needed_bits,optimal_num_hashes};
+use super::hashing::HashIter;
+
+/// An unsigned integer type that can be used for the counters of a
+/// `NativeCountingBloomFilter`.  Implemented for `u8`, `u16` and
+/// `u32`.
+pub trait Counter: Copy + Default + PartialEq {
+    /// The largest value a counter can hold
+    const MAX: Self;
+
+    /// Add one, stopping at `MAX`
+    fn increment(self) -> Self;
+
+    /// Subtract one, stopping at 0
+    fn decrement(self) -> Self;
+
+    /// The value of this counter
+    fn to_u64(self) -> u64;
+}
+
+macro_rules! impl_counter {
+    ($t:ty) => {
+        impl Counter for $t {
+            const MAX: $t = <$t>::MAX;
+
+            fn increment(self) -> $t {
+                self.saturating_add(1)
+            }
+
+            fn decrement(self) -> $t {
+                self.saturating_sub(1)
+            }
+
+            fn to_u64(self) -> u64 {
+                self as u64
+            }
+        }
+    }
+}
+
+impl_counter!(u8);
+impl_counter!(u16);
+impl_counter!(u32);
+
+/// A counting bloom filter that keeps each counter in a native
+/// integer type `C` (`u8`, `u16` or `u32`) rather than packing them
+/// into a `ValueVec` like `CountingBloomFilter`.  Reading and writing
+/// a counter is then a plain load or store, which is faster, at the
+/// cost of using a whole `C` per counter.  Counters stop at `C::MAX`.
+///
+/// # Example Usage
+///
+/// ```rust
+/// use bloom::{ASMS,NativeCountingBloomFilter};
+///
+/// let mut filter: NativeCountingBloomFilter<u16> = NativeCountingBloomFilter::with_rate(0.01,1000);
+/// filter.insert(&1);
+/// filter.insert(&1);
+/// assert_eq!(filter.estimate_count(&1),2);
+/// filter.remove(&1);
+/// assert_eq!(filter.estimate_count(&1),1);
+/// ```
+pub struct NativeCountingBloomFilter<C = u8, R = RandomState, S = RandomState> {
+    counters: Vec<C>,
+    num_entries: u64,
+    num_hashes: u32,
+    hash_builder_one: R,
+    hash_builder_two: S,
+}
+
+impl<C: Counter> NativeCountingBloomFilter<C,RandomState,RandomState> {
+    /// Create a new NativeCountingBloomFilter with `num_entries`
+    /// counters, using `num_hashes` hashes
+    ///
+    /// # Panics
+    /// Panics if `num_entries` is 0
+    pub fn with_size(num_entries: usize, num_hashes: u32) -> NativeCountingBloomFilter<C,RandomState,RandomState> {
+        NativeCountingBloomFilter::with_size_and_hashers(num_entries,num_hashes,
+                                                         RandomState::new(),RandomState::new())
+    }
+
+    /// Create a NativeCountingBloomFilter that expects to hold
+    /// `expected_num_items`.  The filter will be sized to have a
+    /// false positive rate of the value specified in `rate`.
+    pub fn with_rate(rate: f32, expected_num_items: u32) -> NativeCountingBloomFilter<C,RandomState,RandomState> {
+        NativeCountingBloomFilter::with_rate_and_hashers(rate,expected_num_items,
+                                                         RandomState::new(),RandomState::new())
+    }
+}
+
+impl<C,R,S> NativeCountingBloomFilter<C,R,S>
+    where C: Counter, R: BuildHasher, S: BuildHasher
+{
+    /// Create a new NativeCountingBloomFilter with `num_entries`
+    /// counters, `num_hashes` hashes, and the two specified
+    /// HashBuilders.  The HashBuilders MUST provide independent hash
+    /// values, as for `CountingBloomFilter::with_size_and_hashers`.
+    ///
+    /// # Panics
+    /// Panics if `num_entries` is 0
+    pub fn with_size_and_hashers(num_entries: usize, num_hashes: u32,
+                                 hash_builder_one: R, hash_builder_two: S) -> NativeCountingBloomFilter<C,R,S> {
+        if num_entries == 0 {
+            panic!("NativeCountingBloomFilter must have at least one entry");
+        }
+        NativeCountingBloomFilter {
+            counters: vec![C::default(); num_entries],
+            num_entries: num_entries as u64,
+            num_hashes: num_hashes,
+            hash_builder_one: hash_builder_one,
+            hash_builder_two: hash_builder_two,
+        }
+    }
+
+    /// Create a NativeCountingBloomFilter that expects to hold
+    /// `expected_num_items` with a false positive rate of `rate`,
+    /// hashing items with `hash_builder_one` and `hash_builder_two`.
+    pub fn with_rate_and_hashers(rate: f32, expected_num_items: u32,
+                                 hash_builder_one: R, hash_builder_two: S) -> NativeCountingBloomFilter<C,R,S> {
+        let entries = needed_bits(rate,expected_num_items);
+        NativeCountingBloomFilter::with_size_and_hashers(entries,optimal_num_hashes(entries,expected_num_items),
+                                                         hash_builder_one,hash_builder_two)
+    }
+
+    /// Get the number of counters this NativeCountingBloomFilter is
+    /// using
+    pub fn num_entries(&self) -> usize {
+        self.num_entries as usize
+    }
+
+    /// Get the number of hash functions this
+    /// NativeCountingBloomFilter is using
+    pub fn num_hashes(&self) -> u32 {
+        self.num_hashes
+    }
+
+    /// Inserts an item, returns the estimated count of the number of
+    /// times this item had previously been inserted (not counting
+    /// this insertion)
+    pub fn insert_get_count<T: Hash>(&mut self, item: &T) -> u32 {
+        let mut min = u64::MAX;
+        for h in self.hashes(item) {
+            let idx = (h % self.num_entries) as usize;
+            let cur = self.counters[idx];
+            min = min.min(cur.to_u64());
+            self.counters[idx] = cur.increment();
+        }
+        clamp_count(min)
+    }
+
+    /// Remove an item.  Returns an upper bound of the number of times
+    /// this item had been inserted previously (i.e. the count before
+    /// this remove).  Returns 0 if item was never inserted.
+    ///
+    /// As for `CountingBloomFilter::remove`, removing a false
+    /// positive lowers its counters, which may remove other items.
+    /// Counters never drop below 0.
+    pub fn remove<T: Hash>(&mut self, item: &T) -> u32 {
+        let count = self.estimate_count(item);
+        if count == 0 {
+            return 0;
+        }
+        for h in self.hashes(item) {
+            let idx = (h % self.num_entries) as usize;
+            self.counters[idx] = self.counters[idx].decrement();
+        }
+        count
+    }
+
+    /// Return an estimate of the number of times `item` has been
+    /// inserted into the filter.  The estimate is an upper bound on
+    /// the count, unless the item's counters have reached `C::MAX`.
+    pub fn estimate_count<T: Hash>(&self, item: &T) -> u32 {
+        let min = self.hashes(item)
+            .map(|h| self.counters[(h % self.num_entries) as usize].to_u64())
+            .min()
+            .unwrap_or(u64::MAX);
+        clamp_count(min)
+    }
+
+    // the hash values for item
+    fn hashes<T: Hash>(&self, item: &T) -> HashIter {
+        HashIter::from(item,
+                       self.num_hashes,
+                       &self.hash_builder_one,
+                       &self.hash_builder_two)
+    }
+}
+
+// a u32 counter or a filter with no hashes can exceed the counts a
+// filter reports, so saturate at u32::MAX
+fn clamp_count(count: u64) -> u32 {
+    if count > u32::MAX as u64 {
+        u32::MAX
+    } else {
+        count as u32
+    }
+}
+
+impl<C,R,S> ASMS for NativeCountingBloomFilter<C,R,S>
+    where C: Counter, R: BuildHasher, S: BuildHasher {
+    /// Inserts an item, returns true if this item was already in the
+    /// filter any number of times
+    fn insert<T: Hash>(&mut self, item: &T) -> bool {
+        self.insert_get_count(item) > 0
+    }
+
+    /// Check if the item has been inserted into this
+    /// NativeCountingBloomFilter.  This function can return false
+    /// positives, but not false negatives.
+    fn contains<T: Hash>(&self, item: &T) -> bool {
+        self.hashes(item).all(|h| self.counters[(h % self.num_entries) as usize] != C::default())
+    }
+
+    /// Remove all values from this NativeCountingBloomFilter
+    fn clear(&mut self) {
+        for c in self.counters.iter_mut() {
+            *c = C::default();
+        }
+    }
+
+    /// The number of bits used by all the counters, `num_entries`
+    /// times the width of `C`
+    fn capacity_bits(&self) -> usize {
+        self.counters.len() * mem::size_of::<C>() * 8
+    }
+
+    /// The number of hash functions this NativeCountingBloomFilter
+    /// is using
+    fn num_hashes(&self) -> u32 {
+        self.num_hashes
+    }
+}
+
+impl<C,R,S> Resettable for NativeCountingBloomFilter<C,R,S>
+    where C: Counter, R: BuildHasher, S: BuildHasher {
+    /// Remove all values from this NativeCountingBloomFilter
+    fn reset(&mut self) {
+        self.clear();
+    }
+}
+
+#[cfg(test)]
+extern crate rand;
+
+#[cfg(feature = "do-bench")]
+#[cfg(test)]
+mod bench {
+    extern crate test;
+    use self::test::Bencher;
+    use native_counting::rand::{self,Rng};
+
+    use super::NativeCountingBloomFilter;
+    use counting::CountingBloomFilter;
+    use ASMS;
+
+    const CNT: u32 = 500000;
+    const RATE: f32 = 0.01;
+
+    #[bench]
+    fn native_insert_benchmark(b: &mut Bencher) {
+        let mut cbf: NativeCountingBloomFilter<u8> = NativeCountingBloomFilter::with_rate(RATE,CNT);
+        let mut rng = rand::thread_rng();
+        b.iter(|| {
+            let v = rng.gen::<i32>();
+            cbf.insert(&v);
+        })
+    }
+
+    #[bench]
+    fn packed_insert_benchmark(b: &mut Bencher) {
+        // the same 8 bit counters, packed into a ValueVec
+        let mut cbf = CountingBloomFilter::with_rate(8,RATE,CNT);
+        let mut rng = rand::thread_rng();
+        b.iter(|| {
+            let v = rng.gen::<i32>();
+            cbf.insert(&v);
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Counter,NativeCountingBloomFilter};
+    use hashers::SipBuildHasher;
+    use ASMS;
+
+    #[test]
+    fn simple() {
+        let (h1, h2) = SipBuildHasher::pair_from_seed(19);
+        let mut cbf: NativeCountingBloomFilter<u8,_,_> = NativeCountingBloomFilter::with_rate_and_hashers(0.01,100,h1,h2);
+        assert!(!cbf.insert(&1));
+        assert!(cbf.insert(&1));
+        assert!(cbf.contains(&1));
+        assert!(!cbf.contains(&2));
+        assert_eq!(cbf.estimate_count(&1),2);
+        assert_eq!(cbf.remove(&1),2);
+        assert_eq!(cbf.remove(&2),0);
+        assert_eq!(cbf.estimate_count(&1),1);
+        cbf.clear();
+        assert!(!cbf.contains(&1));
+    }
+
+    #[test]
+    fn saturates_u8() {
+        let (h1, h2) = SipBuildHasher::pair_from_seed(19);
+        let mut cbf: NativeCountingBloomFilter<u8,_,_> = NativeCountingBloomFilter::with_rate_and_hashers(0.01,100,h1,h2);
+        for _ in 0..300 {
+            cbf.insert(&1);
+        }
+        assert_eq!(cbf.estimate_count(&1),255);
+        assert_eq!(cbf.insert_get_count(&1),255);
+        assert_eq!(cbf.remove(&1),255);
+        assert_eq!(cbf.estimate_count(&1),254);
+    }
+
+    #[test]
+    fn saturates_u16() {
+        let (h1, h2) = SipBuildHasher::pair_from_seed(19);
+        let mut cbf: NativeCountingBloomFilter<u16,_,_> = NativeCountingBloomFilter::with_rate_and_hashers(0.01,100,h1,h2);
+        for _ in 0..70000 {
+            cbf.insert(&1);
+        }
+        assert_eq!(cbf.estimate_count(&1),65535);
+        assert!(!cbf.contains(&2));
+    }
+
+    #[test]
+    fn counter_limits() {
+        assert_eq!(u8::MAX.increment(),u8::MAX);
+        assert_eq!(<u32 as Counter>::MAX.increment(),u32::MAX);
+        assert_eq!(0u16.decrement(),0);
+        assert_eq!(<u32 as Counter>::MAX.to_u64(),u32::MAX as u64);
+    }
+
+    #[test]
+    #[should_panic(expected = "at least one entry")]
+    fn zero_entries() {
+        NativeCountingBloomFilter::<u32>::with_size(0,3);
+    }
+}
@@ -0,0 +1,256 @@
+// This program is free software; you can redistribute it and/or
+// modify it under the terms of the GNU General Public License as
+// published by the Free Software Foundation; either version 2 of the
+// License, or (at your option) any later version.
+
+// This program is distributed in the hope that it will be useful, but
+// WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the GNU
+// General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with this program; if not, write to the Free Software
+// Foundation, Inc., 51 Franklin Street, Fifth Floor, Boston, MA
+// 02110-1301, USA.
+
+extern crate bit_vec;
+
+use bit_vec::BitVec;
+use std::collections::hash_map::RandomState;
+use std::hash::{BuildHasher,Hash};
+
+use super::{ASMS,Resettable};
+use super::bloom::{needed_bits,optimal_num_hashes};
+use super::hashing::HashIter;
+
+/// A Partitioned BloomFilter.  The bits are divided into one
+/// partition per hash, and hash `i` only ever sets a bit in partition
+/// `i`.  Every insert sets at most one bit in each partition, so the
+/// partitions fill up evenly, and each can be updated independently
+/// of the others.  The false positive rate is very close to that of a
+/// standard BloomFilter with the same number of bits.
+///
+/// # Example Usage
+///
+/// ```rust
+/// use bloom::{ASMS,PartitionedBloomFilter};
+///
+/// let mut filter = PartitionedBloomFilter::with_rate(0.01,1000);
+/// filter.insert(&1);
+/// assert!(filter.contains(&1));
+/// ```
+pub struct PartitionedBloomFilter<R = RandomState, S = RandomState> {
+    bits: BitVec,
+    partition_bits: u64,
+    num_hashes: u32,
+    hash_builder_one: R,
+    hash_builder_two: S,
+}
+
+impl PartitionedBloomFilter<RandomState, RandomState> {
+    /// Create a new PartitionedBloomFilter with at least the
+    /// specified number of bits, and hashes.  The number of bits is
+    /// rounded up to a multiple of `num_hashes`.
+    ///
+    /// # Panics
+    /// Panics if `num_hashes` is 0
+    pub fn with_size(num_bits: usize, num_hashes: u32) -> PartitionedBloomFilter<RandomState, RandomState> {
+        PartitionedBloomFilter::with_size_and_hashers(num_bits,num_hashes,
+                                                      RandomState::new(),RandomState::new())
+    }
+
+    /// create a PartitionedBloomFilter that expects to hold
+    /// `expected_num_items`.  The filter will be sized as a standard
+    /// BloomFilter with a false positive rate of `rate`, and then
+    /// split into partitions.
+    pub fn with_rate(rate: f32, expected_num_items: u32) -> PartitionedBloomFilter<RandomState, RandomState> {
+        let bits = needed_bits(rate,expected_num_items);
+        PartitionedBloomFilter::with_size(bits,optimal_num_hashes(bits,expected_num_items))
+    }
+}
+
+impl<R,S> PartitionedBloomFilter<R,S>
+    where R: BuildHasher, S: BuildHasher
+{
+    /// Create a new PartitionedBloomFilter with at least the
+    /// specified number of bits, hashes, and the two specified
+    /// HashBuilders.  The HashBuilders MUST provide independent hash
+    /// values, as for `BloomFilter::with_size_and_hashers`.
+    ///
+    /// # Panics
+    /// Panics if `num_hashes` is 0
+    pub fn with_size_and_hashers(num_bits: usize, num_hashes: u32,
+                                 hash_builder_one: R, hash_builder_two: S) -> PartitionedBloomFilter<R,S> {
+        if num_hashes == 0 {
+            panic!("a PartitionedBloomFilter needs at least one hash");
+        }
+        let partition_bits = num_bits.div_ceil(num_hashes as usize).max(1);
+        PartitionedBloomFilter {
+            bits: BitVec::from_elem(partition_bits*num_hashes as usize,false),
+            partition_bits: partition_bits as u64,
+            num_hashes: num_hashes,
+            hash_builder_one: hash_builder_one,
+            hash_builder_two: hash_builder_two,
+        }
+    }
+
+    /// Get the number of bits this PartitionedBloomFilter is using
+    pub fn num_bits(&self) -> usize {
+        self.bits.len()
+    }
+
+    /// Get the number of bits in each partition
+    pub fn partition_bits(&self) -> usize {
+        self.partition_bits as usize
+    }
+
+    /// Get the number of hash functions this PartitionedBloomFilter
+    /// is using, which is also the number of partitions
+    pub fn num_hashes(&self) -> u32 {
+        self.num_hashes
+    }
+
+    /// Get the number of bits set in partition `i`
+    ///
+    /// # Panics
+    /// Panics if `i` is not less than `num_hashes()`
+    pub fn partition_ones(&self, i: u32) -> usize {
+        if i >= self.num_hashes {
+            panic!("partition {} out of range for a PartitionedBloomFilter with {} partitions",
+                   i,self.num_hashes);
+        }
+        let start = i as usize * self.partition_bits as usize;
+        (start..start+self.partition_bits as usize).filter(|&idx| self.bits[idx]).count()
+    }
+
+    // the index of the bit each hash selects, one in each partition
+    fn indices<T: Hash>(&self, item: &T) -> impl Iterator<Item = usize> {
+        let partition_bits = self.partition_bits;
+        HashIter::from(item,
+                       self.num_hashes,
+                       &self.hash_builder_one,
+                       &self.hash_builder_two)
+            .enumerate()
+            .map(move |(i,h)| i * partition_bits as usize + (h % partition_bits) as usize)
+    }
+}
+
+impl<R,S> ASMS for PartitionedBloomFilter<R,S>
+    where R: BuildHasher, S: BuildHasher {
+    /// Insert item into this PartitionedBloomFilter.
+    ///
+    /// If the filter did not have this value present, `true` is returned.
+    ///
+    /// If the filter did have this value present, `false` is returned.
+    fn insert<T: Hash>(&mut self, item: &T) -> bool {
+        let mut contained = true;
+        for idx in self.indices(item) {
+            if !self.bits[idx] {
+                contained = false;
+                self.bits.set(idx,true);
+            }
+        }
+        !contained
+    }
+
+    /// Check if the item has been inserted into this
+    /// PartitionedBloomFilter.  This function can return false
+    /// positives, but not false negatives.
+    fn contains<T: Hash>(&self, item: &T) -> bool {
+        self.indices(item).all(|idx| self.bits[idx])
+    }
+
+    /// Remove all values from this PartitionedBloomFilter
+    fn clear(&mut self) {
+        self.bits.clear();
+    }
+
+    /// The number of bits this PartitionedBloomFilter is using
+    fn capacity_bits(&self) -> usize {
+        self.bits.len()
+    }
+
+    /// The number of hash values computed for each item
+    fn num_hashes(&self) -> u32 {
+        self.num_hashes
+    }
+}
+
+impl<R,S> Resettable for PartitionedBloomFilter<R,S>
+    where R: BuildHasher, S: BuildHasher {
+    /// Remove all values from this PartitionedBloomFilter
+    fn reset(&mut self) {
+        self.clear();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::PartitionedBloomFilter;
+    use hashers::SipBuildHasher;
+    use ASMS;
+
+    #[test]
+    fn simple() {
+        let mut b:PartitionedBloomFilter = PartitionedBloomFilter::with_rate(0.01,100);
+        assert!(b.insert(&1));
+        assert!(!b.insert(&1));
+        assert!(b.contains(&1));
+        assert!(!b.contains(&2));
+        b.clear();
+        assert!(!b.contains(&1));
+    }
+
+    #[test]
+    fn sizes() {
+        let b = PartitionedBloomFilter::with_size(1000,3);
+        assert_eq!(b.partition_bits(),334);
+        assert_eq!(b.num_bits(),1002);
+        assert_eq!(b.capacity_bits(),1002);
+        assert_eq!(ASMS::num_hashes(&b),3);
+    }
+
+    #[test]
+    #[should_panic(expected = "needs at least one hash")]
+    fn no_hashes() {
+        PartitionedBloomFilter::with_size(1000,0);
+    }
+
+    #[test]
+    fn membership() {
+        let (h1, h2) = SipBuildHasher::pair_from_seed(30);
+        let mut b = PartitionedBloomFilter::with_size_and_hashers(95851,7,h1,h2);
+        for i in 0..10000 {
+            b.insert(&i);
+        }
+        for i in 0..10000 {
+            assert!(b.contains(&i));
+        }
+        let false_positives = (10000..110000).filter(|i| b.contains(i)).count();
+        // sized for a rate of 0.01
+        assert!(false_positives < 1500);
+    }
+
+    #[test]
+    fn balanced() {
+        let (h1, h2) = SipBuildHasher::pair_from_seed(30);
+        let mut b = PartitionedBloomFilter::with_size_and_hashers(70000,7,h1,h2);
+        for i in 0..10000 {
+            b.insert(&i);
+        }
+        // each insert sets at most one bit per partition, so with
+        // 10000 items in 10000 bits each partition is about
+        // 1 - 1/e full
+        for i in 0..7 {
+            let ones = b.partition_ones(i);
+            assert!((6000..6650).contains(&ones),"partition {} has {} bits set",i,ones);
+        }
+    }
+
+    #[test]
+    #[should_panic(expected = "partition 3 out of range")]
+    fn partition_out_of_range() {
+        let (h1, h2) = SipBuildHasher::pair_from_seed(30);
+        PartitionedBloomFilter::with_size_and_hashers(300,3,h1,h2).partition_ones(3);
+    }
+}
@@ -0,0 +1,131 @@
+// This program is free software; you can redistribute it and/or
+// modify it under the terms of the GNU General Public License as
+// published by the Free Software Foundation; either version 2 of the
+// License, or (at your option) any later version.
+
+// This program is distributed in the hope that it will be useful, but
+// WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the GNU
+// General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with this program; if not, write to the Free Software
+// Foundation, Inc., 51 Franklin Street, Fifth Floor, Boston, MA
+// 02110-1301, USA.
+
+// counting the set bits in the words backing a filter.  With the
+// `simd` feature on x86_64, CPUs with AVX2 count 256 bits at a time
+
+/// The number of set bits in `words`
+pub fn count_ones(words: &[u32]) -> usize {
+    #[cfg(all(feature = "simd", target_arch = "x86_64"))]
+    {
+        if is_x86_feature_detected!("avx2") {
+            // avx2 is available, which is all count_ones_avx2 needs
+            return unsafe { count_ones_avx2(words) };
+        }
+    }
+    count_ones_scalar(words)
+}
+
+// one word at a time, which compiles to popcnt where the target has it
+fn count_ones_scalar(words: &[u32]) -> usize {
+    words.iter().map(|w| w.count_ones() as usize).sum()
+}
+
+// Mula's nibble lookup: each byte's count is the sum of a table lookup
+// for its low and high nibbles, and sad against zero adds the byte
+// counts up into four u64 lanes
+#[cfg(all(feature = "simd", target_arch = "x86_64"))]
+#[target_feature(enable = "avx2")]
+unsafe fn count_ones_avx2(words: &[u32]) -> usize {
+    use std::arch::x86_64::*;
+
+    let lookup = _mm256_setr_epi8(0, 1, 1, 2, 1, 2, 2, 3, 1, 2, 2, 3, 2, 3, 3, 4,
+                                  0, 1, 1, 2, 1, 2, 2, 3, 1, 2, 2, 3, 2, 3, 3, 4);
+    let low_mask = _mm256_set1_epi8(0x0f);
+    let zero = _mm256_setzero_si256();
+    let mut acc = zero;
+    let chunks = words.chunks_exact(8);
+    let rest = chunks.remainder();
+    for chunk in chunks {
+        let v = _mm256_loadu_si256(chunk.as_ptr() as *const __m256i);
+        let lo = _mm256_and_si256(v, low_mask);
+        let hi = _mm256_and_si256(_mm256_srli_epi16(v, 4), low_mask);
+        let counts = _mm256_add_epi8(_mm256_shuffle_epi8(lookup, lo),
+                                     _mm256_shuffle_epi8(lookup, hi));
+        acc = _mm256_add_epi64(acc, _mm256_sad_epu8(counts, zero));
+    }
+    let mut lanes = [0u64; 4];
+    _mm256_storeu_si256(lanes.as_mut_ptr() as *mut __m256i, acc);
+    lanes.iter().sum::<u64>() as usize + count_ones_scalar(rest)
+}
+
+#[cfg(test)]
+extern crate rand;
+
+#[cfg(feature = "do-bench")]
+#[cfg(test)]
+mod bench {
+    extern crate test;
+    use self::test::Bencher;
+    use popcount::rand::{self,Rng};
+
+    // a 4 MiB filter
+    fn words() -> Vec<u32> {
+        let mut rng = rand::thread_rng();
+        (0..1 << 20).map(|_| rng.gen::<u32>()).collect()
+    }
+
+    #[bench]
+    fn count_ones_benchmark(b: &mut Bencher) {
+        let words = words();
+        b.iter(|| super::count_ones(&words))
+    }
+
+    #[bench]
+    fn count_ones_scalar_benchmark(b: &mut Bencher) {
+        let words = words();
+        b.iter(|| super::count_ones_scalar(&words))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use popcount::rand::{self,Rng};
+    use super::{count_ones,count_ones_scalar};
+
+    // random words of every length up to a few 256 bit chunks, so the
+    // remainder handling is covered, with sparse and dense patterns
+    fn patterns() -> Vec<Vec<u32>> {
+        let mut rng = rand::thread_rng();
+        let mut patterns = Vec::new();
+        for len in 0..40 {
+            patterns.push((0..len).map(|_| rng.gen::<u32>()).collect());
+            patterns.push((0..len).map(|_| rng.gen::<u32>() & rng.gen::<u32>() & rng.gen::<u32>()).collect());
+            patterns.push(vec![u32::MAX; len]);
+        }
+        patterns
+    }
+
+    #[test]
+    fn matches_scalar() {
+        for words in patterns() {
+            let expected = words.iter().map(|w| w.count_ones() as usize).sum::<usize>();
+            assert_eq!(count_ones_scalar(&words),expected);
+            assert_eq!(count_ones(&words),expected);
+        }
+    }
+
+    #[cfg(all(feature = "simd", target_arch = "x86_64"))]
+    #[test]
+    fn avx2_matches_scalar() {
+        if !is_x86_feature_detected!("avx2") {
+            return;
+        }
+        for words in patterns() {
+            let simd = unsafe { super::count_ones_avx2(&words) };
+            assert_eq!(simd,count_ones_scalar(&words));
+        }
+    }
+}
@@ -0,0 +1,298 @@
+// This program is free software; you can redistribute it and/or
+// modify it under the terms of the GNU General Public License as
+// published by the Free Software Foundation; either version 2 of the
+// License, or (at your option) any later version.
+
+// This program is distributed in the hope that it will be useful, but
+// WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the GNU
+// General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with this program; if not, write to the Free Software
+// Foundation, Inc., 51 Franklin Street, Fifth Floor, Boston, MA
+// 02110-1301, USA.
+
+use std::collections::hash_map::RandomState;
+use std::hash::{BuildHasher,Hash};
+
+use super::{ASMS,Resettable};
+use super::bloom::BloomFilter;
+
+/// Each new slice's false positive rate is this fraction of the
+/// previous slice's rate.  The rates form a geometric series, so the
+/// overall rate stays below the target no matter how many slices are
+/// added.
+const TIGHTENING_RATIO: f32 = 0.8;
+
+/// A Scalable Bloom Filter, which grows as items are inserted so that
+/// it doesn't need to know how many items it will hold up front.
+///
+/// The filter is a series of BloomFilters (slices).  Items are
+/// inserted into the newest slice, and once that slice holds as many
+/// items as it was sized for a new slice is added, `growth_factor`
+/// times larger and with a tighter false positive rate.  `contains`
+/// checks every slice, and the overall false positive rate stays
+/// below the rate the filter was created with.
+///
+/// A slice is full once as many distinct items have been inserted
+/// into it as it was sized for, rather than once an estimate of its
+/// fill from its set bits passes a threshold.  `insert` skips items
+/// that are already contained, so the count is of the items the
+/// slice actually holds, and each slice reaches its target rate when
+/// it fills, without counting its bits on every insert.
+///
+/// # Example Usage
+///
+/// ```rust
+/// use bloom::{ASMS,ScalableBloomFilter};
+///
+/// let mut filter = ScalableBloomFilter::new(100,0.01,2);
+/// for i in 0..1000 {
+///     filter.insert(&i);
+/// }
+/// assert!(filter.contains(&999));
+/// assert!(filter.num_slices() > 1);
+/// ```
+pub struct ScalableBloomFilter<R = RandomState, S = RandomState> {
+    slices: Vec<BloomFilter<R,S>>,
+    initial_capacity: u32,
+    rate: f32,
+    growth_factor: u32,
+    slice_capacity: u32,
+    slice_rate: f32,
+    slice_count: u32,
+    hash_builder_one: R,
+    hash_builder_two: S,
+}
+
+impl ScalableBloomFilter<RandomState, RandomState> {
+    /// Create a ScalableBloomFilter whose first slice holds
+    /// `initial_capacity` items, with each later slice
+    /// `growth_factor` times larger than the last.  The filter will
+    /// have a false positive rate of at most `rate` however many
+    /// items are inserted.
+    ///
+    /// # Panics
+    /// Panics if `initial_capacity` is 0, `growth_factor` is less
+    /// than 1, or `rate` is not strictly between 0 and 1
+    pub fn new(initial_capacity: u32, rate: f32, growth_factor: u32) -> ScalableBloomFilter<RandomState, RandomState> {
+        ScalableBloomFilter::with_hashers(initial_capacity,rate,growth_factor,
+                                          RandomState::new(),RandomState::new())
+    }
+}
+
+impl<R,S> ScalableBloomFilter<R,S>
+    where R: BuildHasher + Clone, S: BuildHasher + Clone
+{
+    /// Create a ScalableBloomFilter like `new`, whose slices all hash
+    /// items with `hash_builder_one` and `hash_builder_two`.  These
+    /// MUST provide independent hash values.
+    ///
+    /// # Panics
+    /// Panics if `initial_capacity` is 0, `growth_factor` is less
+    /// than 1, or `rate` is not strictly between 0 and 1
+    pub fn with_hashers(initial_capacity: u32, rate: f32, growth_factor: u32,
+                        hash_builder_one: R, hash_builder_two: S) -> ScalableBloomFilter<R,S> {
+        // the slices' rates are smaller than rate, so wouldn't catch a
+        // rate that is too large
+        if !(rate > 0.0 && rate < 1.0) {
+            panic!("false positive rate must be between 0 and 1 (exclusive), got {}", rate);
+        }
+        if initial_capacity == 0 {
+            panic!("ScalableBloomFilter needs an initial capacity of at least 1");
+        }
+        if growth_factor < 1 {
+            panic!("ScalableBloomFilter needs a growth factor of at least 1");
+        }
+        let slice_rate = rate * (1.0 - TIGHTENING_RATIO);
+        ScalableBloomFilter {
+            slices: vec![BloomFilter::with_rate_and_hashers(slice_rate,initial_capacity,
+                                                            hash_builder_one.clone(),
+                                                            hash_builder_two.clone())],
+            initial_capacity: initial_capacity,
+            rate: rate,
+            growth_factor: growth_factor,
+            slice_capacity: initial_capacity,
+            slice_rate: slice_rate,
+            slice_count: 0,
+            hash_builder_one: hash_builder_one,
+            hash_builder_two: hash_builder_two,
+        }
+    }
+
+    /// Get the number of BloomFilter slices this filter is using
+    pub fn num_slices(&self) -> usize {
+        self.slices.len()
+    }
+
+    /// Get the total number of bits used by all the slices of this
+    /// filter
+    pub fn num_bits(&self) -> usize {
+        self.slices.iter().map(|s| s.num_bits()).sum()
+    }
+
+    /// Get the overall false positive rate this filter was created
+    /// with
+    pub fn rate(&self) -> f32 {
+        self.rate
+    }
+
+    // add a new, larger slice for future inserts
+    fn grow(&mut self) {
+        self.slice_capacity = self.slice_capacity.saturating_mul(self.growth_factor);
+        self.slice_rate *= TIGHTENING_RATIO;
+        self.slices.push(BloomFilter::with_rate_and_hashers(self.slice_rate,self.slice_capacity,
+                                                            self.hash_builder_one.clone(),
+                                                            self.hash_builder_two.clone()));
+        self.slice_count = 0;
+    }
+}
+
+impl<R,S> ASMS for ScalableBloomFilter<R,S>
+    where R: BuildHasher + Clone, S: BuildHasher + Clone {
+    /// Insert item into this ScalableBloomFilter, adding a new slice
+    /// first if the newest slice is full.
+    ///
+    /// If the filter did not have this value present, `true` is
+    /// returned.
+    ///
+    /// If the filter did have this value present, `false` is
+    /// returned, and the filter is unchanged.
+    fn insert<T: Hash>(&mut self, item: &T) -> bool {
+        if self.contains(item) {
+            return false;
+        }
+        if self.slice_count >= self.slice_capacity {
+            self.grow();
+        }
+        let last = self.slices.len() - 1;
+        self.slices[last].insert(item);
+        self.slice_count += 1;
+        true
+    }
+
+    /// Check if the item has been inserted into any slice of this
+    /// filter.  This function can return false positives, but not
+    /// false negatives.
+    fn contains<T: Hash>(&self, item: &T) -> bool {
+        self.slices.iter().any(|s| s.contains(item))
+    }
+
+    /// Remove all values from this ScalableBloomFilter, shrinking it
+    /// back to a single slice
+    fn clear(&mut self) {
+        self.slices.truncate(1);
+        self.slices[0].clear();
+        self.slice_capacity = self.initial_capacity;
+        self.slice_rate = self.rate * (1.0 - TIGHTENING_RATIO);
+        self.slice_count = 0;
+    }
+
+    /// The total number of bits used by all the slices of this
+    /// filter, the same as `num_bits`
+    fn capacity_bits(&self) -> usize {
+        self.num_bits()
+    }
+
+    /// The total number of hash functions used by all the slices of
+    /// this filter, as `contains` checks every slice
+    fn num_hashes(&self) -> u32 {
+        self.slices.iter().map(|s| s.num_hashes()).sum()
+    }
+}
+
+impl<R,S> Resettable for ScalableBloomFilter<R,S>
+    where R: BuildHasher + Clone, S: BuildHasher + Clone {
+    /// Remove all values from this ScalableBloomFilter, shrinking it
+    /// back to a single slice
+    fn reset(&mut self) {
+        self.clear();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::ScalableBloomFilter;
+    use hashers::SipBuildHasher;
+    use ASMS;
+
+    #[test]
+    fn simple() {
+        let mut sbf = ScalableBloomFilter::new(10,0.01,2);
+        assert!(sbf.insert(&1));
+        assert!(!sbf.insert(&1));
+        assert!(sbf.contains(&1));
+        assert!(!sbf.contains(&2));
+        sbf.clear();
+        assert!(!sbf.contains(&1));
+        assert_eq!(sbf.num_slices(),1);
+    }
+
+    #[test]
+    fn seeded() {
+        let (h1, h2) = SipBuildHasher::pair_from_seed(37);
+        let mut a = ScalableBloomFilter::with_hashers(10,0.01,2,h1,h2);
+        let mut b = ScalableBloomFilter::with_hashers(10,0.01,2,h1,h2);
+        for i in 0..100 {
+            a.insert(&i);
+            b.insert(&i);
+        }
+        assert_eq!(a.num_slices(),4);
+        // the same hashers set the same bits in every slice
+        for i in 0..1000 {
+            assert_eq!(a.contains(&i),b.contains(&i));
+        }
+
+        // clearing keeps the hashers and starts over from the first
+        // slice
+        a.clear();
+        assert_eq!(a.num_slices(),1);
+        for i in 0..100 {
+            a.insert(&i);
+        }
+        assert_eq!(a.num_slices(),4);
+        assert_eq!(a.num_bits(),b.num_bits());
+    }
+
+    #[test]
+    fn grows() {
+        let mut sbf = ScalableBloomFilter::new(10,0.01,2);
+        let bits = sbf.num_bits();
+        for i in 0..100 {
+            sbf.insert(&i);
+        }
+        // 10 + 20 + 40 + 80 > 100
+        assert_eq!(sbf.num_slices(),4);
+        assert!(sbf.num_bits() > bits);
+        for i in 0..100 {
+            assert!(sbf.contains(&i));
+        }
+    }
+
+    #[test]
+    #[should_panic(expected = "false positive rate must be between 0 and 1")]
+    fn bad_rate() {
+        ScalableBloomFilter::new(10,1.0,2);
+    }
+
+    #[test]
+    fn fpr_test() {
+        let rate = 0.01;
+        let (h1, h2) = SipBuildHasher::pair_from_seed(38);
+        let mut sbf = ScalableBloomFilter::with_hashers(1000,rate,2,h1,h2);
+        for i in 0..100000 {
+            sbf.insert(&i);
+        }
+        assert!(sbf.num_slices() > 5);
+        for i in 0..100000 {
+            assert!(sbf.contains(&i));
+        }
+
+        // make sure we stay under the target, even after growing well
+        // past the initial capacity
+        let false_positives = (100000..200000).filter(|i| sbf.contains(i)).count();
+        let actual_rate = false_positives as f32 / 100000.0;
+        assert!(actual_rate < rate);
+    }
+}
@@ -0,0 +1,343 @@
+// This program is free software; you can redistribute it and/or
+// modify it under the terms of the GNU General Public License as
+// published by the Free Software Foundation; either version 2 of the
+// License, or (at your option) any later version.
+
+// This program is distributed in the hope that it will be useful, but
+// WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the GNU
+// General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with this program; if not, write to the Free Software
+// Foundation, Inc., 51 Franklin Street, Fifth Floor, Boston, MA
+// 02110-1301, USA.
+
+use std::collections::hash_map::RandomState;
+use std::hash::{BuildHasher,Hash};
+
+use super::{ASMS,Resettable};
+use super::ValueVec;
+use super::hashing::HashIter;
+
+/// A Stable Bloom Filter, for checking recent membership in an
+/// unbounded stream of items.
+///
+/// The filter is an array of small counters (cells).  Every insert
+/// first decrements `num_decrements` cells, starting from a
+/// pseudo-random position, and then sets the item's cells to the
+/// largest value a cell can hold.  Items that haven't been inserted
+/// for a while have their cells worn down to 0 and stop being
+/// contained, so the fraction of non-zero cells, and with it the
+/// false positive rate, settles at a fixed point however many items
+/// are inserted.  In exchange the filter can give false negatives
+/// for items that were inserted long enough ago.
+///
+/// With cells that hold at most `max`, an item is always contained
+/// for at least `max - 1` inserts after its own.  Each insert lowers
+/// a cell by at most one before it sets its own cells, and a cell
+/// lowered to 0 no longer counts.
+///
+/// # Example Usage
+///
+/// ```rust
+/// use bloom::{ASMS,StableBloomFilter};
+///
+/// let mut filter = StableBloomFilter::with_rate(10000,3,0.01);
+/// filter.insert(&1);
+/// assert!(filter.contains(&1));
+/// ```
+pub struct StableBloomFilter<R = RandomState, S = RandomState> {
+    cells: ValueVec,
+    num_cells: u64,
+    num_hashes: u32,
+    num_decrements: usize,
+    rng_state: u64,
+    hash_builder_one: R,
+    hash_builder_two: S,
+}
+
+impl StableBloomFilter<RandomState, RandomState> {
+    /// Create a StableBloomFilter with `num_cells` cells of
+    /// `bits_per_cell` bits each, which uses `num_hashes` hashes and
+    /// decrements `num_decrements` cells on each insert.
+    ///
+    /// # Panics
+    /// Panics if `num_cells` or `bits_per_cell` is 0,
+    /// `bits_per_cell` is more than 64, or `num_decrements` is more
+    /// than `num_cells`
+    pub fn with_size(num_cells: usize, bits_per_cell: usize, num_hashes: u32,
+                     num_decrements: usize) -> StableBloomFilter<RandomState, RandomState> {
+        StableBloomFilter::with_size_and_hashers(num_cells,bits_per_cell,num_hashes,num_decrements,
+                                                 RandomState::new(),RandomState::new())
+    }
+
+    /// Create a StableBloomFilter with `num_cells` cells of
+    /// `bits_per_cell` bits each, whose false positive rate settles
+    /// at about `rate`.  The number of hashes and decrements are
+    /// chosen with `optimal_stable_num_hashes` and
+    /// `optimal_stable_num_decrements`.
+    ///
+    /// # Panics
+    /// Panics if `num_cells` or `bits_per_cell` is 0,
+    /// `bits_per_cell` is more than 64, or `rate` is not strictly
+    /// between 0 and 1
+    pub fn with_rate(num_cells: usize, bits_per_cell: usize, rate: f32) -> StableBloomFilter<RandomState, RandomState> {
+        StableBloomFilter::with_rate_and_hashers(num_cells,bits_per_cell,rate,
+                                                 RandomState::new(),RandomState::new())
+    }
+}
+
+impl<R,S> StableBloomFilter<R,S>
+    where R: BuildHasher, S: BuildHasher
+{
+    /// Create a StableBloomFilter like `with_size`, hashing items
+    /// with `hash_builder_one` and `hash_builder_two`.  These MUST
+    /// provide independent hash values.
+    ///
+    /// # Panics
+    /// Panics if `num_cells` or `bits_per_cell` is 0,
+    /// `bits_per_cell` is more than 64, or `num_decrements` is more
+    /// than `num_cells`
+    pub fn with_size_and_hashers(num_cells: usize, bits_per_cell: usize, num_hashes: u32,
+                                 num_decrements: usize,
+                                 hash_builder_one: R, hash_builder_two: S) -> StableBloomFilter<R,S> {
+        if num_cells == 0 {
+            panic!("StableBloomFilter must have at least one cell");
+        }
+        if bits_per_cell == 0 {
+            panic!("StableBloomFilter cells must have at least one bit");
+        }
+        if bits_per_cell > 64 {
+            panic!("StableBloomFilter cells can have at most 64 bits, asked for {}",
+                   bits_per_cell);
+        }
+        if num_decrements > num_cells {
+            panic!("can't decrement {} cells of a StableBloomFilter with {} cells",
+                   num_decrements,num_cells);
+        }
+        StableBloomFilter {
+            cells: ValueVec::new(bits_per_cell,num_cells),
+            num_cells: num_cells as u64,
+            num_hashes: num_hashes,
+            num_decrements: num_decrements,
+            rng_state: 0x9e3779b97f4a7c15,
+            hash_builder_one: hash_builder_one,
+            hash_builder_two: hash_builder_two,
+        }
+    }
+
+    /// Create a StableBloomFilter like `with_rate`, hashing items
+    /// with `hash_builder_one` and `hash_builder_two`.  These MUST
+    /// provide independent hash values.
+    ///
+    /// # Panics
+    /// Panics if `num_cells` or `bits_per_cell` is 0,
+    /// `bits_per_cell` is more than 64, or `rate` is not strictly
+    /// between 0 and 1
+    pub fn with_rate_and_hashers(num_cells: usize, bits_per_cell: usize, rate: f32,
+                                 hash_builder_one: R, hash_builder_two: S) -> StableBloomFilter<R,S> {
+        let num_hashes = optimal_stable_num_hashes(rate);
+        let num_decrements = optimal_stable_num_decrements(num_cells,num_hashes,bits_per_cell,rate);
+        StableBloomFilter::with_size_and_hashers(num_cells,bits_per_cell,num_hashes,num_decrements,
+                                                 hash_builder_one,hash_builder_two)
+    }
+
+    /// Get the number of cells in this StableBloomFilter
+    pub fn num_cells(&self) -> usize {
+        self.num_cells as usize
+    }
+
+    /// Get the number of hash functions this StableBloomFilter is
+    /// using
+    pub fn num_hashes(&self) -> u32 {
+        self.num_hashes
+    }
+
+    /// Get the number of cells decremented on each insert
+    pub fn num_decrements(&self) -> usize {
+        self.num_decrements
+    }
+
+    // lower num_decrements consecutive cells, wrapping around, from a
+    // pseudo-random starting cell
+    fn decrement(&mut self) {
+        let mut x = self.rng_state;
+        x ^= x << 13;
+        x ^= x >> 7;
+        x ^= x << 17;
+        self.rng_state = x;
+        let start = (x % self.num_cells) as usize;
+        for i in 0..self.num_decrements {
+            self.cells.decrement((start + i) % self.num_cells as usize);
+        }
+    }
+}
+
+impl<R,S> ASMS for StableBloomFilter<R,S>
+    where R: BuildHasher, S: BuildHasher {
+    /// Insert item into this StableBloomFilter, after decrementing
+    /// `num_decrements` cells.
+    ///
+    /// If the filter did not have this value present, `true` is
+    /// returned.
+    ///
+    /// If the filter did have this value present, `false` is
+    /// returned.
+    fn insert<T: Hash>(&mut self, item: &T) -> bool {
+        let contained = self.contains(item);
+        self.decrement();
+        let max = self.cells.max_value();
+        for h in HashIter::from(item,
+                                self.num_hashes,
+                                &self.hash_builder_one,
+                                &self.hash_builder_two) {
+            self.cells.set((h % self.num_cells) as usize,max);
+        }
+        !contained
+    }
+
+    /// Check if the item has been inserted into this
+    /// StableBloomFilter recently.  This function can return both
+    /// false positives and, for items inserted long enough ago, false
+    /// negatives.
+    fn contains<T: Hash>(&self, item: &T) -> bool {
+        for h in HashIter::from(item,
+                                self.num_hashes,
+                                &self.hash_builder_one,
+                                &self.hash_builder_two) {
+            if self.cells.get((h % self.num_cells) as usize) == 0 {
+                return false;
+            }
+        }
+        true
+    }
+
+    /// Remove all values from this StableBloomFilter
+    fn clear(&mut self) {
+        self.cells.clear();
+    }
+
+    /// The number of bits used by all the cells, `num_cells` times
+    /// the bits per cell
+    fn capacity_bits(&self) -> usize {
+        self.cells.count() * self.cells.bits_per_val()
+    }
+
+    /// The number of hash functions this StableBloomFilter is using
+    fn num_hashes(&self) -> u32 {
+        self.num_hashes
+    }
+}
+
+impl<R,S> Resettable for StableBloomFilter<R,S>
+    where R: BuildHasher, S: BuildHasher {
+    /// Remove all values from this StableBloomFilter
+    fn reset(&mut self) {
+        self.clear();
+    }
+}
+
+/// Return the number of hashes a StableBloomFilter should use for
+/// its false positive rate to settle at `rate`
+///
+/// # Panics
+/// Panics if `rate` is not strictly between 0 and 1
+pub fn optimal_stable_num_hashes(rate: f32) -> u32 {
+    if !(rate > 0.0 && rate < 1.0) {
+        panic!("false positive rate must be between 0 and 1 (exclusive), got {}", rate);
+    }
+    ((1.0 / rate as f64).log2().ceil() as u32).max(1)
+}
+
+/// Return the number of cells a StableBloomFilter with `num_cells`
+/// cells of `bits_per_cell` bits, using `num_hashes` hashes, should
+/// decrement on each insert for its false positive rate to settle at
+/// `rate`.  This is the formula from Deng and Rafiei, "Approximately
+/// Detecting Duplicates for Streaming Data using Stable Bloom
+/// Filters".  Always returns at least 1.
+///
+/// # Panics
+/// Panics if `rate` is not strictly between 0 and 1
+pub fn optimal_stable_num_decrements(num_cells: usize, num_hashes: u32,
+                                     bits_per_cell: usize, rate: f32) -> usize {
+    if !(rate > 0.0 && rate < 1.0) {
+        panic!("false positive rate must be between 0 and 1 (exclusive), got {}", rate);
+    }
+    let max = ((1u64 << bits_per_cell.min(63)) - 1) as f64;
+    let k = num_hashes as f64;
+    let sub = (1.0 - (rate as f64).powf(1.0 / k)).powf(1.0 / max);
+    let denom = (1.0 / sub - 1.0) * (1.0 / k - 1.0 / num_cells as f64);
+    let decrements = (1.0 / denom) as usize;
+    decrements.clamp(1,num_cells.max(1))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{StableBloomFilter,optimal_stable_num_hashes,optimal_stable_num_decrements};
+    use hashers::SipBuildHasher;
+    use ASMS;
+
+    #[test]
+    fn simple() {
+        let (h1, h2) = SipBuildHasher::pair_from_seed(12);
+        let mut sbf = StableBloomFilter::with_rate_and_hashers(1000,3,0.01,h1,h2);
+        assert!(sbf.insert(&1));
+        assert!(!sbf.insert(&1));
+        assert!(sbf.contains(&1));
+        assert!(!sbf.contains(&2));
+        sbf.clear();
+        assert!(!sbf.contains(&1));
+    }
+
+    #[test]
+    fn parameters() {
+        assert_eq!(optimal_stable_num_hashes(0.01),7);
+        assert_eq!(optimal_stable_num_hashes(0.5),1);
+        let p = optimal_stable_num_decrements(10000,7,3,0.01);
+        assert!((1..100).contains(&p));
+        let (h1, h2) = SipBuildHasher::pair_from_seed(12);
+        let sbf = StableBloomFilter::with_rate_and_hashers(10000,3,0.01,h1,h2);
+        assert_eq!(sbf.num_hashes(),7);
+        assert_eq!(sbf.num_decrements(),p);
+    }
+
+    #[test]
+    fn stream() {
+        let (h1, h2) = SipBuildHasher::pair_from_seed(12);
+        let mut sbf = StableBloomFilter::with_rate_and_hashers(10000,3,0.01,h1,h2);
+        for i in 0..200000 {
+            sbf.insert(&i);
+        }
+        // a cell can be lowered by at most one per insert, and cells
+        // hold at most 7, so the last item and the 6 before it are
+        // always contained
+        for i in 200000-7..200000 {
+            assert!(sbf.contains(&i));
+        }
+        let recent = (200000-100..200000).filter(|i| sbf.contains(i)).count();
+        assert!(recent > 90);
+        let old = (0..1000).filter(|i| sbf.contains(i)).count();
+        assert!(old < 50);
+        let absent = (200000..210000).filter(|i| sbf.contains(i)).count();
+        assert!(absent < 300);
+    }
+
+    #[test]
+    #[should_panic(expected = "at least one cell")]
+    fn zero_cells() {
+        StableBloomFilter::with_size(0,3,3,1);
+    }
+
+    #[test]
+    #[should_panic(expected = "at most 64 bits")]
+    fn wide_cells() {
+        StableBloomFilter::with_size(10,65,3,1);
+    }
+
+    #[test]
+    #[should_panic(expected = "can't decrement")]
+    fn too_many_decrements() {
+        StableBloomFilter::with_size(10,3,3,11);
+    }
+}
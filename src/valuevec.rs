@@ -13,34 +13,63 @@
 // Foundation, Inc., 51 Franklin Street, Fifth Floor, Boston, MA
 // 02110-1301, USA.
 
-extern crate core;
-extern crate bit_vec;
+use alloc::vec::Vec;
+use core::cmp;
+use core::fmt;
+use core::mem;
+use core::slice;
+#[cfg(feature = "std")]
+use std::error::Error;
 
-use bit_vec::BitVec;
+use super::Resettable;
+
+/// Number of bits in each block of storage
+const BLOCK_BITS: usize = 64;
+/// How many values the Debug output of a ValueVec shows
+const DEBUG_VALUES: usize = 8;
 
 /// A ValueVec is a bit vector that holds fixed sized unsigned integer
-/// values.
+/// values of up to 64 bits.
+///
+/// Two ValueVecs are equal if they use the same number of bits per
+/// value and hold the same values.
+#[derive(Clone, PartialEq, Eq)]
 pub struct ValueVec {
     bits_per_val: usize,
-    mask: u32,
-    bits: BitVec,
+    mask: u64,
+    num_bits: usize,
+    count: usize,
+    // stored big endian, so the bytes in memory are the values packed
+    // most significant bit first, which as_bytes exposes
+    blocks: Vec<u64>,
 }
 
 impl ValueVec {
 
     /// Create a ValueVec that holds values with `bits_per_val` bits and
     /// space to hold `count` values.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `bits_per_val` is more than 64
     pub fn new(bits_per_val: usize, count: usize) -> ValueVec {
+        if bits_per_val > BLOCK_BITS {
+            panic!("ValueVec can hold values of at most {} bits, asked for {}",
+                   BLOCK_BITS,bits_per_val);
+        }
         let bits = bits_per_val*count;
         ValueVec {
             bits_per_val: bits_per_val,
-            mask: 2u32.pow(bits_per_val as u32)-1,
-            bits: BitVec::from_elem(bits,false),
+            mask: low_mask(bits_per_val),
+            num_bits: bits,
+            count: count,
+            blocks: vec![0; bits.div_ceil(BLOCK_BITS)],
         }
     }
 
     /// Create a ValueVec that can hold `count` values where the
-    /// maximam value of each entry is at least `max_val` (inclusive)
+    /// maximam value of each entry is at least `max_val` (inclusive).
+    /// A `max_val` of 0 still uses 1 bit per value.
     ///
     /// # Example
     ///
@@ -50,7 +79,7 @@ impl ValueVec {
     /// vv.set(0,7); // okay
     /// vv.set(0,8); // will panic
     /// ```
-    pub fn with_max(max_val: u32, count: usize) -> ValueVec {
+    pub fn with_max(max_val: u64, count: usize) -> ValueVec {
         let mut bits_per_val = 0;
         let mut cur = max_val;
         // there are fancy faster versions of this, but this is only
@@ -59,7 +88,7 @@ impl ValueVec {
             bits_per_val+=1;
             cur>>=1;
         }
-        ValueVec::new(bits_per_val,count)
+        ValueVec::new(cmp::max(bits_per_val,1),count)
     }
 
     /// How many bits this ValueVec is using to store each value
@@ -68,65 +97,155 @@ impl ValueVec {
     }
 
     /// The maximum value this ValueVec can hold per entry
-    pub fn max_value(&self) -> u32 {
+    pub fn max_value(&self) -> u64 {
         self.mask
     }
 
     /// Resets all values to 0 in this ValueVec
     pub fn clear(&mut self) {
-        self.bits.clear();
+        for block in self.blocks.iter_mut() {
+            *block = 0;
+        }
     }
 
-    fn set_bits(&mut self, idx: usize,  val: u32, num_bits: usize) {
-        let mut blocks = unsafe {self.bits.storage_mut()};
-        let blockidx = idx/32;
-        let shift = 32-(idx%32)-num_bits;
+    fn set_bits(&mut self, idx: usize,  val: u64, num_bits: usize) {
+        let blockidx = idx/BLOCK_BITS;
+        let shift = BLOCK_BITS-(idx%BLOCK_BITS)-num_bits;
         let mask =
             if num_bits==self.bits_per_val {
                 self.mask
             } else {
-                2u32.pow(num_bits as u32)-1
+                low_mask(num_bits)
             } << shift;
-        let block = blocks[blockidx];
+        let block = u64::from_be(self.blocks[blockidx]);
 
         // this will be the value with all bits in our value set to zero
         let zeroed = (block ^ mask) & block;
         // or in the new val
-        blocks[blockidx] = zeroed | (val<<shift);
+        self.blocks[blockidx] = (zeroed | (val<<shift)).to_be();
     }
 
-    fn get_bits(&self, idx: usize, num_bits: usize) -> u32 {
-        let blocks = self.bits.storage();
-        let shift = 32-(idx%32)-num_bits;
+    fn get_bits(&self, idx: usize, num_bits: usize) -> u64 {
+        let shift = BLOCK_BITS-(idx%BLOCK_BITS)-num_bits;
         let mask =
             if num_bits==self.bits_per_val {
                 self.mask
             } else {
-                2u32.pow(num_bits as u32)-1
+                low_mask(num_bits)
             } << shift;
-        let val = blocks[idx/32] & mask;
+        let val = u64::from_be(self.blocks[idx/BLOCK_BITS]) & mask;
         val >> shift
     }
 
     /// Get the total number of bits this valuevec is using
     pub fn len(&self) -> usize {
-        self.bits.len()
+        self.num_bits
+    }
+
+    /// Get the number of bytes this ValueVec is using, counting both
+    /// the struct itself and the storage for the values
+    pub fn memory_bytes(&self) -> usize {
+        mem::size_of::<ValueVec>() + mem::size_of_val(&self.blocks[..])
+    }
+
+    /// The packed values in this ValueVec, without copying them.
+    /// Values are packed one after the other, most significant bit
+    /// first, so value `i` starts at bit `i * bits_per_val()`, and
+    /// the last byte is padded with zero bits.  The layout is the
+    /// same on every platform, so these bytes can be written out and
+    /// read back with `from_bytes`.
+    pub fn as_bytes(&self) -> &[u8] {
+        let len = self.num_bits.div_ceil(8);
+        // blocks holds at least len bytes, and any bytes are valid u8s
+        unsafe { slice::from_raw_parts(self.blocks.as_ptr() as *const u8, len) }
+    }
+
+    /// Create a ValueVec holding `count` values of `bits_per_val`
+    /// bits, from bytes packed as by `as_bytes`.  The padding bits in
+    /// the last byte are ignored.
+    ///
+    /// Returns `ValueVecError::WrongLength` unless `bytes` is exactly
+    /// `ceil(bits_per_val * count / 8)` bytes long.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `bits_per_val` is more than 64
+    pub fn from_bytes(bits_per_val: usize, count: usize, bytes: &[u8]) -> Result<ValueVec, ValueVecError> {
+        let mut vv = ValueVec::new(bits_per_val,count);
+        let expected = vv.num_bits.div_ceil(8);
+        if bytes.len() != expected {
+            return Err(ValueVecError::WrongLength { expected: expected, actual: bytes.len() });
+        }
+        for (block,chunk) in vv.blocks.iter_mut().zip(bytes.chunks(8)) {
+            let mut buf = [0; 8];
+            buf[..chunk.len()].copy_from_slice(chunk);
+            // the blocks are stored big endian, so keep the byte order
+            *block = u64::from_ne_bytes(buf);
+        }
+        let padding = expected*8 - vv.num_bits;
+        if padding > 0 {
+            let last = vv.blocks.len()-1;
+            let real_bits = vv.num_bits - last*BLOCK_BITS;
+            let block = u64::from_be(vv.blocks[last]) & !low_mask(BLOCK_BITS-real_bits);
+            vv.blocks[last] = block.to_be();
+        }
+        Ok(vv)
+    }
+
+    /// Get the number of values this ValueVec holds
+    pub fn count(&self) -> usize {
+        self.count
+    }
+
+    /// Get the sum of all the values in this ValueVec, saturating at
+    /// `u64::MAX`
+    pub fn sum(&self) -> u64 {
+        self.iter().fold(0u64, |sum, v| sum.saturating_add(v))
+    }
+
+    /// Get the largest value in this ValueVec, or 0 if it is empty
+    pub fn max(&self) -> u64 {
+        self.iter().max().unwrap_or(0)
+    }
+
+    /// Iterate over the values in this ValueVec, in index order
+    pub fn iter(&self) -> Iter<'_> {
+        Iter {
+            vv: self,
+            i: 0,
+        }
     }
 
     /// Set value at index `i` to value `val`.
     ///
     /// # Panics
     ///
-    /// Panics if `val` needs more bits to store than the number of
-    /// bits this vec is using per value
-    pub fn set(&mut self, i: usize, val: u32) {
+    /// Panics if `i` is not less than `count()`, or `val` needs more
+    /// bits to store than the number of bits this vec is using per
+    /// value
+    pub fn set(&mut self, i: usize, val: u64) {
+        if let Err(e) = self.try_set(i,val) {
+            panic!("{}", e);
+        }
+    }
+
+    /// Set value at index `i` to value `val`, or return an error if
+    /// `i` is out of range or `val` is more than `max_value()`
+    pub fn try_set(&mut self, i: usize, val: u64) -> Result<(), ValueVecError> {
+        if i >= self.count {
+            return Err(ValueVecError::IndexOutOfRange { index: i, count: self.count });
+        }
         if val > self.mask {
-            panic!("set with val {}, max value this ValueVec can hold is {}",
-                   val,self.mask);
+            return Err(ValueVecError::ValueTooLarge { value: val, max: self.mask });
         }
         let idx = i*self.bits_per_val;
-        //println!("idx is: {}",idx);
-        let rem = 32-(idx%32);
+        self.set_at(idx,val);
+        Ok(())
+    }
+
+    // set the value starting at bit offset idx
+    fn set_at(&mut self, idx: usize, val: u64) {
+        let rem = BLOCK_BITS-(idx%BLOCK_BITS);
         if rem < self.bits_per_val {
             // rem is how many bits needed in the lower part
             let left = self.bits_per_val-rem;
@@ -134,7 +253,7 @@ impl ValueVec {
             self.set_bits(idx,lowerval,rem);
 
             // now put the rest of the bits in
-            let upval = val&(2u32.pow(left as u32)-1);
+            let upval = val&low_mask(left);
             self.set_bits(idx+rem,upval,left);
         } else {
             let vs = self.bits_per_val;
@@ -142,10 +261,69 @@ impl ValueVec {
         }
     }
 
-    /// Get the value in this ValueVec stored at index `i`
-    pub fn get(&self, i: usize) -> u32 {
+    /// Reset the value at index `i` to 0, leaving the other values
+    /// unchanged.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `i` is not less than the number of values this
+    /// ValueVec holds
+    pub fn reset(&mut self, i: usize) {
+        if i >= self.count {
+            panic!("reset index {} out of range for ValueVec holding {} values", i, self.count);
+        }
         let idx = i*self.bits_per_val;
-        let rem = 32-(idx%32);
+        self.set_at(idx,0);
+    }
+
+    /// Get the value in this ValueVec stored at index `i`
+    ///
+    /// # Panics
+    ///
+    /// Panics if `i` is not less than `count()`
+    pub fn get(&self, i: usize) -> u64 {
+        match self.try_get(i) {
+            Some(val) => val,
+            None => panic!("{}", ValueVecError::IndexOutOfRange { index: i, count: self.count }),
+        }
+    }
+
+    // panic, as get does, unless i is less than count.  Past the last
+    // value are only the padding bits of the last block, which must
+    // stay 0
+    fn check_index(&self, i: usize) {
+        if i >= self.count {
+            panic!("{}", ValueVecError::IndexOutOfRange { index: i, count: self.count });
+        }
+    }
+
+    /// Get the value in this ValueVec stored at index `i`.  This is
+    /// the same as `get`, and is the accessor to use where `vv[i]`
+    /// would be used for a slice.  ValueVec can't implement `Index`,
+    /// as `Index` must return a reference, and values packed into
+    /// bits that may straddle two blocks aren't stored anywhere a
+    /// reference could point to.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `i` is not less than `count()`
+    pub fn value_at(&self, i: usize) -> u64 {
+        self.get(i)
+    }
+
+    /// Get the value in this ValueVec stored at index `i`, or `None`
+    /// if `i` is out of range
+    pub fn try_get(&self, i: usize) -> Option<u64> {
+        if i < self.count {
+            Some(self.get_at(i*self.bits_per_val))
+        } else {
+            None
+        }
+    }
+
+    // get the value starting at bit offset idx
+    fn get_at(&self, idx: usize) -> u64 {
+        let rem = BLOCK_BITS-(idx%BLOCK_BITS);
         if rem < self.bits_per_val {
             let lower = self.get_bits(idx,rem);
             let left = self.bits_per_val-rem;
@@ -155,11 +333,201 @@ impl ValueVec {
             self.get_bits(idx,self.bits_per_val)
         }
     }
+
+    /// Add one to the value at index `i`, unless it is already
+    /// `max_value()`.  Returns the new value.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `i` is not less than `count()`
+    pub fn increment(&mut self, i: usize) -> u64 {
+        self.check_index(i);
+        let idx = i*self.bits_per_val;
+        let cur = self.get_at(idx);
+        if cur < self.mask {
+            self.set_at(idx,cur+1);
+            cur+1
+        } else {
+            cur
+        }
+    }
+
+    /// Subtract one from the value at index `i`, unless it is already
+    /// 0.  Returns the new value.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `i` is not less than `count()`
+    pub fn decrement(&mut self, i: usize) -> u64 {
+        self.check_index(i);
+        let idx = i*self.bits_per_val;
+        let cur = self.get_at(idx);
+        if cur > 0 {
+            self.set_at(idx,cur-1);
+            cur-1
+        } else {
+            cur
+        }
+    }
+}
+
+// a mask of the lowest num_bits bits
+fn low_mask(num_bits: usize) -> u64 {
+    if num_bits >= BLOCK_BITS {
+        u64::MAX
+    } else {
+        (1u64 << num_bits) - 1
+    }
+}
+
+impl Resettable for ValueVec {
+    /// Resets all values to 0 in this ValueVec
+    fn reset(&mut self) {
+        self.clear();
+    }
+}
+
+/// An iterator over the values of a ValueVec, created by
+/// `ValueVec::iter`
+pub struct Iter<'a> {
+    vv: &'a ValueVec,
+    i: usize,
+}
+
+impl<'a> Iterator for Iter<'a> {
+    type Item = u64;
+
+    fn next(&mut self) -> Option<u64> {
+        if self.i == self.vv.count {
+            return None;
+        }
+        let v = self.vv.get_at(self.i*self.vv.bits_per_val);
+        self.i += 1;
+        Some(v)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let left = self.vv.count - self.i;
+        (left, Some(left))
+    }
+}
+
+impl<'a> ExactSizeIterator for Iter<'a> {}
+
+impl fmt::Debug for ValueVec {
+    /// Shows the width and number of values, and the first few
+    /// values
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("ValueVec")
+            .field("bits_per_val", &self.bits_per_val)
+            .field("count", &self.count)
+            .field("values", &DebugValues(self))
+            .finish()
+    }
 }
 
+// formats the first DEBUG_VALUES values of a ValueVec as a list,
+// ending with ".." if there are more
+struct DebugValues<'a>(&'a ValueVec);
+
+impl<'a> fmt::Debug for DebugValues<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let vv = self.0;
+        write!(f, "[")?;
+        for i in 0..vv.count.min(DEBUG_VALUES) {
+            if i > 0 {
+                write!(f, ", ")?;
+            }
+            write!(f, "{}", vv.get(i))?;
+        }
+        if vv.count > DEBUG_VALUES {
+            write!(f, ", ..")?;
+        }
+        write!(f, "]")
+    }
+}
+
+/// Errors from `ValueVec::try_set` and `ValueVec::from_bytes`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ValueVecError {
+    /// The index was not less than the number of values
+    IndexOutOfRange {
+        /// The index asked for
+        index: usize,
+        /// The number of values the ValueVec holds
+        count: usize,
+    },
+    /// The value needs more bits than each value has
+    ValueTooLarge {
+        /// The value being set
+        value: u64,
+        /// The largest value the ValueVec can hold
+        max: u64,
+    },
+    /// The bytes given to `from_bytes` were the wrong length for the
+    /// number and size of values
+    WrongLength {
+        /// The number of bytes the values need
+        expected: usize,
+        /// The number of bytes given
+        actual: usize,
+    },
+}
+
+impl fmt::Display for ValueVecError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            ValueVecError::IndexOutOfRange { index, count } => {
+                write!(f, "index {} out of range for ValueVec holding {} values", index, count)
+            }
+            ValueVecError::ValueTooLarge { value, max } => {
+                write!(f, "set with val {}, max value this ValueVec can hold is {}", value, max)
+            }
+            ValueVecError::WrongLength { expected, actual } => {
+                write!(f, "ValueVec needs {} bytes, got {}", expected, actual)
+            }
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl Error for ValueVecError {}
+
+#[cfg(test)]
+extern crate rand;
+
 #[cfg(test)]
 mod tests {
-    use valuevec::ValueVec;
+    use std::mem;
+    use valuevec::{ValueVec,ValueVecError};
+    use valuevec::rand::{self,Rng};
+
+    #[test]
+    fn iter() {
+        let mut vv = ValueVec::new(5,40);
+        for i in 0..40 {
+            vv.set(i,(i as u64 * 7) % 32);
+        }
+        assert_eq!(vv.iter().len(),40);
+        let vals: Vec<u64> = vv.iter().collect();
+        for (i, v) in vals.iter().enumerate() {
+            assert_eq!(*v,vv.get(i));
+        }
+        assert_eq!(ValueVec::new(3,0).iter().next(),None);
+    }
+
+    #[test]
+    fn value_at() {
+        // 7 bit values straddle blocks at indices 9 and 18
+        let mut vv = ValueVec::new(7,20);
+        for i in 0..20 {
+            vv.set(i,(i as u64 * 13) % 128);
+        }
+        for &i in &[0,1,9,10,18,19] {
+            assert_eq!(vv.value_at(i),(i as u64 * 13) % 128);
+        }
+        assert_eq!(vv.value_at(9) + vv.value_at(18),117 + 106);
+    }
 
     #[test]
     fn set_get_no_overlap() {
@@ -219,6 +587,247 @@ mod tests {
         assert_eq!(vv.get(1),0);
     }
 
+    // set values near the max at every alignment relative to the
+    // 64 bit blocks, and make sure they and their neighbours read
+    // back correctly
+    fn check_wide(bits_per_val: usize) {
+        let count = 70;
+        let mut vv = ValueVec::new(bits_per_val,count);
+        let max = vv.max_value();
+        assert_eq!(max, u64::MAX >> (64-bits_per_val));
+        for i in 0..count {
+            vv.set(i,max - i as u64);
+        }
+        for i in 0..count {
+            assert_eq!(vv.get(i),max - i as u64);
+        }
+        vv.set(5,0);
+        assert_eq!(vv.get(4),max - 4);
+        assert_eq!(vv.get(5),0);
+        assert_eq!(vv.get(6),max - 6);
+        vv.set(5,max);
+        assert_eq!(vv.get(5),max);
+    }
+
+    #[test]
+    fn len_clear() {
+        let mut vv = ValueVec::new(5,13);
+        assert_eq!(vv.len(),65);
+        for i in 0..13 {
+            vv.set(i,31);
+        }
+        vv.clear();
+        assert_eq!(vv.len(),65);
+        for i in 0..13 {
+            assert_eq!(vv.get(i),0);
+        }
+    }
+
+    #[test]
+    fn try_get_set() {
+        let mut vv = ValueVec::new(3,30);
+        assert_eq!(vv.count(),30);
+        assert_eq!(vv.try_set(29,7),Ok(()));
+        assert_eq!(vv.try_get(29),Some(7));
+        assert_eq!(vv.try_get(30),None);
+        assert_eq!(vv.try_set(30,1),Err(ValueVecError::IndexOutOfRange { index: 30, count: 30 }));
+        assert_eq!(vv.try_set(0,8),Err(ValueVecError::ValueTooLarge { value: 8, max: 7 }));
+        assert_eq!(vv.get(0),0);
+    }
+
+    #[test]
+    #[should_panic(expected = "index 30 out of range")]
+    fn get_out_of_range() {
+        // 30 3 bit values leave room in the last block for more
+        let vv = ValueVec::new(3,30);
+        vv.get(30);
+    }
+
+    #[test]
+    #[should_panic(expected = "index 12 out of range")]
+    fn set_out_of_range() {
+        let mut vv = ValueVec::new(4,12);
+        vv.set(12,1);
+    }
+
+    #[test]
+    fn sum_max() {
+        let mut vv = ValueVec::new(3,30);
+        assert_eq!(vv.sum(),0);
+        assert_eq!(vv.max(),0);
+        for i in 0..30 {
+            vv.set(i,(i % 8) as u64);
+        }
+        assert_eq!(vv.sum(),(0..30).map(|i| i % 8).sum::<u64>());
+        assert_eq!(vv.max(),7);
+
+        let mut wide = ValueVec::new(64,3);
+        wide.set(0,u64::MAX);
+        wide.set(2,1);
+        assert_eq!(wide.sum(),u64::MAX);
+        assert_eq!(wide.max(),u64::MAX);
+    }
+
+    #[test]
+    fn reset() {
+        let mut vv = ValueVec::new(3,30);
+        for i in 0..30 {
+            vv.set(i,5);
+        }
+        // 21*3 = 63, so this value straddles two blocks
+        vv.reset(21);
+        vv.reset(0);
+        assert_eq!(vv.get(0),0);
+        assert_eq!(vv.get(21),0);
+        assert_eq!(vv.get(1),5);
+        assert_eq!(vv.get(20),5);
+        assert_eq!(vv.get(22),5);
+    }
+
+    #[test]
+    #[should_panic(expected = "reset index 30 out of range")]
+    fn reset_out_of_range() {
+        let mut vv = ValueVec::new(3,30);
+        vv.reset(30);
+    }
+
+    #[test]
+    fn memory_bytes() {
+        // 13 5 bit values fit in two blocks
+        let vv = ValueVec::new(5,13);
+        assert_eq!(vv.memory_bytes(), mem::size_of::<ValueVec>() + 16);
+    }
+
+    #[test]
+    fn increment_decrement() {
+        let mut vv = ValueVec::new(3,30);
+        for i in 0..7 {
+            assert_eq!(vv.increment(10),i+1);
+        }
+        assert_eq!(vv.increment(10),7);
+        assert_eq!(vv.get(10),7);
+        assert_eq!(vv.get(9),0);
+        assert_eq!(vv.get(11),0);
+
+        for i in (0..7).rev() {
+            assert_eq!(vv.decrement(10),i);
+        }
+        assert_eq!(vv.decrement(10),0);
+        assert_eq!(vv.get(10),0);
+
+        // 21*3 = 63, so this value straddles two blocks
+        vv.set(21,5);
+        assert_eq!(vv.increment(21),6);
+        assert_eq!(vv.decrement(21),5);
+    }
+
+    #[test]
+    #[should_panic(expected = "index 35 out of range for ValueVec holding 30 values")]
+    fn increment_out_of_range() {
+        // 30 3 bit values leave room in the last block for more
+        let mut vv = ValueVec::new(3,30);
+        vv.increment(35);
+    }
+
+    #[test]
+    #[should_panic(expected = "index 30 out of range for ValueVec holding 30 values")]
+    fn decrement_out_of_range() {
+        let mut vv = ValueVec::new(3,30);
+        vv.decrement(30);
+    }
+
+    #[test]
+    fn full_u32() {
+        let mut vv = ValueVec::new(32,4);
+        assert_eq!(vv.max_value(), u32::MAX as u64);
+        vv.set(1,u32::MAX as u64);
+        vv.set(2,1);
+        assert_eq!(vv.get(0),0);
+        assert_eq!(vv.get(1),u32::MAX as u64);
+        assert_eq!(vv.get(2),1);
+    }
+
+    // set random values in a ValueVec with bits_per_val bits per
+    // value and check them against a plain Vec.  Blocks are 64 bits
+    // and values at most 64, so a value spans at most two blocks, and
+    // 130 values cover every starting offset within a block
+    fn check_random(bits_per_val: usize) {
+        let mut rng = rand::thread_rng();
+        let count = 130;
+        let mut vv = ValueVec::new(bits_per_val,count);
+        let max = vv.max_value();
+        let mut expected: Vec<u64> = (0..count).map(|_| rng.gen::<u64>() & max).collect();
+        for (i,val) in expected.iter().enumerate() {
+            vv.set(i,*val);
+        }
+        for _ in 0..1000 {
+            let i = rng.gen_range(0,count as u64) as usize;
+            let val = rng.gen::<u64>() & vv.max_value();
+            vv.set(i,val);
+            expected[i] = val;
+            let start = i.saturating_sub(1);
+            for (j,val) in expected.iter().enumerate().skip(start).take(3) {
+                assert_eq!(vv.get(j),*val,"bits_per_val {} index {}",bits_per_val,j);
+            }
+        }
+        for (i,val) in expected.iter().enumerate() {
+            assert_eq!(vv.get(i),*val,"bits_per_val {} index {}",bits_per_val,i);
+        }
+    }
+
+    #[test]
+    fn random_all_widths() {
+        for bits_per_val in 1..65 {
+            check_random(bits_per_val);
+        }
+    }
+
+    #[test]
+    fn wide_33() {
+        check_wide(33);
+    }
+
+    #[test]
+    fn wide_40() {
+        check_wide(40);
+    }
+
+    #[test]
+    fn wide_64() {
+        check_wide(64);
+    }
+
+    #[test]
+    #[should_panic]
+    fn too_wide() {
+        ValueVec::new(65,1);
+    }
+
+    #[test]
+    fn debug() {
+        let mut vv = ValueVec::new(3,12);
+        vv.set(1,5);
+        assert_eq!(format!("{:?}", vv),
+                   "ValueVec { bits_per_val: 3, count: 12, values: [0, 5, 0, 0, 0, 0, 0, 0, ..] }");
+        let vv = ValueVec::new(4,2);
+        assert_eq!(format!("{:?}", vv), "ValueVec { bits_per_val: 4, count: 2, values: [0, 0] }");
+    }
+
+    #[test]
+    fn clone_eq() {
+        let mut vv = ValueVec::new(3,12);
+        vv.set(3,4);
+        let mut copy = vv.clone();
+        assert_eq!(copy,vv);
+        copy.set(4,1);
+        assert!(copy != vv);
+        copy.set(4,0);
+        assert_eq!(copy,vv);
+
+        // same bits, but split into different values
+        assert!(ValueVec::new(4,3) != ValueVec::new(3,4));
+    }
+
     #[test]
     #[should_panic]
     fn over_with_max() {
@@ -226,4 +835,55 @@ mod tests {
         vv.set(0,7);
         vv.set(1,8);
     }
+
+    #[test]
+    fn with_max_zero() {
+        let mut vv = ValueVec::with_max(0,10);
+        assert_eq!(vv.bits_per_val(),1);
+        assert_eq!(vv.max_value(),1);
+        vv.set(9,0);
+        vv.set(3,1);
+        assert_eq!(vv.get(3),1);
+        assert_eq!(vv.get(9),0);
+    }
+
+    #[test]
+    fn bytes_round_trip() {
+        let mut rng = rand::thread_rng();
+        for bits_per_val in 1..65 {
+            let mut vv = ValueVec::new(bits_per_val,37);
+            let max = vv.max_value();
+            for i in 0..37 {
+                vv.set(i,rng.gen::<u64>() & max);
+            }
+            let bytes = vv.as_bytes();
+            assert_eq!(bytes.len(),(bits_per_val*37).div_ceil(8));
+            let copy = ValueVec::from_bytes(bits_per_val,37,bytes).unwrap();
+            assert_eq!(copy,vv);
+        }
+    }
+
+    #[test]
+    fn bytes_layout() {
+        // 4 bit values pack two to a byte, first value high
+        let mut vv = ValueVec::new(4,3);
+        vv.set(0,1);
+        vv.set(1,2);
+        vv.set(2,15);
+        assert_eq!(vv.as_bytes(),&[0x12,0xf0]);
+
+        // padding bits are dropped
+        let vv = ValueVec::from_bytes(4,3,&[0x12,0xff]).unwrap();
+        assert_eq!(vv.get(2),15);
+        assert_eq!(vv.as_bytes(),&[0x12,0xf0]);
+    }
+
+    #[test]
+    fn bytes_wrong_length() {
+        assert_eq!(ValueVec::from_bytes(4,3,&[0x12]),
+                   Err(ValueVecError::WrongLength { expected: 2, actual: 1 }));
+        assert_eq!(ValueVec::from_bytes(4,3,&[0,0,0]),
+                   Err(ValueVecError::WrongLength { expected: 2, actual: 3 }));
+        assert_eq!(ValueVec::from_bytes(4,0,&[]).unwrap().count(),0);
+    }
 }
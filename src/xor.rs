@@ -0,0 +1,296 @@
+// This program is free software; you can redistribute it and/or
+// modify it under the terms of the GNU General Public License as
+// published by the Free Software Foundation; either version 2 of the
+// License, or (at your option) any later version.
+
+// This program is distributed in the hope that it will be useful, but
+// WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the GNU
+// General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with this program; if not, write to the Free Software
+// Foundation, Inc., 51 Franklin Street, Fifth Floor, Boston, MA
+// 02110-1301, USA.
+
+use std::collections::hash_map::RandomState;
+use std::fmt;
+use std::hash::{BuildHasher,Hash};
+
+use super::hashing::fmix64;
+
+/// How many seeds construction tries before giving up.  Each attempt
+/// fails with a small probability, so this is never reached in
+/// practice.
+const MAX_ATTEMPTS: u32 = 100;
+
+/// An XOR Filter, for sets that are known up front and never change.
+/// Like a BloomFilter it answers `contains` with no false negatives
+/// for the items it was constructed from, and a small chance of false
+/// positives, but it uses about 1.23 bytes per item for a false
+/// positive rate of about 1/256, where a BloomFilter needs about 44%
+/// more space for the same rate.
+///
+/// Each item maps to three slots, one in each third of the filter,
+/// and the 8 bit fingerprints in those slots XOR to the item's
+/// fingerprint.  The slots are filled in by repeatedly peeling off
+/// slots that only one item maps to, which is why the filter is
+/// immutable: there is no `insert` after construction, and it does
+/// not implement `ASMS`.
+///
+/// # Example Usage
+///
+/// ```rust
+/// use bloom::XorFilter;
+///
+/// let filter = XorFilter::from_slice(&[1,2,3]);
+/// assert!(filter.contains(&1));
+/// assert!(filter.contains(&3));
+/// ```
+pub struct XorFilter<R = RandomState> {
+    fingerprints: Vec<u8>,
+    block_length: usize,
+    seed: u64,
+    len: usize,
+    hash_builder: R,
+}
+
+impl XorFilter<RandomState> {
+    /// Create an XorFilter holding `items`
+    pub fn from_slice<T: Hash>(items: &[T]) -> XorFilter<RandomState> {
+        XorFilter::from_slice_with_hasher(items,RandomState::new())
+    }
+}
+
+impl<R> XorFilter<R>
+    where R: BuildHasher
+{
+    /// Create an XorFilter holding `items`, hashing them with
+    /// `hash_builder`.  Repeated items are only stored once.
+    ///
+    /// # Panics
+    /// Panics if no seed could be found to construct the filter,
+    /// which in practice only happens if `hash_builder` gives
+    /// distinct items the same hash far more often than it should
+    pub fn from_slice_with_hasher<T: Hash>(items: &[T], mut hash_builder: R) -> XorFilter<R> {
+        let mut keys: Vec<u64> = items.iter().map(|item| hash_builder.hash_one(item)).collect();
+        // a repeated key would map to the same three slots twice,
+        // and could never be peeled
+        keys.sort_unstable();
+        keys.dedup();
+
+        let capacity = 32 + (1.23 * keys.len() as f64).ceil() as usize;
+        let block_length = capacity / 3;
+        let mut seed_state = 0x9e3779b97f4a7c15;
+        for _ in 0..MAX_ATTEMPTS {
+            seed_state = mix(seed_state,0x9e3779b97f4a7c15);
+            let mut filter = XorFilter {
+                fingerprints: vec![0; block_length * 3],
+                block_length: block_length,
+                seed: seed_state,
+                len: keys.len(),
+                hash_builder: hash_builder,
+            };
+            match filter.peel(&keys) {
+                Some(order) => {
+                    filter.assign(order);
+                    return filter;
+                }
+                None => {
+                    hash_builder = filter.hash_builder;
+                }
+            }
+        }
+        panic!("couldn't construct an XorFilter from {} items in {} attempts",
+               keys.len(),MAX_ATTEMPTS);
+    }
+
+    /// Check if an item is in this XorFilter.  Items it was
+    /// constructed from are always found, while other items are found
+    /// with a probability of about 1/256.
+    pub fn contains<T: Hash>(&self, item: &T) -> bool {
+        let key = self.hash_builder.hash_one(item);
+        let h = mix(key,self.seed);
+        let (a,b,c) = self.slots(h);
+        fingerprint(h) == self.fingerprints[a] ^ self.fingerprints[b] ^ self.fingerprints[c]
+    }
+
+    // order the keys so each can be given a slot no key after it
+    // uses, or None if this seed leaves a cycle that can't be peeled
+    fn peel(&self, keys: &[u64]) -> Option<Vec<(u64,usize)>> {
+        let mut counts = vec![0u32; self.fingerprints.len()];
+        let mut xor_keys = vec![0u64; self.fingerprints.len()];
+        for &key in keys {
+            let (a,b,c) = self.slots(mix(key,self.seed));
+            for &slot in &[a,b,c] {
+                counts[slot] += 1;
+                xor_keys[slot] ^= key;
+            }
+        }
+
+        // a slot with one key left is that key's to set
+        let mut queue: Vec<usize> = (0..counts.len()).filter(|&i| counts[i] == 1).collect();
+        let mut order = Vec::with_capacity(keys.len());
+        while let Some(slot) = queue.pop() {
+            if counts[slot] != 1 {
+                continue;
+            }
+            let key = xor_keys[slot];
+            order.push((key,slot));
+            let (a,b,c) = self.slots(mix(key,self.seed));
+            for &other in &[a,b,c] {
+                counts[other] -= 1;
+                xor_keys[other] ^= key;
+                if counts[other] == 1 {
+                    queue.push(other);
+                }
+            }
+        }
+        if order.len() == keys.len() {
+            Some(order)
+        } else {
+            None
+        }
+    }
+
+    // set each key's slot, in the reverse of the peeling order, so
+    // that the slot is the last of its three to be set
+    fn assign(&mut self, order: Vec<(u64,usize)>) {
+        for (key,slot) in order.into_iter().rev() {
+            let h = mix(key,self.seed);
+            let (a,b,c) = self.slots(h);
+            // the slot itself is still 0, so it drops out
+            self.fingerprints[slot] = fingerprint(h) ^ self.fingerprints[a]
+                ^ self.fingerprints[b] ^ self.fingerprints[c];
+        }
+    }
+
+    // one slot in each third of the filter
+    fn slots(&self, h: u64) -> (usize, usize, usize) {
+        (reduce(h as u32,self.block_length),
+         reduce(h.rotate_left(21) as u32,self.block_length) + self.block_length,
+         reduce(h.rotate_left(42) as u32,self.block_length) + 2 * self.block_length)
+    }
+}
+
+impl<R> XorFilter<R> {
+    /// Get the number of distinct items this XorFilter was
+    /// constructed from
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    /// Returns true if this XorFilter was constructed from no items
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// The number of bits of fingerprints in this XorFilter
+    pub fn capacity_bits(&self) -> usize {
+        self.fingerprints.len() * 8
+    }
+}
+
+impl<R> fmt::Debug for XorFilter<R> {
+    /// Shows the size of the filter and how many items it holds, but
+    /// not the fingerprints or the hasher
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("XorFilter")
+            .field("capacity_bits", &self.capacity_bits())
+            .field("len", &self.len)
+            .finish()
+    }
+}
+
+// finalize the key with the seed, so each seed gives unrelated
+// slots for the same key
+fn mix(key: u64, seed: u64) -> u64 {
+    fmix64(key.wrapping_add(seed))
+}
+
+fn fingerprint(h: u64) -> u8 {
+    (h ^ (h >> 32)) as u8
+}
+
+// map x onto 0..n without a division
+fn reduce(x: u32, n: usize) -> usize {
+    ((x as u64 * n as u64) >> 32) as usize
+}
+
+#[cfg(test)]
+mod tests {
+    use super::XorFilter;
+    use hashers::SipBuildHasher;
+
+    #[test]
+    fn simple() {
+        let filter = XorFilter::from_slice(&["one","two","three"]);
+        assert!(filter.contains(&"one"));
+        assert!(filter.contains(&"two"));
+        assert!(filter.contains(&"three"));
+        assert_eq!(filter.len(),3);
+    }
+
+    #[test]
+    fn membership() {
+        let items: Vec<u64> = (0..10000).collect();
+        let (h1, _) = SipBuildHasher::pair_from_seed(29);
+        let filter = XorFilter::from_slice_with_hasher(&items,h1);
+        for i in &items {
+            assert!(filter.contains(i));
+        }
+        assert_eq!(filter.len(),10000);
+        // about 1.23 bytes per item
+        assert!(filter.capacity_bits() < 10000 * 10);
+    }
+
+    #[test]
+    fn absence() {
+        let items: Vec<u64> = (0..1000).collect();
+        let (h1, _) = SipBuildHasher::pair_from_seed(29);
+        let filter = XorFilter::from_slice_with_hasher(&items,h1);
+        let found = (1000..2000u64).filter(|i| filter.contains(i)).count();
+        assert!(found < 20);
+    }
+
+    #[test]
+    fn fpr() {
+        let items: Vec<u64> = (0..10000).collect();
+        let (h1, _) = SipBuildHasher::pair_from_seed(29);
+        let filter = XorFilter::from_slice_with_hasher(&items,h1);
+        let false_positives = (10000..210000u64).filter(|i| filter.contains(i)).count();
+        // 1/256 of 200000 is about 781
+        let rate = false_positives as f64 / 200000.0;
+        assert!(rate > 0.003 && rate < 0.0048, "false positive rate {}", rate);
+    }
+
+    #[test]
+    fn duplicates() {
+        let items = [5u64,7,5,5,9,7];
+        let (h1, _) = SipBuildHasher::pair_from_seed(29);
+        let filter = XorFilter::from_slice_with_hasher(&items,h1);
+        assert_eq!(filter.len(),3);
+        for i in &items {
+            assert!(filter.contains(i));
+        }
+    }
+
+    #[test]
+    fn empty() {
+        let (h1, _) = SipBuildHasher::pair_from_seed(29);
+        let filter = XorFilter::from_slice_with_hasher(&[0u64; 0],h1);
+        assert!(filter.is_empty());
+        // every fingerprint is 0, so only items with a 0 fingerprint
+        // are found
+        let found = (0..10000u64).filter(|i| filter.contains(i)).count();
+        assert!(found < 100);
+    }
+
+    #[test]
+    fn debug() {
+        let (h1, _) = SipBuildHasher::pair_from_seed(29);
+        let filter = XorFilter::from_slice_with_hasher(&[1,2,3],h1);
+        assert_eq!(format!("{:?}", filter),
+                   format!("XorFilter {{ capacity_bits: {}, len: 3 }}", filter.capacity_bits()));
+    }
+}